@@ -1,42 +1,983 @@
+use crate::export::overlay::{AspectRatio, CellRect, Corner};
+use crate::export::split::SplitMode;
+use crate::recording::{LoadLimits, RecordingFormat, TimeFormat};
+use crate::terminal::virtual_term::CursorStyle;
+use crate::terminal::TermColor;
 use structopt::StructOpt;
 
+// Record and export options left unset fall back to rcrd.toml, then to
+// built-in defaults. The arguments are parsed once, so the size of the
+// largest variant doesn't matter.
 #[derive(StructOpt, Debug)]
+#[allow(clippy::large_enum_variant)]
 #[structopt(name = "terminal-recorder", about = "Records terminal sessions")]
 pub enum Cli {
     #[structopt(about = "Record a new terminal session")]
     Record {
-        #[structopt(short, long, help = "Output file name", default_value = "demo.json")]
-        output: String,
+        #[structopt(short, long, help = "Output file name [default: demo.json]")]
+        output: Option<String>,
+
+        #[structopt(long, help = "Record from a named pipe instead of a shell (Unix only)")]
+        fifo: Option<String>,
+
+        #[structopt(
+            long,
+            help = "Recording format (json or jsonl) [default: from the output extension]"
+        )]
+        format: Option<RecordingFormat>,
+
+        #[structopt(
+            long,
+            help = "Speed to play this recording at when none is given",
+            parse(try_from_str = parse_positive)
+        )]
+        default_speed: Option<f32>,
+
+        #[structopt(
+            long,
+            help = "Show elapsed time and frames captured on stderr while recording"
+        )]
+        status: bool,
+
+        #[structopt(
+            long,
+            help = "Continue an existing recording, adding new frames after its last one",
+            conflicts_with = "output"
+        )]
+        append: Option<String>,
+
+        #[structopt(
+            long,
+            help = "Save JSON recordings without indentation, for smaller files"
+        )]
+        compact_json: bool,
+
+        #[structopt(
+            long,
+            help = "Indent JSON recordings even if the config file sets compact_json",
+            conflicts_with = "compact-json"
+        )]
+        pretty_json: bool,
+
+        #[structopt(
+            short,
+            long,
+            help = "Run this command in the shell and stop recording when it finishes",
+            conflicts_with = "fifo"
+        )]
+        command: Option<String>,
+
+        #[structopt(
+            long,
+            help = "Shell to record [default: $SHELL, or bash (cmd on Windows)]",
+            conflicts_with = "fifo"
+        )]
+        shell: Option<String>,
     },
     #[structopt(about = "Play back a recorded terminal session")]
     Play {
         #[structopt(help = "File to replay")]
         file: String,
 
-        #[structopt(short, long, help = "Playback speed multiplier", default_value = "1.0")]
-        speed: f32,
+        #[structopt(
+            short,
+            long,
+            help = "Playback speed multiplier [default: the recording's default speed, or 1.0]",
+            parse(try_from_str = parse_positive)
+        )]
+        speed: Option<f32>,
+
+        #[structopt(long, help = "Replay the screen states back to front")]
+        reverse: bool,
+
+        #[structopt(long, help = "Show each frame's timestamp on stderr")]
+        show_time: bool,
+
+        #[structopt(
+            long,
+            help = "Control playback from the keyboard: space pauses, right arrow steps, + and - change speed, q quits"
+        )]
+        interactive: bool,
+
+        #[structopt(
+            long,
+            help = "Shorten pauses longer than this many seconds to that length",
+            parse(try_from_str = parse_positive)
+        )]
+        max_idle: Option<f32>,
+
+        #[structopt(
+            long,
+            help = "Redraw each frame through a virtual terminal to clean up damaged output"
+        )]
+        repair: bool,
+
+        #[structopt(
+            short,
+            long,
+            help = "Terminal width used when re-rendering frames",
+            default_value = "80",
+            parse(try_from_str = parse_dimension)
+        )]
+        width: u16,
+
+        #[structopt(
+            short,
+            long,
+            help = "Terminal height used when re-rendering frames",
+            default_value = "24",
+            parse(try_from_str = parse_dimension)
+        )]
+        height: u16,
+
+        #[structopt(flatten)]
+        limits: LimitArgs,
     },
     #[structopt(about = "Convert a recording to a GIF")]
     Export {
         #[structopt(help = "Input recording file")]
         input: String,
 
-        #[structopt(help = "Output GIF file", default_value = "output.gif")]
+        #[structopt(help = "Output GIF file [default: output.gif, or named after --chapter]")]
+        output: Option<String>,
+
+        #[structopt(
+            short,
+            long,
+            help = "Playback speed multiplier [default: the recording's default speed, or 1.0]",
+            parse(try_from_str = parse_positive)
+        )]
+        speed: Option<f32>,
+
+        #[structopt(
+            short,
+            long,
+            help = "Terminal width [default: the recorded width, or 80]",
+            parse(try_from_str = parse_dimension)
+        )]
+        width: Option<u16>,
+
+        #[structopt(
+            short,
+            long,
+            help = "Terminal height [default: the recorded height, or 24]",
+            parse(try_from_str = parse_dimension)
+        )]
+        height: Option<u16>,
+
+        #[structopt(
+            short,
+            long,
+            help = "Font size (pixels) [default: 16]",
+            parse(try_from_str = parse_font_size)
+        )]
+        font_size: Option<u8>,
+
+        #[structopt(long, help = "Dark theme")]
+        dark_theme: bool,
+
+        #[structopt(
+            long,
+            help = "Light theme, even if the config file sets dark_theme",
+            conflicts_with = "dark-theme"
+        )]
+        light_theme: bool,
+
+        #[structopt(
+            long,
+            help = "Default background as #rrggbb, overriding the theme's (wins over --dark-theme)"
+        )]
+        bg_color: Option<TermColor>,
+
+        #[structopt(
+            long,
+            help = "Default text color as #rrggbb, overriding the theme's (wins over --dark-theme)"
+        )]
+        fg_color: Option<TermColor>,
+
+        #[structopt(long, help = "Draw an elapsed-time clock over each frame")]
+        clock: bool,
+
+        #[structopt(
+            long,
+            help = "Don't draw the clock, even if the config file turns it on",
+            conflicts_with = "clock"
+        )]
+        no_clock: bool,
+
+        #[structopt(
+            long,
+            help = "Corner for the clock (top-left, top-right, bottom-left, bottom-right) [default: bottom-right]"
+        )]
+        clock_position: Option<Corner>,
+
+        #[structopt(long, help = "Text to show in the bottom-left corner of every frame")]
+        watermark: Option<String>,
+
+        #[structopt(
+            long,
+            help = "Stop after writing this many GIF frames",
+            parse(try_from_str = parse_frame_count)
+        )]
+        max_frames: Option<usize>,
+
+        #[structopt(long, help = "Emit the frames back to front")]
+        reverse: bool,
+
+        #[structopt(
+            long,
+            help = "Replace recorded prompts with this template ({cwd}, {dir}, {user}, {host})"
+        )]
+        prompt: Option<String>,
+
+        #[structopt(long, help = "Regex used to detect the recorded prompt")]
+        prompt_pattern: Option<String>,
+
+        #[structopt(long, help = "Center the recorded content within the terminal")]
+        center: bool,
+
+        #[structopt(
+            long,
+            help = "Don't center the content, even if the config file turns it on",
+            conflicts_with = "center"
+        )]
+        no_center: bool,
+
+        #[structopt(long, help = "Embed the source recording in the GIF")]
+        embed_source: bool,
+
+        #[structopt(
+            long,
+            help = "Don't embed the source recording, even if the config file turns it on",
+            conflicts_with = "embed-source"
+        )]
+        no_embed_source: bool,
+
+        #[structopt(long, help = "Transliterate non-ASCII text to ASCII")]
+        ascii_only: bool,
+
+        #[structopt(
+            long,
+            help = "Keep non-ASCII text, even if the config file sets ascii_only",
+            conflicts_with = "ascii-only"
+        )]
+        no_ascii_only: bool,
+
+        #[structopt(
+            long,
+            help = "Type out the final screen at this many characters per second instead of replaying the recorded timing"
+        )]
+        reveal: Option<f32>,
+
+        #[structopt(long, help = "Show every frame for at least this many milliseconds")]
+        min_visible: Option<u64>,
+
+        #[structopt(
+            long,
+            help = "Play each section between chapter starts in this many seconds",
+            parse(try_from_str = parse_positive)
+        )]
+        section_duration: Option<f32>,
+
+        #[structopt(
+            long,
+            help = "Shorten pauses longer than this many seconds to that length",
+            parse(try_from_str = parse_positive)
+        )]
+        max_idle: Option<f32>,
+
+        #[structopt(
+            long,
+            help = "With --min-visible, shorten later pauses to keep the total duration"
+        )]
+        preserve_duration: bool,
+
+        #[structopt(long, help = "Export only this chapter from the chapters sidecar")]
+        chapter: Option<String>,
+
+        #[structopt(
+            long,
+            help = "Show the window title set by the session above the terminal"
+        )]
+        title_caption: bool,
+
+        #[structopt(
+            long,
+            help = "Only emit frames that change the screen, with a fixed gap between them"
+        )]
+        on_change_only: bool,
+
+        #[structopt(long, help = "Fail if the recording uses unsupported escape sequences")]
+        strict: bool,
+
+        #[structopt(long, help = "Render the GIF in grayscale")]
+        grayscale: bool,
+
+        #[structopt(
+            long,
+            help = "Render in color, even if the config file sets grayscale",
+            conflicts_with = "grayscale"
+        )]
+        no_grayscale: bool,
+
+        #[structopt(long, help = "Draw the text callouts stored in the recording")]
+        with_callouts: bool,
+
+        #[structopt(
+            long,
+            help = "Take the GIF's colors from this image, snapping the terminal's colors to them"
+        )]
+        palette_from: Option<String>,
+
+        #[structopt(
+            long,
+            help = "Share one palette across all frames for a smaller GIF (holds every frame in memory)"
+        )]
+        optimize: bool,
+
+        #[structopt(
+            long,
+            help = "Give each frame its own palette, even if the config file sets optimize",
+            conflicts_with = "optimize"
+        )]
+        no_optimize: bool,
+
+        #[structopt(
+            long,
+            help = "Repeat the GIF this many times, 0 to loop forever (many viewers treat 1 as play once) [default: 0]"
+        )]
+        loop_count: Option<u16>,
+
+        #[structopt(
+            long,
+            help = "Draw text with this .ttf or .otf font instead of the built-in bitmap font"
+        )]
+        font: Option<String>,
+
+        #[structopt(
+            long,
+            help = "Enlarge the output by a whole factor, e.g. 2 for HiDPI displays [default: 1]",
+            parse(try_from_str = parse_scale)
+        )]
+        scale: Option<u8>,
+
+        #[structopt(
+            long,
+            alias = "raw",
+            help = "Export the recording as-is, without intro, outro or added delay"
+        )]
+        no_enhance: bool,
+
+        #[structopt(long, help = "Leave out the title card before the recording")]
+        no_intro: bool,
+
+        #[structopt(long, help = "Leave out the prompt drawn after the title card")]
+        no_intro_prompt: bool,
+
+        #[structopt(long, help = "Start the recording without waiting for the intro")]
+        no_time_offset: bool,
+
+        #[structopt(long, help = "Leave out the closing banner")]
+        no_outro: bool,
+
+        #[structopt(
+            long,
+            help = "Only output this rectangle of cells, given as x,y,width,height"
+        )]
+        crop: Option<CellRect>,
+
+        #[structopt(
+            long,
+            help = "Float the terminal on a white canvas with a soft drop shadow"
+        )]
+        shadow: bool,
+
+        #[structopt(
+            long,
+            help = "End on the last frame that changes the screen, with a short final pause"
+        )]
+        trim_trailing: bool,
+
+        #[structopt(
+            long,
+            help = "Draw tabs, trailing spaces and line breaks as visible markers"
+        )]
+        show_whitespace: bool,
+
+        #[structopt(
+            long,
+            help = "Draw the cursor as a block, underline or bar, or not at all",
+            default_value = "none"
+        )]
+        cursor: CursorStyle,
+
+        #[structopt(long, help = "Draw faint lines along the cell boundaries")]
+        grid: bool,
+
+        #[structopt(
+            long,
+            help = "Tint the cells each frame changed, fading over the next few frames"
+        )]
+        onion_skin: bool,
+
+        #[structopt(long, help = "Pad the GIF with bars to this aspect ratio, e.g. 16:9")]
+        aspect: Option<AspectRatio>,
+
+        #[structopt(
+            long,
+            help = "Color of the --aspect bars as #rrggbb [default: the terminal background]",
+            requires = "aspect"
+        )]
+        bar_color: Option<TermColor>,
+
+        #[structopt(flatten)]
+        limits: LimitArgs,
+    },
+    #[structopt(about = "Convert a recording to an asciinema v2 cast")]
+    Cast {
+        #[structopt(help = "Input recording file")]
+        input: String,
+
+        #[structopt(help = "Output cast file", default_value = "output.cast")]
+        output: String,
+
+        #[structopt(
+            short,
+            long,
+            help = "Terminal width [default: the recorded width, or 80]",
+            parse(try_from_str = parse_dimension)
+        )]
+        width: Option<u16>,
+
+        #[structopt(
+            short,
+            long,
+            help = "Terminal height [default: the recorded height, or 24]",
+            parse(try_from_str = parse_dimension)
+        )]
+        height: Option<u16>,
+    },
+    #[structopt(about = "Convert a recording to an MP4 or WebM video (requires ffmpeg)")]
+    Video {
+        #[structopt(help = "Input recording file")]
+        input: String,
+
+        #[structopt(
+            help = "Output video file, encoded as VP9 if it ends in .webm and H.264 otherwise",
+            default_value = "output.mp4"
+        )]
         output: String,
 
-        #[structopt(short, long, help = "Playback speed multiplier", default_value = "1.0")]
-        speed: f32,
+        #[structopt(
+            long,
+            help = "Frames per second",
+            default_value = "30",
+            parse(try_from_str = parse_fps)
+        )]
+        fps: u32,
+
+        #[structopt(
+            short,
+            long,
+            help = "Playback speed multiplier [default: the recording's default speed, or 1.0]",
+            parse(try_from_str = parse_positive)
+        )]
+        speed: Option<f32>,
+
+        #[structopt(
+            short,
+            long,
+            help = "Terminal width [default: the recorded width, or 80]",
+            parse(try_from_str = parse_dimension)
+        )]
+        width: Option<u16>,
+
+        #[structopt(
+            short,
+            long,
+            help = "Terminal height [default: the recorded height, or 24]",
+            parse(try_from_str = parse_dimension)
+        )]
+        height: Option<u16>,
+
+        #[structopt(
+            short,
+            long,
+            help = "Font size (pixels) [default: 16]",
+            parse(try_from_str = parse_font_size)
+        )]
+        font_size: Option<u8>,
+
+        #[structopt(long, help = "Dark theme")]
+        dark_theme: bool,
+
+        #[structopt(
+            long,
+            help = "Light theme, even if the config file sets dark_theme",
+            conflicts_with = "dark-theme"
+        )]
+        light_theme: bool,
+
+        #[structopt(
+            long,
+            help = "Draw text with this .ttf or .otf font instead of the built-in bitmap font"
+        )]
+        font: Option<String>,
 
-        #[structopt(short, long, help = "Terminal width", default_value = "80")]
+        #[structopt(
+            long,
+            help = "Enlarge the output by a whole factor, e.g. 2 for HiDPI displays [default: 1]",
+            parse(try_from_str = parse_scale)
+        )]
+        scale: Option<u8>,
+
+        #[structopt(
+            long,
+            help = "Shorten pauses longer than this many seconds to that length",
+            parse(try_from_str = parse_positive)
+        )]
+        max_idle: Option<f32>,
+
+        #[structopt(
+            long,
+            help = "Draw the cursor as a block, underline or bar, or not at all",
+            default_value = "none"
+        )]
+        cursor: CursorStyle,
+
+        #[structopt(flatten)]
+        limits: LimitArgs,
+    },
+    #[structopt(about = "Extract the recording embedded in a GIF")]
+    Extract {
+        #[structopt(help = "GIF exported with --embed-source")]
+        gif: String,
+
+        #[structopt(help = "Output recording file", default_value = "extracted.json")]
+        output: String,
+    },
+    #[structopt(about = "Write the commands typed during a recording out as a shell script")]
+    ExtractCommands {
+        #[structopt(help = "Recording file")]
+        input: String,
+
+        #[structopt(help = "Output script", default_value = "commands.sh")]
+        output: String,
+    },
+    #[structopt(about = "Create a looping GIF that types out some text")]
+    Type {
+        #[structopt(
+            help = "Text to type, may contain ANSI escape sequences",
+            required_unless = "file"
+        )]
+        text: Option<String>,
+
+        #[structopt(short, long, help = "Output GIF file", default_value = "typing.gif")]
+        output: String,
+
+        #[structopt(
+            long,
+            help = "Read the text from a file instead",
+            conflicts_with = "text"
+        )]
+        file: Option<String>,
+
+        #[structopt(
+            short,
+            long,
+            help = "Delay between characters (ms)",
+            default_value = "80"
+        )]
+        delay: u64,
+
+        #[structopt(
+            long,
+            help = "How long to hold the finished text before looping (ms)",
+            default_value = "2000"
+        )]
+        hold: u64,
+
+        #[structopt(
+            short,
+            long,
+            help = "Terminal width",
+            default_value = "80",
+            parse(try_from_str = parse_dimension)
+        )]
         width: u16,
 
-        #[structopt(short, long, help = "Terminal height", default_value = "24")]
+        #[structopt(
+            short,
+            long,
+            help = "Terminal height",
+            default_value = "24",
+            parse(try_from_str = parse_dimension)
+        )]
         height: u16,
 
-        #[structopt(short, long, help = "Font size (pixels)", default_value = "16")]
+        #[structopt(
+            short,
+            long,
+            help = "Font size (pixels)",
+            default_value = "16",
+            parse(try_from_str = parse_font_size)
+        )]
+        font_size: u8,
+
+        #[structopt(long, help = "Dark theme")]
+        dark_theme: bool,
+    },
+    #[structopt(about = "Split a recording into several shorter GIFs")]
+    Split {
+        #[structopt(help = "Input recording file")]
+        input: String,
+
+        #[structopt(help = "Prefix for the numbered output GIFs", default_value = "part")]
+        output_prefix: String,
+
+        #[structopt(
+            long,
+            help = "Where to split (duration:<seconds>, frames:<count>, or chapters to start a part at each chapter)",
+            default_value = "duration:10"
+        )]
+        by: SplitMode,
+
+        #[structopt(
+            short,
+            long,
+            help = "Playback speed multiplier [default: the recording's default speed, or 1.0]",
+            parse(try_from_str = parse_positive)
+        )]
+        speed: Option<f32>,
+
+        #[structopt(
+            short,
+            long,
+            help = "Terminal width [default: the recorded width, or 80]",
+            parse(try_from_str = parse_dimension)
+        )]
+        width: Option<u16>,
+
+        #[structopt(
+            short,
+            long,
+            help = "Terminal height [default: the recorded height, or 24]",
+            parse(try_from_str = parse_dimension)
+        )]
+        height: Option<u16>,
+
+        #[structopt(
+            short,
+            long,
+            help = "Font size (pixels)",
+            default_value = "16",
+            parse(try_from_str = parse_font_size)
+        )]
         font_size: u8,
 
         #[structopt(long, help = "Dark theme")]
         dark_theme: bool,
+
+        #[structopt(flatten)]
+        limits: LimitArgs,
+    },
+    #[structopt(about = "Compare the final screens of two recordings")]
+    Diff {
+        #[structopt(help = "First recording")]
+        a: String,
+
+        #[structopt(help = "Second recording")]
+        b: String,
+
+        #[structopt(long, help = "Also compare colors and text attributes")]
+        color: bool,
+
+        #[structopt(
+            short,
+            long,
+            help = "Terminal width",
+            default_value = "80",
+            parse(try_from_str = parse_dimension)
+        )]
+        width: u16,
+
+        #[structopt(
+            short,
+            long,
+            help = "Terminal height",
+            default_value = "24",
+            parse(try_from_str = parse_dimension)
+        )]
+        height: u16,
+    },
+    #[structopt(about = "List the chapters defined for a recording")]
+    Chapters {
+        #[structopt(help = "Recording file")]
+        file: String,
+    },
+    #[structopt(about = "Add, list or remove text callouts in a recording")]
+    Annotate {
+        #[structopt(help = "Recording file")]
+        file: String,
+
+        #[structopt(subcommand)]
+        action: AnnotateAction,
+    },
+    #[structopt(about = "Render a sample screen in each built-in theme to compare them")]
+    ThemePreview {
+        #[structopt(help = "Directory to save the PNGs in", default_value = ".")]
+        output_dir: String,
+    },
+    #[structopt(about = "List the escape sequences a recording uses and which are supported")]
+    Escapes {
+        #[structopt(help = "Recording file")]
+        file: String,
+    },
+    #[structopt(about = "Show details of a recording and the environment it was made in")]
+    Info {
+        #[structopt(help = "Recording file")]
+        file: String,
+
+        #[structopt(
+            long,
+            help = "List every frame's time as absolute (wall clock), relative (since the previous frame) or offset (since the start)"
+        )]
+        time_format: Option<TimeFormat>,
+    },
+    #[structopt(about = "Change the metadata stored in a recording")]
+    Edit {
+        #[structopt(help = "Recording file")]
+        file: String,
+
+        #[structopt(
+            long,
+            help = "Speed to play this recording at when none is given",
+            parse(try_from_str = parse_positive)
+        )]
+        default_speed: Option<f32>,
+
+        #[structopt(
+            long,
+            help = "Remove the stored default speed",
+            conflicts_with = "default-speed"
+        )]
+        clear_default_speed: bool,
+    },
+    #[structopt(about = "Cut dead air out of a recording")]
+    Trim {
+        #[structopt(help = "Recording file")]
+        file: String,
+
+        #[structopt(
+            short,
+            long,
+            help = "Write the trimmed recording here instead of replacing the file"
+        )]
+        output: Option<String>,
+
+        #[structopt(
+            long,
+            help = "Drop the frames before this many seconds in; the screen they drew is kept",
+            parse(try_from_str = parse_seconds)
+        )]
+        start: Option<f32>,
+
+        #[structopt(
+            long,
+            help = "Drop the frames after this many seconds in",
+            parse(try_from_str = parse_seconds)
+        )]
+        end: Option<f32>,
+
+        #[structopt(
+            long,
+            help = "Drop frames after the last screen change and shorten the final pause"
+        )]
+        trailing: bool,
+    },
+}
+
+// Size limits for commands that load recordings, which may come from
+// untrusted sources
+#[derive(StructOpt, Debug)]
+pub struct LimitArgs {
+    #[structopt(
+        long,
+        help = "Refuse recordings with a frame larger than this many bytes (K, M and G suffixes allowed) [default: 16M]",
+        parse(try_from_str = parse_byte_size)
+    )]
+    max_frame_bytes: Option<u64>,
+
+    #[structopt(
+        long,
+        help = "Refuse recording files larger than this many bytes (K, M and G suffixes allowed) [default: 512M]",
+        parse(try_from_str = parse_byte_size)
+    )]
+    max_total_bytes: Option<u64>,
+}
+
+impl LimitArgs {
+    pub fn limits(&self) -> LoadLimits {
+        let defaults = LoadLimits::default();
+        LoadLimits {
+            max_frame_bytes: self.max_frame_bytes.unwrap_or(defaults.max_frame_bytes),
+            max_total_bytes: self.max_total_bytes.unwrap_or(defaults.max_total_bytes),
+        }
+    }
+}
+
+#[derive(StructOpt, Debug)]
+pub enum AnnotateAction {
+    #[structopt(about = "Add a callout")]
+    Add {
+        #[structopt(
+            long,
+            help = "When the callout appears (milliseconds into the recording)"
+        )]
+        start: u128,
+
+        #[structopt(
+            long,
+            help = "When the callout disappears (milliseconds into the recording)"
+        )]
+        end: u128,
+
+        #[structopt(long, help = "Text to show; \\n starts a new line")]
+        text: String,
+
+        #[structopt(
+            long,
+            help = "Corner to show the callout in (top-left, top-right, bottom-left, bottom-right)",
+            default_value = "bottom-left"
+        )]
+        position: Corner,
     },
+    #[structopt(about = "List the callouts")]
+    List,
+    #[structopt(about = "Remove a callout by its number in the list")]
+    Remove {
+        #[structopt(help = "Callout number")]
+        number: usize,
+    },
+}
+
+// Terminal width or height, which must be at least one cell
+fn parse_dimension(s: &str) -> Result<u16, String> {
+    match s.parse::<u16>() {
+        Ok(0) => Err("must be at least 1".to_string()),
+        Ok(value) => Ok(value),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// Font size in pixels, which must be at least 1
+fn parse_font_size(s: &str) -> Result<u8, String> {
+    match s.parse::<u8>() {
+        Ok(0) => Err("must be at least 1".to_string()),
+        Ok(value) => Ok(value),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// Whole-number output scale, which must be at least 1
+fn parse_scale(s: &str) -> Result<u8, String> {
+    match s.parse::<u8>() {
+        Ok(0) => Err("must be at least 1".to_string()),
+        Ok(value) => Ok(value),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// Video frame rate, which must be at least 1
+fn parse_fps(s: &str) -> Result<u32, String> {
+    match s.parse::<u32>() {
+        Ok(0) => Err("must be at least 1".to_string()),
+        Ok(value) => Ok(value),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// Number of frames to stop after, which must be at least 1
+fn parse_frame_count(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(0) => Err("must be at least 1".to_string()),
+        Ok(value) => Ok(value),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// Byte count with an optional binary K, M or G suffix (16M is 16 MiB)
+fn parse_byte_size(s: &str) -> Result<u64, String> {
+    let (digits, multiplier) = match s.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&s[..i], 1 << 10),
+        Some((i, 'M' | 'm')) => (&s[..i], 1 << 20),
+        Some((i, 'G' | 'g')) => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+    digits
+        .parse::<u64>()
+        .map_err(|e| e.to_string())?
+        .checked_mul(multiplier)
+        .ok_or_else(|| "is too large".to_string())
+}
+
+// A speed multiplier or duration, which must be positive
+fn parse_positive(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(value) if value.is_finite() && value > 0.0 => Ok(value),
+        Ok(_) => Err("must be a positive number".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// A point in a recording, in seconds from the start
+fn parse_seconds(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(value) if value.is_finite() && value >= 0.0 => Ok(value),
+        Ok(_) => Err("must be zero or more seconds".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dimensions_must_be_at_least_one() {
+        assert_eq!(parse_dimension("1"), Ok(1));
+        assert!(parse_dimension("0").is_err());
+        assert!(parse_dimension("-2").is_err());
+    }
+
+    #[test]
+    fn font_sizes_must_be_at_least_one() {
+        assert_eq!(parse_font_size("1"), Ok(1));
+        assert!(parse_font_size("0").is_err());
+        assert!(parse_font_size("256").is_err());
+    }
+
+    #[test]
+    fn speeds_must_be_positive_and_finite() {
+        assert_eq!(parse_positive("0.001"), Ok(0.001));
+        for speed in ["0", "-1", "NaN", "inf"] {
+            assert!(parse_positive(speed).is_err(), "{}", speed);
+        }
+    }
+
+    #[test]
+    fn frame_counts_must_be_positive() {
+        assert_eq!(parse_frame_count("5"), Ok(5));
+        assert!(parse_frame_count("0").is_err());
+    }
+
+    #[test]
+    fn negating_flags_conflict_with_their_pair() {
+        let parse =
+            |args: &[&str]| Cli::from_iter_safe(["rcrd", "export", "in.json"].iter().chain(args));
+        assert!(parse(&["--no-clock"]).is_ok());
+        assert!(parse(&["--clock", "--no-clock"]).is_err());
+        assert!(parse(&["--dark-theme", "--light-theme"]).is_err());
+        assert!(parse(&["--ascii-only", "--no-ascii-only"]).is_err());
+    }
 }
@@ -0,0 +1,151 @@
+use crate::export::overlay::Corner;
+use crate::recording::RecordingFormat;
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+const CONFIG_FILE: &str = "rcrd.toml";
+
+// Defaults read from rcrd.toml. Every setting is optional, and flags given
+// on the command line take precedence over the file.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub record: RecordConfig,
+    pub export: ExportConfig,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecordConfig {
+    pub output: Option<String>,
+    pub format: Option<RecordingFormat>,
+    pub compact_json: Option<bool>,
+    pub shell: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExportConfig {
+    pub speed: Option<f32>,
+    pub width: Option<u16>,
+    pub height: Option<u16>,
+    pub font_size: Option<u8>,
+    pub dark_theme: Option<bool>,
+    pub clock: Option<bool>,
+    pub clock_position: Option<Corner>,
+    pub prompt: Option<String>,
+    pub prompt_pattern: Option<String>,
+    pub center: Option<bool>,
+    pub embed_source: Option<bool>,
+    pub ascii_only: Option<bool>,
+    pub grayscale: Option<bool>,
+    pub palette_from: Option<String>,
+    pub optimize: Option<bool>,
+    pub loop_count: Option<u16>,
+    pub font: Option<String>,
+    pub scale: Option<u8>,
+}
+
+impl Config {
+    // Load rcrd.toml from the current directory, falling back to
+    // $XDG_CONFIG_HOME/rcrd/ (~/.config/rcrd/). A missing file is not an error.
+    pub fn load() -> io::Result<Self> {
+        let path = match config_paths().into_iter().find(|path| path.exists()) {
+            Some(path) => path,
+            None => return Ok(Config::default()),
+        };
+
+        let contents = fs::read_to_string(&path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Failed to read {}: {}", path.display(), e),
+            )
+        })?;
+
+        let config: Config = toml::from_str(&contents).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid config in {}: {}", path.display(), e),
+            )
+        })?;
+
+        if let Some(speed) = config.export.speed {
+            if !(speed.is_finite() && speed > 0.0) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Invalid config in {}: export.speed must be a positive number, got {}",
+                        path.display(),
+                        speed
+                    ),
+                ));
+            }
+        }
+
+        // Sizes that must be at least 1, as their flags require
+        let sizes = [
+            ("width", config.export.width),
+            ("height", config.export.height),
+            ("font_size", config.export.font_size.map(u16::from)),
+            ("scale", config.export.scale.map(u16::from)),
+        ];
+        for (setting, value) in sizes {
+            if value == Some(0) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Invalid config in {}: export.{} must be at least 1",
+                        path.display(),
+                        setting
+                    ),
+                ));
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+// A setting turned on or off by a pair of flags, such as --clock and
+// --no-clock, falling back to the config file when neither is given
+pub fn switch(on: bool, off: bool, configured: Option<bool>) -> bool {
+    if on || off {
+        on
+    } else {
+        configured.unwrap_or(false)
+    }
+}
+
+fn config_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+
+    if let Ok(dir) = env::current_dir() {
+        paths.push(dir.join(CONFIG_FILE));
+    }
+
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    if let Some(config_home) = config_home {
+        paths.push(config_home.join("rcrd").join(CONFIG_FILE));
+    }
+
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_override_the_config_either_way() {
+        assert!(switch(true, false, Some(false)));
+        assert!(!switch(false, true, Some(true)));
+        assert!(switch(false, false, Some(true)));
+        assert!(!switch(false, false, None));
+    }
+}
@@ -0,0 +1,29 @@
+use crate::recording::RecordedFrame;
+use crate::terminal::parser::{self, Token};
+use deunicode::deunicode_char;
+
+// Transliterate printable non-ASCII characters so they can be drawn with the
+// built-in font ("café" becomes "cafe"). Escape sequences pass through as-is.
+pub fn transliterate(frames: Vec<RecordedFrame>) -> Vec<RecordedFrame> {
+    frames
+        .into_iter()
+        .map(|frame| RecordedFrame {
+            content: transliterate_text(&frame.content),
+            raw: None,
+            ..frame
+        })
+        .collect()
+}
+
+fn transliterate_text(content: &str) -> String {
+    let mut output = String::with_capacity(content.len());
+
+    for (token, raw) in parser::tokenize(content) {
+        match token {
+            Token::Text(c) if !c.is_ascii() => output.push_str(deunicode_char(c).unwrap_or("?")),
+            _ => output.push_str(raw),
+        }
+    }
+
+    output
+}
@@ -3,7 +3,6 @@ use std::collections::HashMap;
 // Character bitmap for rendering text
 pub type CharBitmap = Vec<Vec<bool>>;
 
-// Scale a bitmap to the desired size
 // Scale a bitmap to the desired size
 pub fn scale_bitmap(bitmap: &CharBitmap, scale: usize) -> CharBitmap {
     if scale <= 1 {
@@ -16,9 +15,9 @@ pub fn scale_bitmap(bitmap: &CharBitmap, scale: usize) -> CharBitmap {
         let mut scaled_rows = vec![vec![false; row.len() * scale]; scale];
 
         for (x, &pixel) in row.iter().enumerate() {
-            for sy in 0..scale {
+            for scaled_row in scaled_rows.iter_mut() {
                 for sx in 0..scale {
-                    scaled_rows[sy][x * scale + sx] = pixel;
+                    scaled_row[x * scale + sx] = pixel;
                 }
             }
         }
@@ -29,6 +28,17 @@ pub fn scale_bitmap(bitmap: &CharBitmap, scale: usize) -> CharBitmap {
     scaled
 }
 
+// Rows a glyph hangs below the baseline. The bitmaps are all the same
+// height, so glyphs with descenders are drawn this much lower to line their
+// bodies up with the rest of the text.
+pub fn descent(character: char) -> usize {
+    match character {
+        'g' | 'j' | 'p' | 'q' | 'y' => 2,
+        ',' | ';' => 1,
+        _ => 0,
+    }
+}
+
 // Create bitmap representations of characters
 pub fn create_character_bitmaps() -> HashMap<char, CharBitmap> {
     let mut maps = HashMap::new();
@@ -1273,6 +1283,46 @@ pub fn create_character_bitmaps() -> HashMap<char, CharBitmap> {
         ],
     );
 
+    // Markers drawn for whitespace by --show-whitespace
+    maps.insert(
+        '→',
+        vec![
+            vec![false, false, false, false, false],
+            vec![false, false, true, false, false],
+            vec![false, false, false, true, false],
+            vec![true, true, true, true, true],
+            vec![false, false, false, true, false],
+            vec![false, false, true, false, false],
+            vec![false, false, false, false, false],
+        ],
+    );
+
+    maps.insert(
+        '·',
+        vec![
+            vec![false],
+            vec![false],
+            vec![false],
+            vec![true],
+            vec![false],
+            vec![false],
+            vec![false],
+        ],
+    );
+
+    maps.insert(
+        '¶',
+        vec![
+            vec![false, true, true, true],
+            vec![true, true, false, true],
+            vec![true, true, false, true],
+            vec![false, true, false, true],
+            vec![false, true, false, true],
+            vec![false, true, false, true],
+            vec![false, true, false, true],
+        ],
+    );
+
     // Add a fallback for unknown characters
     maps.insert(
         '�',
@@ -0,0 +1,139 @@
+use crate::recording::{FrameOrigin, Recording, DEFAULT_HEIGHT, DEFAULT_WIDTH};
+use crate::utils;
+use serde_json::{json, Map};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+// Convert a recording to an asciinema v2 cast: a header line giving the
+// terminal size, then one [seconds, code, data] line per event. Output is
+// "o", typed input "i" and resizes "r" with the new "WxH".
+pub fn export_to_cast(
+    input_file: &str,
+    output_file: &str,
+    width: Option<u16>,
+    height: Option<u16>,
+) -> io::Result<()> {
+    let recording = Recording::open(&utils::get_absolute_path(input_file))?;
+    let metadata = &recording.metadata;
+
+    // The size given on the command line, else the one the session was
+    // recorded at
+    let width = width.or(metadata.width).unwrap_or(DEFAULT_WIDTH);
+    let height = height.or(metadata.height).unwrap_or(DEFAULT_HEIGHT);
+
+    let mut header = json!({
+        "version": 2,
+        "width": width,
+        "height": height,
+    });
+    let mut env = Map::new();
+    if let Some(shell) = &metadata.shell {
+        env.insert("SHELL".to_string(), json!(shell));
+    }
+    if let Some(term) = metadata.env.get("TERM") {
+        env.insert("TERM".to_string(), json!(term));
+    }
+    if !env.is_empty() {
+        header["env"] = env.into();
+    }
+    if let Some(created) = metadata.created {
+        header["timestamp"] = json!(created);
+    }
+
+    let output_path = utils::get_absolute_path(output_file);
+    let file = File::create(&output_path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Failed to create {}: {}", output_path.display(), e),
+        )
+    })?;
+    let mut writer = BufWriter::new(file);
+
+    serde_json::to_writer(&mut writer, &header)?;
+    writer.write_all(b"\n")?;
+    for frame in &recording.frames {
+        let code = match frame.origin {
+            FrameOrigin::Output => "o",
+            FrameOrigin::Input => "i",
+            FrameOrigin::Resize => "r",
+        };
+        let seconds = frame.timestamp as f64 / 1000.0;
+        serde_json::to_writer(&mut writer, &json!([seconds, code, frame.content]))?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+
+    println!(
+        "Wrote {} events to {}",
+        recording.frames.len(),
+        output_path.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::{Metadata, RecordedFrame};
+    use std::fs;
+
+    #[test]
+    fn cast_export_round_trips() {
+        let frames = vec![
+            RecordedFrame::new("$ ".to_string(), 0),
+            RecordedFrame {
+                origin: FrameOrigin::Input,
+                ..RecordedFrame::new("ls\r".to_string(), 1500)
+            },
+            RecordedFrame {
+                origin: FrameOrigin::Resize,
+                ..RecordedFrame::new("100x30".to_string(), 1750)
+            },
+            RecordedFrame::new("src\r\n".to_string(), 2000),
+        ];
+        let mut recording = Recording::from_frames(frames.clone());
+        recording.metadata = Metadata {
+            width: Some(90),
+            height: Some(20),
+            created: Some(1_700_000_000),
+            ..Metadata::default()
+        };
+        let input = utils::temp_path("cast-source.json");
+        let output = utils::temp_path("export.cast");
+        recording.save(&input).unwrap();
+
+        export_to_cast(
+            input.to_str().unwrap(),
+            output.to_str().unwrap(),
+            None,
+            None,
+        )
+        .unwrap();
+
+        let text = fs::read_to_string(&output).unwrap();
+        let lines: Vec<serde_json::Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            lines[0],
+            json!({"version": 2, "width": 90, "height": 20, "timestamp": 1_700_000_000})
+        );
+        assert_eq!(lines[2], json!([1.5, "i", "ls\r"]));
+        assert_eq!(lines[3], json!([1.75, "r", "100x30"]));
+
+        let loaded = Recording::open(&output).unwrap();
+        let events = |frames: &[RecordedFrame]| -> Vec<(String, u128, FrameOrigin)> {
+            frames
+                .iter()
+                .map(|f| (f.content.clone(), f.timestamp, f.origin))
+                .collect()
+        };
+        assert_eq!(events(&loaded.frames), events(&frames));
+        assert_eq!(loaded.metadata.width, Some(90));
+        assert_eq!(loaded.metadata.created, Some(1_700_000_000));
+
+        let _ = fs::remove_file(&input);
+        let _ = fs::remove_file(&output);
+    }
+}
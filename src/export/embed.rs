@@ -0,0 +1,136 @@
+use crate::utils;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+// Marks a GIF comment as holding an embedded rcrd recording
+const SOURCE_PREFIX: &str = "rcrd-source:gzip+base64:";
+
+// GIF block identifiers
+const EXTENSION_INTRODUCER: u8 = 0x21;
+const IMAGE_SEPARATOR: u8 = 0x2C;
+const TRAILER: u8 = 0x3B;
+const COMMENT_LABEL: u8 = 0xFE;
+
+// Encode a recording's JSON as the text of a GIF comment extension
+pub fn source_comment(recording_json: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(recording_json)?;
+    let compressed = encoder.finish()?;
+
+    let mut comment = SOURCE_PREFIX.as_bytes().to_vec();
+    comment.extend_from_slice(STANDARD.encode(compressed).as_bytes());
+    Ok(comment)
+}
+
+// Find the recording embedded in a GIF by `export --embed-source`
+pub fn extract_source(gif_path: &Path) -> io::Result<Vec<u8>> {
+    let data = fs::read(gif_path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Failed to read {}: {}", gif_path.display(), e),
+        )
+    })?;
+
+    for comment in gif_comments(&data)? {
+        if let Some(encoded) = comment.strip_prefix(SOURCE_PREFIX.as_bytes()) {
+            let compressed = STANDARD.decode(encoded).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Embedded recording is not valid base64: {}", e),
+                )
+            })?;
+
+            let mut json = Vec::new();
+            GzDecoder::new(compressed.as_slice()).read_to_end(&mut json)?;
+            return Ok(json);
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("No embedded recording found in {}", gif_path.display()),
+    ))
+}
+
+// Write the recording embedded in a GIF back out as a recording file
+pub fn extract_to_file(gif_file: &str, output_file: &str) -> io::Result<()> {
+    let gif_path = utils::get_absolute_path(gif_file);
+    let output_path = utils::get_absolute_path(output_file);
+
+    let source = extract_source(&gif_path)?;
+    fs::write(&output_path, &source)?;
+
+    println!(
+        "Extracted recording ({} bytes) to {}",
+        source.len(),
+        output_path.display()
+    );
+    Ok(())
+}
+
+// Walk the GIF block structure and collect the payload of every comment
+fn gif_comments(data: &[u8]) -> io::Result<Vec<Vec<u8>>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Malformed GIF file");
+
+    if data.len() < 13 || !data.starts_with(b"GIF8") {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a GIF file"));
+    }
+
+    // Header and logical screen descriptor, then the optional global palette
+    let mut pos = 13;
+    let flags = data[10];
+    if flags & 0x80 != 0 {
+        pos += 3 << ((flags & 0x07) + 1);
+    }
+
+    let mut comments = Vec::new();
+
+    while pos < data.len() {
+        match data[pos] {
+            EXTENSION_INTRODUCER => {
+                let label = *data.get(pos + 1).ok_or_else(invalid)?;
+                let (payload, next) = read_sub_blocks(data, pos + 2).ok_or_else(invalid)?;
+                if label == COMMENT_LABEL {
+                    comments.push(payload);
+                }
+                pos = next;
+            }
+            IMAGE_SEPARATOR => {
+                // Image descriptor, optional local palette, LZW code size
+                let flags = *data.get(pos + 9).ok_or_else(invalid)?;
+                pos += 10;
+                if flags & 0x80 != 0 {
+                    pos += 3 << ((flags & 0x07) + 1);
+                }
+                let (_, next) = read_sub_blocks(data, pos + 1).ok_or_else(invalid)?;
+                pos = next;
+            }
+            TRAILER => break,
+            _ => return Err(invalid()),
+        }
+    }
+
+    Ok(comments)
+}
+
+// Concatenate data sub-blocks starting at `pos`, returning the payload and
+// the position just past the block terminator
+fn read_sub_blocks(data: &[u8], mut pos: usize) -> Option<(Vec<u8>, usize)> {
+    let mut payload = Vec::new();
+
+    loop {
+        let len = *data.get(pos)? as usize;
+        pos += 1;
+        if len == 0 {
+            return Some((payload, pos));
+        }
+        payload.extend_from_slice(data.get(pos..pos + len)?);
+        pos += len;
+    }
+}
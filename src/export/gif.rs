@@ -1,19 +1,260 @@
-use crate::recording::{RecordedFrame, Recording};
-use crate::terminal::VirtualTerminal;
+use crate::export::ascii;
+use crate::export::embed;
+use crate::export::overlay::{self, AspectRatio, CellRect, Corner, DropShadow, OnionSkin};
+use crate::export::palette::{self, Palette};
+use crate::export::processor::FrameProcessor;
+use crate::export::prompt::PromptRewrite;
+use crate::export::timing;
+use crate::recording::annotations::{self, Callout, Highlight};
+use crate::recording::{
+    trim, typing, LoadLimits, RecordedFrame, Recording, Resize, DEFAULT_HEIGHT, DEFAULT_WIDTH,
+};
+use crate::terminal::font::OutlineFont;
+use crate::terminal::virtual_term::{CursorStyle, GlyphFit, BLINK_INTERVAL_MS};
+use crate::terminal::{TermColor, VirtualTerminal};
 use crate::utils;
-use gif::{Encoder, Frame, Repeat};
-use std::fs::File;
+use gif::{AnyExtension, DisposalMethod, Encoder, Extension, Frame, Repeat};
+use image::{ImageBuffer, Rgb};
+use std::fs::{self, File};
 use std::io::{self, BufWriter, Write};
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+
+// Time added in front of the recording for the intro banner
+const INTRO_OFFSET: u128 = 1500;
+
+// When the intro prompt and the outro appear, in milliseconds after the
+// intro and the last frame respectively
+const INTRO_PROMPT_DELAY: u128 = 1000;
+const OUTRO_DELAY: u128 = 1000;
+
+// Color and opacity used to draw highlight annotations
+const HIGHLIGHT_COLOR: TermColor = TermColor {
+    r: 255,
+    g: 215,
+    b: 0,
+};
+const HIGHLIGHT_OPACITY: f32 = 0.35;
+
+// How strongly the --grid lines show over the session
+const GRID_OPACITY: f32 = 0.25;
+
+// How strongly callout boxes cover the session behind them
+const CALLOUT_OPACITY: f32 = 0.8;
+
+// Delay between frames with --on-change-only (0.5 seconds)
+const CHANGE_GAP_CENTISECS: u16 = 50;
+
+// Embedded recordings larger than this get a size warning
+const EMBED_WARN_BYTES: usize = 1024 * 1024;
+
+// Set by Ctrl+C during an export, which then stops at the next frame
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// Framing added around a recording when it's exported
+#[derive(Clone, Copy, Debug)]
+pub struct Enhancements {
+    // Title card before the recording
+    pub intro: bool,
+    // Colored prompt drawn after the title card
+    pub intro_prompt: bool,
+    // Delay the recording to leave time to read the intro
+    pub time_offset: bool,
+    // Closing banner after the last frame
+    pub outro: bool,
+}
+
+impl Enhancements {
+    pub fn all() -> Self {
+        Enhancements {
+            intro: true,
+            intro_prompt: true,
+            time_offset: true,
+            outro: true,
+        }
+    }
+
+    pub fn none() -> Self {
+        Enhancements {
+            intro: false,
+            intro_prompt: false,
+            time_offset: false,
+            outro: false,
+        }
+    }
+}
+
+impl Default for Enhancements {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+// Settings controlling how a recording is rendered to a GIF
+#[derive(Clone, Debug)]
+pub struct ExportOptions {
+    // Speed multiplier; None uses the recording's default speed, or 1.0
+    pub speed: Option<f32>,
+    // Terminal size; None uses the size the recording was made at, or 80x24
+    pub width: Option<u16>,
+    pub height: Option<u16>,
+    pub font_size: u8,
+    pub dark_theme: bool,
+    // Default text and background colors; each replaces the theme's
+    pub fg_color: Option<TermColor>,
+    pub bg_color: Option<TermColor>,
+    // Corner to draw an elapsed-time clock in, if any
+    pub clock: Option<Corner>,
+    // Stop after emitting this many GIF frames
+    pub max_frames: Option<usize>,
+    // Emit the rendered frames back to front
+    pub reverse: bool,
+    // Replace the recorded shell prompt with a clean one
+    pub prompt: Option<PromptRewrite>,
+    // Center the region the recording draws in within the output
+    pub center: bool,
+    // Store the source recording in a GIF comment block
+    pub embed_source: bool,
+    // Transliterate non-ASCII text for the built-in font
+    pub ascii_only: bool,
+    // Replace the recorded timing with a steady reveal of the final screen
+    // at this many characters per second
+    pub reveal: Option<f32>,
+    // Keep every frame on screen for at least this many milliseconds
+    pub min_visible: Option<u64>,
+    // Play each section between chapter starts in this many seconds
+    pub section_duration: Option<f32>,
+    // Cap every pause between frames at this many seconds. Only the GIF's
+    // delays change; timestamps used for chapters, callouts and the clock
+    // are the recorded ones.
+    pub max_idle: Option<f32>,
+    // Win back the time added by min_visible from later idle gaps
+    pub preserve_duration: bool,
+    // Only export the chapter with this name from the chapters sidecar
+    pub chapter: Option<String>,
+    // Show the window title set by the session in a strip above the terminal
+    pub title_caption: bool,
+    // Skip frames that leave the screen unchanged and replace the recorded
+    // timing with a fixed gap between changes
+    pub on_change_only: bool,
+    // Fail the export if the recording uses escape sequences the virtual
+    // terminal doesn't support, listing which ones
+    pub strict: bool,
+    // Convert the output to shades of gray
+    pub grayscale: bool,
+    // Draw the text callouts stored in the recording
+    pub with_callouts: bool,
+    // Image to take the GIF's colors from; every frame is snapped to them
+    pub palette_from: Option<String>,
+    // Share one palette computed from every frame instead of quantizing
+    // each frame separately. Frames are held in memory until the end.
+    pub optimize: bool,
+    // How many times the GIF repeats, 0 for forever. Viewers disagree on
+    // whether this counts the first play, and many treat 1 as "play once".
+    pub loop_count: u16,
+    // TrueType or OpenType font to draw text with instead of the built-in
+    // bitmaps
+    pub font: Option<String>,
+    // Enlarge every frame by this whole factor, pixel for pixel
+    pub scale: u8,
+    // Intro, outro and timing added around the recording
+    pub enhance: Enhancements,
+    // Only output this part of the screen
+    pub crop: Option<CellRect>,
+    // Float the terminal on a padded canvas with a soft shadow behind it
+    pub shadow: bool,
+    // End on the last frame that changes the screen
+    pub trim_trailing: bool,
+    // Draw tabs, trailing spaces and line breaks as visible markers
+    pub show_whitespace: bool,
+    // How to draw the cursor; programs that hide it still hide it
+    pub cursor: CursorStyle,
+    // Draw the cell grid over the terminal, to check glyph alignment
+    pub grid: bool,
+    // Tint the cells each frame changed, fading over the next few frames
+    pub onion_skin: bool,
+    // Largest frame and recording file that will be loaded
+    pub limits: LoadLimits,
+    // Pad the output with bars to exactly this aspect ratio
+    pub aspect: Option<AspectRatio>,
+    // Color of the bars; the terminal background (or the shadow canvas) if unset
+    pub bar_color: Option<TermColor>,
+}
+
+impl ExportOptions {
+    // Terminal size to render at, where none was given or recorded
+    pub fn size(&self) -> (u16, u16) {
+        (
+            self.width.unwrap_or(DEFAULT_WIDTH),
+            self.height.unwrap_or(DEFAULT_HEIGHT),
+        )
+    }
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions {
+            speed: None,
+            width: None,
+            height: None,
+            font_size: 16,
+            dark_theme: true,
+            fg_color: None,
+            bg_color: None,
+            clock: None,
+            max_frames: None,
+            reverse: false,
+            prompt: None,
+            center: false,
+            embed_source: false,
+            ascii_only: false,
+            reveal: None,
+            min_visible: None,
+            section_duration: None,
+            max_idle: None,
+            preserve_duration: false,
+            chapter: None,
+            title_caption: false,
+            on_change_only: false,
+            strict: false,
+            grayscale: false,
+            with_callouts: false,
+            palette_from: None,
+            optimize: false,
+            loop_count: 0,
+            font: None,
+            scale: 1,
+            enhance: Enhancements::default(),
+            crop: None,
+            shadow: false,
+            trim_trailing: false,
+            show_whitespace: false,
+            cursor: CursorStyle::None,
+            grid: false,
+            onion_skin: false,
+            limits: LoadLimits::default(),
+            aspect: None,
+            bar_color: None,
+        }
+    }
+}
 
 pub fn export_to_gif(
     input_file: &str,
     output_file: &str,
-    speed: f32,
-    width: u16,
-    height: u16,
-    font_size: u8,
-    dark_theme: bool,
+    options: &ExportOptions,
+) -> io::Result<()> {
+    export_to_gif_with(input_file, output_file, options, &mut [])
+}
+
+// Export a recording, handing every rendered frame to the processors so
+// they can draw their own overlays
+pub fn export_to_gif_with(
+    input_file: &str,
+    output_file: &str,
+    options: &ExportOptions,
+    processors: &mut [Box<dyn FrameProcessor>],
 ) -> io::Result<()> {
     let input_path = utils::get_absolute_path(input_file);
     let output_path = utils::get_absolute_path(output_file);
@@ -28,15 +269,7 @@ pub fn export_to_gif(
                 "Original file not found, but found autosave: {}",
                 autosave_path.display()
             );
-            return export_to_gif_from_path(
-                &autosave_path,
-                &output_path,
-                speed,
-                width,
-                height,
-                font_size,
-                dark_theme,
-            );
+            return export_to_gif_from_path(&autosave_path, &output_path, options, processors);
         }
 
         return Err(io::Error::new(
@@ -45,32 +278,66 @@ pub fn export_to_gif(
         ));
     }
 
-    export_to_gif_from_path(
-        &input_path,
+    export_to_gif_from_path(&input_path, &output_path, options, processors)
+}
+
+// Render frames that were generated rather than loaded from a file. They are
+// written as-is, without the intro and outro added to recordings.
+pub fn export_frames_to_gif(
+    frames: Vec<RecordedFrame>,
+    output_file: &str,
+    options: &ExportOptions,
+) -> io::Result<()> {
+    let output_path = utils::get_absolute_path(output_file);
+
+    if frames.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "No frames to export",
+        ));
+    }
+
+    let source = if options.embed_source {
+        Some(
+            serde_json::to_vec_pretty(&frames)
+                .map_err(|e| io::Error::other(format!("JSON serialization error: {}", e)))?,
+        )
+    } else {
+        None
+    };
+
+    write_gif(
+        frames,
+        &Timeline::default(),
+        source.as_deref(),
         &output_path,
-        speed,
-        width,
-        height,
-        font_size,
-        dark_theme,
+        options,
+        &mut [],
     )
 }
 
 fn export_to_gif_from_path(
     input_path: &Path,
     output_path: &Path,
-    speed: f32,
-    width: u16,
-    height: u16,
-    font_size: u8,
-    dark_theme: bool,
+    options: &ExportOptions,
+    processors: &mut [Box<dyn FrameProcessor>],
 ) -> io::Result<()> {
     println!("Converting terminal recording to GIF...");
 
     // Load the frames
-    let frames = Recording::load(input_path)?;
+    let mut recording = Recording::open_with_limits(input_path, options.limits)?;
+    let resizes = recording.resizes();
+    let frames = recording.take_output();
+    let metadata = recording.metadata;
     println!("Loaded {} frames", frames.len());
 
+    let options = &ExportOptions {
+        speed: Some(options.speed.or(metadata.default_speed).unwrap_or(1.0)),
+        width: options.width.or(metadata.width),
+        height: options.height.or(metadata.height),
+        ..options.clone()
+    };
+
     if frames.is_empty() {
         return Err(io::Error::new(
             io::ErrorKind::InvalidData,
@@ -78,40 +345,329 @@ fn export_to_gif_from_path(
         ));
     }
 
+    let (frames, chapter_start) = match &options.chapter {
+        Some(name) => select_chapter(input_path, frames, name)?,
+        None => (frames, 0),
+    };
+
+    let frames = if options.trim_trailing {
+        let count = frames.len();
+        let (width, height) = options.size();
+        let frames = trim::trim_trailing(frames, width as usize, height as usize);
+        println!("Trimmed {} frames from the end", count - frames.len());
+        frames
+    } else {
+        frames
+    };
+
+    let frames = match &options.prompt {
+        Some(prompt) => prompt.apply(frames),
+        None => frames,
+    };
+
+    let frames = if options.ascii_only {
+        ascii::transliterate(frames)
+    } else {
+        frames
+    };
+
+    let frames = match options.reveal {
+        Some(chars_per_second) if chars_per_second > 0.0 && chars_per_second.is_finite() => {
+            reveal_frames(&frames, chars_per_second, options)
+        }
+        Some(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Reveal rate must be a positive number of characters per second",
+            ))
+        }
+        None => frames,
+    };
+
+    // Chapter starts mark the sections. Like the minimum below, the duration
+    // is in output time and is scaled back to recording time.
+    let frames = match options.section_duration {
+        Some(seconds) => {
+            let boundaries: Vec<u128> = annotations::load_chapters(input_path)?
+                .iter()
+                .filter_map(|c| c.start_ms.checked_sub(chapter_start))
+                .collect();
+            if boundaries.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "--section-duration needs chapters to mark the sections (see 'rcrd chapters')",
+                ));
+            }
+
+            let frames = timing::pace_sections(
+                frames,
+                &boundaries,
+                (seconds * 1000.0 * options.speed.unwrap_or(1.0)) as u128,
+            );
+            println!("Paced sections to {}s each", seconds);
+            frames
+        }
+        None => frames,
+    };
+
+    // The minimum is in output time, so scale it back to recording time
+    let frames = match options.min_visible {
+        Some(min_ms) => timing::enforce_min_visible(
+            frames,
+            (min_ms as f32 * options.speed.unwrap_or(1.0)) as u128,
+            options.preserve_duration,
+        ),
+        None => frames,
+    };
+
+    // Highlights are timed against the whole recording
+    let highlights: Vec<Highlight> = annotations::load_highlights(input_path)?
+        .into_iter()
+        .filter(|h| h.end_ms > chapter_start)
+        .map(|h| Highlight {
+            start_ms: h.start_ms.saturating_sub(chapter_start),
+            end_ms: h.end_ms - chapter_start,
+            ..h
+        })
+        .collect();
+    if !highlights.is_empty() {
+        println!("Loaded {} highlights", highlights.len());
+    }
+
+    let callouts: Vec<Callout> = if options.with_callouts {
+        metadata
+            .annotations
+            .into_iter()
+            .filter(|c| c.end_ms > chapter_start)
+            .map(|c| Callout {
+                start_ms: c.start_ms.saturating_sub(chapter_start),
+                end_ms: c.end_ms - chapter_start,
+                ..c
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    if !callouts.is_empty() {
+        println!("Loaded {} callouts", callouts.len());
+    }
+
+    // Resizes before the chapter all apply at its start, so the last one
+    // sets the size it opens with
+    let resizes: Vec<Resize> = resizes
+        .into_iter()
+        .map(|r| Resize {
+            timestamp: r.timestamp.saturating_sub(chapter_start),
+            ..r
+        })
+        .collect();
+    if !resizes.is_empty() {
+        println!("Loaded {} resizes", resizes.len());
+    }
+
+    let source = if options.embed_source {
+        Some(fs::read(input_path)?)
+    } else {
+        None
+    };
+
+    // Center what the recording draws, not the intro and outro around it
+    let center_offset = if options.center {
+        let (width, height) = options.size();
+        Some(centering_offset(
+            &frames,
+            width as usize,
+            height as usize,
+            options,
+        ))
+    } else {
+        None
+    };
+
+    // Add whichever intro, outro and timing enhancements are enabled
+    let (enhanced_frames, time_offset) = enhance_recording(frames, options.enhance);
+
+    write_gif(
+        enhanced_frames,
+        &Timeline {
+            time_offset,
+            center_offset,
+            highlights: &highlights,
+            callouts: &callouts,
+            resizes: &resizes,
+        },
+        source.as_deref(),
+        output_path,
+        options,
+        processors,
+    )
+}
+
+// Events timed against the recording's own timeline rather than the frames
+// being written
+#[derive(Default)]
+struct Timeline<'a> {
+    // How far the frames were shifted from the recording's timeline
+    time_offset: u128,
+    // Centering worked out before the intro and outro were added, if the
+    // frames have them
+    center_offset: Option<(i64, i64)>,
+    highlights: &'a [Highlight],
+    callouts: &'a [Callout],
+    resizes: &'a [Resize],
+}
+
+fn write_gif(
+    frames: Vec<RecordedFrame>,
+    timeline: &Timeline,
+    source: Option<&[u8]>,
+    output_path: &Path,
+    options: &ExportOptions,
+    processors: &mut [Box<dyn FrameProcessor>],
+) -> io::Result<()> {
+    let ExportOptions {
+        speed,
+        font_size,
+        dark_theme,
+        ..
+    } = *options;
+    let (width, height) = options.size();
+
+    if width == 0 || height == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Terminal size {}x{} is too small (width and height must be at least 1)",
+                width, height
+            ),
+        ));
+    }
+
+    // Frames are drawn at the largest size the terminal reaches, with
+    // smaller screens drawn at the top left
+    let (grid_width, grid_height) = timeline.resizes.iter().fold((width, height), |(w, h), r| {
+        (w.max(r.width), h.max(r.height))
+    });
+
+    if let Some(crop) = options.crop {
+        if crop.x as u32 + crop.width as u32 > grid_width as u32
+            || crop.y as u32 + crop.height as u32 > grid_height as u32
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Crop {},{},{},{} extends past the {}x{} terminal",
+                    crop.x, crop.y, crop.width, crop.height, grid_width, grid_height
+                ),
+            ));
+        }
+    }
+
     // Create the virtual terminal
     let mut terminal = VirtualTerminal::new(width as usize, height as usize, dark_theme);
+    terminal.set_default_colors(options.fg_color, options.bg_color);
+    if let Some(path) = &options.font {
+        terminal.set_font(OutlineFont::load(path)?);
+    }
+    terminal.set_strict(options.strict);
+    terminal.set_show_whitespace(options.show_whitespace);
+    check_glyph_fit(&terminal, font_size);
 
-    // Enhanced frames with intro text
-    let enhanced_frames = enhance_recording(frames);
+    let center_offset = if options.center {
+        timeline
+            .center_offset
+            .unwrap_or_else(|| centering_offset(&frames, width as usize, height as usize, options))
+    } else {
+        (0, 0)
+    };
+
+    let mut palette = match &options.palette_from {
+        Some(image) => {
+            let palette = Palette::from_image(image, palette::PALETTE_SIZE)?;
+            println!("Extracted {} colors from {}", palette.len(), image);
+            Some(palette)
+        }
+        None => None,
+    };
 
-    // Setup GIF encoder
-    let file = File::create(output_path)?;
     let cell_width = font_size as u32;
     let cell_height = (font_size as f32 * 2.0) as u32;
-    let image_width = width as u32 * cell_width;
-    let image_height = height as u32 * cell_height;
-
-    // Create the encoder
-    let mut encoder = Encoder::new(
-        BufWriter::new(file),
-        image_width as u16,
-        image_height as u16,
-        &[],
-    )
-    .map_err(|e| {
-        io::Error::new(
-            io::ErrorKind::Other,
-            format!("Failed to create GIF encoder: {}", e),
-        )
-    })?;
+    let scale = options.scale.max(1) as u32;
+    let grid_size = (
+        grid_width as u32 * cell_width,
+        grid_height as u32 * cell_height,
+    );
+    let (columns, rows) = options
+        .crop
+        .map_or((grid_width, grid_height), |crop| (crop.width, crop.height));
+    let window_width = columns as u32 * cell_width;
+    let window_height = rows as u32 * cell_height
+        + if options.title_caption {
+            cell_height
+        } else {
+            0
+        };
+    let shadow = options
+        .shadow
+        .then(|| DropShadow::new(window_width, window_height));
+    let (padded_width, padded_height) = if options.shadow {
+        DropShadow::padded_size(window_width, window_height)
+    } else {
+        (window_width, window_height)
+    };
+    let letterbox_size = options
+        .aspect
+        .map(|aspect| aspect.letterbox_size(padded_width, padded_height));
+    let (unscaled_width, unscaled_height) = letterbox_size.unwrap_or((padded_width, padded_height));
+    let bar_color = options.bar_color.unwrap_or(if options.shadow {
+        overlay::SHADOW_CANVAS
+    } else {
+        terminal.default_colors().1
+    });
+    // Saturate rather than wrap so oversized output is caught below
+    let image_width = unscaled_width.saturating_mul(scale);
+    let image_height = unscaled_height.saturating_mul(scale);
 
-    // Configure the GIF encoder
-    encoder.set_repeat(Repeat::Infinite).map_err(|e| {
-        io::Error::new(
-            io::ErrorKind::Other,
-            format!("Failed to set GIF repeat mode: {}", e),
-        )
-    })?;
+    if image_width > u16::MAX as u32 || image_height > u16::MAX as u32 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Output would be {}x{} pixels, larger than a GIF allows ({}x{}); reduce the size, font size, scale or aspect ratio",
+                image_width,
+                image_height,
+                u16::MAX,
+                u16::MAX
+            ),
+        ));
+    }
+
+    if image_width == 0 || image_height == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Output would be {}x{} pixels; the font size and scale must be at least 1",
+                image_width, image_height
+            ),
+        ));
+    }
+
+    install_interrupt_handler();
+
+    // Reversed and optimized GIFs can only be encoded once every frame has
+    // been rendered, the latter because the palette comes first in the file
+    let buffered = options.reverse || options.optimize;
+    let image_size = (image_width as u16, image_height as u16);
+    let mut encoder = if buffered {
+        None
+    } else {
+        Some(create_encoder(
+            output_path,
+            image_size,
+            palette.as_ref(),
+            options.loop_count,
+            source,
+        )?)
+    };
 
     println!(
         "Creating GIF with dimensions {}x{}",
@@ -120,46 +676,212 @@ fn export_to_gif_from_path(
 
     // Process frames and add to GIF
     let mut last_timestamp: u128 = 0;
+    let mut elapsed_centisecs: u64 = 0;
+    let mut last_screen: Option<String> = None;
     let mut frame_counter = 0;
+    let mut snapshots = Vec::new();
+    // Rendered frame waiting to be written, held back so it can be compared
+    // with the next one
+    let mut pending: Option<Snapshot> = None;
+    // Delay of identical frames that were dropped. A frame's delay is the
+    // wait that led up to it, so this goes to the next frame that differs
+    // (or the last one, to keep the length).
+    let mut carried_delay: u16 = 0;
+    // GIF frames written or waiting in `pending`, and whether the cap on
+    // them stopped the export
+    let mut written_frames = 0;
+    let mut truncated = false;
+    let mut onion_skin = options
+        .onion_skin
+        .then(|| OnionSkin::new(&terminal, options.show_whitespace));
+    let mut resizes = timeline.resizes.iter().peekable();
+
+    for frame in frames {
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            println!();
+            eprintln!("Interrupted, finishing the GIF with the frames rendered so far");
+            break;
+        }
 
-    for frame in enhanced_frames {
         // Calculate delay since last frame
         let mut delay_centisecs = 10; // Default delay (0.1 seconds)
 
         if last_timestamp > 0 {
-            let delay_ms = frame.timestamp - last_timestamp;
+            let delay_ms = timing::cap_idle(
+                frame.timestamp.saturating_sub(last_timestamp),
+                options.max_idle,
+            );
             // Convert to centiseconds and apply speed factor
-            delay_centisecs = ((delay_ms as f32 / speed) / 10.0) as u16;
+            delay_centisecs = ((delay_ms as f32 / speed.unwrap_or(1.0)) / 10.0) as u16;
 
             // Limit delay to reasonable bounds (0.02s to 5s)
             delay_centisecs = delay_centisecs.clamp(2, 500);
         }
 
+        // Highlight and resize times are relative to the original recording
+        let recording_time = frame.timestamp.saturating_sub(timeline.time_offset);
+        while let Some(resize) = resizes.next_if(|r| r.timestamp <= recording_time) {
+            terminal.resize(resize.width as usize, resize.height as usize);
+        }
+
         // Process this frame's content
         terminal.process_content(&frame.content);
 
-        // Render the terminal to an image
-        let img = terminal.render_to_image(font_size);
+        if options.on_change_only {
+            let screen = terminal.styled_screen_text();
+            if last_screen.as_ref() == Some(&screen) {
+                last_timestamp = frame.timestamp;
+                continue;
+            }
+            last_screen = Some(screen);
+            delay_centisecs = CHANGE_GAP_CENTISECS;
+        }
 
-        // Convert to GIF frame format
-        let mut buffer = Vec::new();
-        for pixel in img.pixels() {
-            buffer.push(pixel[0]);
-            buffer.push(pixel[1]);
-            buffer.push(pixel[2]);
+        if let Some(onion_skin) = &mut onion_skin {
+            onion_skin.update(&terminal);
         }
 
-        // Add frame to GIF
-        let mut gif_frame = Frame::from_rgb(image_width as u16, image_height as u16, &buffer);
+        // Blinking text only toggles if there are frames to show it on, so
+        // long holds are cut at each blink beat while any cell is blinking
+        let beats = if terminal.has_blink() {
+            blink_beats(elapsed_centisecs, delay_centisecs)
+        } else {
+            vec![(elapsed_centisecs, delay_centisecs)]
+        };
 
-        gif_frame.delay = delay_centisecs;
+        for (beat_start, beat_delay) in beats {
+            // Render the terminal to an image
+            let mut img = terminal.render_to_image(font_size, beat_start as u128 * 10);
+            if img.dimensions() != grid_size {
+                img = overlay::pad(&img, grid_size, terminal.default_colors().1);
+            }
+            terminal.draw_cursor(&mut img, font_size, options.cursor);
 
-        encoder.write_frame(&gif_frame).map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::Other,
-                format!("Failed to write frame to GIF: {}", e),
-            )
-        })?;
+            for highlight in timeline
+                .highlights
+                .iter()
+                .filter(|h| h.is_active(recording_time))
+            {
+                overlay::highlight_cells(
+                    &mut img,
+                    (highlight.x, highlight.y, highlight.width, highlight.height),
+                    font_size,
+                    HIGHLIGHT_COLOR,
+                    HIGHLIGHT_OPACITY,
+                );
+            }
+
+            if let Some(onion_skin) = &onion_skin {
+                onion_skin.draw(&mut img, font_size);
+            }
+
+            if center_offset != (0, 0) {
+                let (dx, dy) = center_offset;
+                let (_, default_bg) = terminal.default_colors();
+                img = overlay::shift_image(
+                    &img,
+                    (dx * cell_width as i64, dy * cell_height as i64),
+                    default_bg,
+                );
+            }
+
+            if let Some(crop) = options.crop {
+                img = overlay::crop_cells(&img, crop, font_size);
+            }
+
+            if options.grid {
+                let (default_fg, _) = terminal.default_colors();
+                overlay::draw_grid(&mut img, font_size, default_fg, GRID_OPACITY);
+            }
+
+            for callout in timeline
+                .callouts
+                .iter()
+                .filter(|c| c.is_active(recording_time))
+            {
+                overlay::draw_callout(
+                    &terminal,
+                    &mut img,
+                    &callout.text,
+                    callout.position,
+                    font_size,
+                    CALLOUT_OPACITY,
+                );
+            }
+
+            // The clock reads the recording's own time, so it starts at zero
+            // on the first recorded frame however long the intro is
+            if let Some(corner) = options.clock {
+                let clock = overlay::format_clock(recording_time);
+                overlay::draw_corner_label(&terminal, &mut img, &clock, corner, font_size);
+            }
+
+            if options.title_caption {
+                img = overlay::add_caption_strip(
+                    &terminal,
+                    &img,
+                    terminal.title().unwrap_or(""),
+                    font_size,
+                );
+            }
+
+            for processor in processors.iter_mut() {
+                processor.process(&mut img, frame_counter, frame.timestamp);
+            }
+
+            if let Some(shadow) = &shadow {
+                img = shadow.apply(&img);
+            }
+
+            if let Some(size) = letterbox_size {
+                img = overlay::letterbox(&img, size, bar_color);
+            }
+
+            if options.grayscale {
+                overlay::grayscale(&mut img);
+            }
+
+            if scale > 1 {
+                img = overlay::scale_image(&img, scale);
+            }
+
+            match &mut pending {
+                Some((previous, _)) if *previous == img => {
+                    carried_delay = carried_delay.saturating_add(beat_delay);
+                }
+                _ => {
+                    // The cap counts frames that end up in the GIF, after
+                    // identical ones are folded together
+                    if options.max_frames.is_some_and(|max| written_frames >= max) {
+                        truncated = true;
+                        break;
+                    }
+                    written_frames += 1;
+
+                    let delay = beat_delay.saturating_add(carried_delay);
+                    carried_delay = 0;
+                    if let Some((previous, delay)) = pending.replace((img, delay)) {
+                        emit_frame(
+                            &mut encoder,
+                            &mut snapshots,
+                            previous,
+                            delay,
+                            palette.as_mut(),
+                        )?;
+                    }
+                }
+            }
+        }
+        if truncated {
+            println!();
+            eprintln!(
+                "Warning: Reached the limit of {} frames, output is truncated",
+                written_frames
+            );
+            break;
+        }
+
+        elapsed_centisecs += delay_centisecs as u64;
 
         frame_counter += 1;
         if frame_counter % 10 == 0 {
@@ -170,42 +892,689 @@ fn export_to_gif_from_path(
         last_timestamp = frame.timestamp;
     }
 
+    if let Some((img, delay)) = pending {
+        let delay = delay.saturating_add(carried_delay);
+        emit_frame(&mut encoder, &mut snapshots, img, delay, palette.as_mut())?;
+    }
+
+    let encoder = match encoder {
+        Some(encoder) => encoder,
+        None => {
+            if options.optimize && palette.is_none() {
+                let images: Vec<_> = snapshots.iter().map(|(img, _)| img).collect();
+                let shared = Palette::from_frames(&images);
+                println!("\nComputed a shared palette of {} colors", shared.len());
+                palette = Some(shared);
+            }
+
+            // Terminal state only builds up forwards, so a reversed GIF is
+            // encoded from the snapshot rendered at each step, played back
+            // to front
+            if options.reverse {
+                snapshots.reverse();
+            }
+
+            let mut encoder = create_encoder(
+                output_path,
+                image_size,
+                palette.as_ref(),
+                options.loop_count,
+                source,
+            )?;
+            let mut previous: Option<Vec<u8>> = None;
+            for (img, delay_centisecs) in &snapshots {
+                match (options.optimize, palette.as_mut()) {
+                    (true, Some(palette)) => {
+                        let indices = palette.index_image(img);
+                        write_changed_region(
+                            &mut encoder,
+                            &indices,
+                            previous.as_deref(),
+                            image_size,
+                            *delay_centisecs,
+                        )?;
+                        previous = Some(indices);
+                    }
+                    (_, palette) => write_gif_frame(&mut encoder, img, *delay_centisecs, palette)?,
+                }
+            }
+            encoder
+        }
+    };
+
+    // Write the trailer that makes the file a complete GIF
+    encoder.into_inner()?.flush()?;
+
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        println!(
+            "Saved {} frames to {}",
+            frame_counter,
+            output_path.display()
+        );
+        return Err(io::Error::new(
+            io::ErrorKind::Interrupted,
+            "Export interrupted",
+        ));
+    }
+
     println!("\nGIF successfully created at {}", output_path.display());
     println!("Frames processed: {}", frame_counter);
 
+    let unhandled = terminal.unhandled_sequences();
+    if !unhandled.is_empty() {
+        eprintln!("Unsupported escape sequences:");
+        for (name, count) in unhandled {
+            eprintln!("  {:<16} {}x", name, count);
+        }
+
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Recording uses {} unsupported escape sequences",
+                unhandled.len()
+            ),
+        ));
+    }
+
     Ok(())
 }
 
-fn enhance_recording(frames: Vec<RecordedFrame>) -> Vec<RecordedFrame> {
-    let mut enhanced = Vec::new();
+// Split a frame's display time into (start, delay) pieces at every blink
+// beat, in centiseconds of output time. Pieces shorter than the GIF minimum
+// delay are folded into their neighbours.
+fn blink_beats(start: u64, delay: u16) -> Vec<(u64, u16)> {
+    let interval = (BLINK_INTERVAL_MS / 10) as u64;
+    let end = start + delay as u64;
+    let mut beats = Vec::new();
+    let mut beat_start = start;
+    let mut boundary = (start / interval + 1) * interval;
+
+    while boundary < end {
+        if boundary - beat_start >= 2 && end - boundary >= 2 {
+            beats.push((beat_start, (boundary - beat_start) as u16));
+            beat_start = boundary;
+        }
+        boundary += interval;
+    }
+
+    beats.push((beat_start, (end - beat_start) as u16));
+    beats
+}
+
+// Frames that type out the recording's final screen at a steady pace
+fn reveal_frames(
+    frames: &[RecordedFrame],
+    chars_per_second: f32,
+    options: &ExportOptions,
+) -> Vec<RecordedFrame> {
+    let (width, height) = options.size();
+    let screen = Recording::final_screen(frames, width as usize, height as usize);
+    let delay_ms = ((1000.0 / chars_per_second) as u128).max(1);
+
+    typing::typing_frames(screen.trim_end(), delay_ms, 0)
+}
+
+// Cut the recording down to one chapter, returning its frames (rebased to
+// start at zero) and the original time they start at
+fn select_chapter(
+    input_path: &Path,
+    frames: Vec<RecordedFrame>,
+    name: &str,
+) -> io::Result<(Vec<RecordedFrame>, u128)> {
+    let chapters = annotations::load_chapters(input_path)?;
+    let chapter = chapters.iter().find(|c| c.name == name).ok_or_else(|| {
+        let names: Vec<&str> = chapters.iter().map(|c| c.name.as_str()).collect();
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "No chapter named '{}' (available: {})",
+                name,
+                if names.is_empty() {
+                    "none".to_string()
+                } else {
+                    names.join(", ")
+                }
+            ),
+        )
+    })?;
+
+    let range = chapter.frame_range(&frames);
+    if range.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Chapter '{}' contains no frames", name),
+        ));
+    }
+
+    println!(
+        "Exporting chapter '{}' ({} frames)",
+        chapter.name,
+        range.len()
+    );
+
+    let start = frames[range.start].timestamp;
+    Ok((Recording::slice(&frames, range), start))
+}
+
+// Cell offset that centers everything the recording draws at any point
+fn centering_offset(
+    frames: &[RecordedFrame],
+    width: usize,
+    height: usize,
+    options: &ExportOptions,
+) -> (i64, i64) {
+    let mut terminal = VirtualTerminal::new(width, height, options.dark_theme);
+    terminal.set_default_colors(options.fg_color, options.bg_color);
+    let mut bounds: Option<(usize, usize, usize, usize)> = None;
+
+    for frame in frames {
+        terminal.process_content(&frame.content);
+
+        if let Some((x0, y0, x1, y1)) = terminal.used_region() {
+            bounds = Some(match bounds {
+                None => (x0, y0, x1, y1),
+                Some((min_x, min_y, max_x, max_y)) => {
+                    (min_x.min(x0), min_y.min(y0), max_x.max(x1), max_y.max(y1))
+                }
+            });
+        }
+    }
+
+    match bounds {
+        Some((min_x, min_y, max_x, max_y)) => {
+            let used_width = max_x - min_x + 1;
+            let used_height = max_y - min_y + 1;
+            (
+                ((width - used_width) / 2) as i64 - min_x as i64,
+                ((height - used_height) / 2) as i64 - min_y as i64,
+            )
+        }
+        None => (0, 0),
+    }
+}
+
+// Start a GIF that repeats `loop_count` times (0 for forever), with the
+// palette shared by every frame if there is one and the source recording if
+// it's being embedded
+fn create_encoder(
+    output_path: &Path,
+    (width, height): (u16, u16),
+    palette: Option<&Palette>,
+    loop_count: u16,
+    source: Option<&[u8]>,
+) -> io::Result<Encoder<BufWriter<File>>> {
+    let global_palette = palette.map(Palette::to_bytes).unwrap_or_default();
+    let file = File::create(output_path)?;
+
+    let mut encoder = Encoder::new(BufWriter::new(file), width, height, &global_palette)
+        .map_err(|e| io::Error::other(format!("Failed to create GIF encoder: {}", e)))?;
+
+    let repeat = match loop_count {
+        0 => Repeat::Infinite,
+        count => Repeat::Finite(count),
+    };
+    encoder
+        .set_repeat(repeat)
+        .map_err(|e| io::Error::other(format!("Failed to set GIF repeat mode: {}", e)))?;
+
+    if let Some(source) = source {
+        let comment = embed::source_comment(source)?;
+        if comment.len() > EMBED_WARN_BYTES {
+            eprintln!(
+                "Warning: Embedding the recording adds {} KB to the GIF",
+                comment.len() / 1024
+            );
+        }
+
+        encoder.write_raw_extension(AnyExtension(Extension::Comment as u8), &[&comment])?;
+        println!("Embedded source recording ({} bytes)", comment.len());
+    }
+
+    Ok(encoder)
+}
+
+// Rendered frame and how long it stays on screen, in centiseconds
+type Snapshot = (ImageBuffer<Rgb<u8>, Vec<u8>>, u16);
+
+// Write a frame straight away, or keep it for later if the GIF is encoded
+// only once everything has been rendered
+fn emit_frame(
+    encoder: &mut Option<Encoder<BufWriter<File>>>,
+    snapshots: &mut Vec<Snapshot>,
+    img: ImageBuffer<Rgb<u8>, Vec<u8>>,
+    delay_centisecs: u16,
+    palette: Option<&mut Palette>,
+) -> io::Result<()> {
+    match encoder {
+        Some(encoder) => write_gif_frame(encoder, &img, delay_centisecs, palette),
+        None => {
+            snapshots.push((img, delay_centisecs));
+            Ok(())
+        }
+    }
+}
+
+fn write_gif_frame(
+    encoder: &mut Encoder<BufWriter<File>>,
+    img: &ImageBuffer<Rgb<u8>, Vec<u8>>,
+    delay_centisecs: u16,
+    palette: Option<&mut Palette>,
+) -> io::Result<()> {
+    // Without a fixed palette, each frame gets its own quantized one
+    let mut gif_frame = match palette {
+        Some(palette) => Frame {
+            width: img.width() as u16,
+            height: img.height() as u16,
+            buffer: palette.index_image(img).into(),
+            ..Frame::default()
+        },
+        None => Frame::from_rgb(img.width() as u16, img.height() as u16, img.as_raw()),
+    };
+    gif_frame.delay = delay_centisecs;
+
+    encoder
+        .write_frame(&gif_frame)
+        .map_err(|e| io::Error::other(format!("Failed to write frame to GIF: {}", e)))
+}
 
-    // Add intro frame
-    enhanced.push(RecordedFrame {
-        content: "\x1B[H\x1B[2J\x1B[1;32m# Terminal Recording\x1B[0m\n\n".to_string(),
-        timestamp: 0,
+// Write only the rectangle of pixels that differ from the previous frame,
+// leaving the rest of it on screen. Frames that change nothing still need a
+// pixel to carry their delay.
+fn write_changed_region(
+    encoder: &mut Encoder<BufWriter<File>>,
+    indices: &[u8],
+    previous: Option<&[u8]>,
+    (width, height): (u16, u16),
+    delay_centisecs: u16,
+) -> io::Result<()> {
+    let (width, height) = (width as usize, height as usize);
+    let (mut left, mut top, mut right, mut bottom) = (width, height, 0, 0);
+    match previous {
+        Some(previous) => {
+            for (i, (new, old)) in indices.iter().zip(previous).enumerate() {
+                if new != old {
+                    let (x, y) = (i % width, i / width);
+                    left = left.min(x);
+                    top = top.min(y);
+                    right = right.max(x + 1);
+                    bottom = bottom.max(y + 1);
+                }
+            }
+        }
+        None => (left, top, right, bottom) = (0, 0, width, height),
+    }
+    if left >= right {
+        (left, top, right, bottom) = (0, 0, 1, 1);
+    }
+
+    let buffer: Vec<u8> = (top..bottom)
+        .flat_map(|y| &indices[y * width + left..y * width + right])
+        .copied()
+        .collect();
+    let gif_frame = Frame {
+        left: left as u16,
+        top: top as u16,
+        width: (right - left) as u16,
+        height: (bottom - top) as u16,
+        delay: delay_centisecs,
+        dispose: DisposalMethod::Keep,
+        buffer: buffer.into(),
+        ..Frame::default()
+    };
+
+    encoder
+        .write_frame(&gif_frame)
+        .map_err(|e| io::Error::other(format!("Failed to write frame to GIF: {}", e)))
+}
+
+// Let Ctrl+C stop an export early while still leaving a playable GIF. A
+// second Ctrl+C exits straight away. While recording, the recorder's handler
+// is already installed and takes precedence.
+fn install_interrupt_handler() {
+    static INSTALLED: Once = Once::new();
+
+    INSTALLED.call_once(|| {
+        let _ = ctrlc::set_handler(|| {
+            if INTERRUPTED.swap(true, Ordering::SeqCst) {
+                std::process::exit(130);
+            }
+        });
     });
+}
 
-    // Add a small delay
-    enhanced.push(RecordedFrame {
-        content: "\x1B[1;34m$ \x1B[0m".to_string(), // Colored prompt
-        timestamp: 1000,                            // 1 second after welcome
+// Warn once when the bitmap font doesn't suit the font size, suggesting the
+// nearest size that does
+pub(crate) fn check_glyph_fit(terminal: &VirtualTerminal, font_size: u8) {
+    static WARNED: Once = Once::new();
+
+    let problem = match terminal.glyph_fit(font_size) {
+        GlyphFit::Fits => return,
+        GlyphFit::Overflows => "are larger than their cells and will be clipped",
+        GlyphFit::Cramped => "fill less than half of their cells and will look cramped",
+    };
+
+    let suggestion = (1..=u8::MAX)
+        .flat_map(|d| [font_size.checked_add(d), font_size.checked_sub(d)])
+        .flatten()
+        .find(|&size| terminal.glyph_fit(size) == GlyphFit::Fits);
+
+    WARNED.call_once(|| match suggestion {
+        Some(size) => eprintln!(
+            "Warning: at font size {} glyphs {}; try --font-size {}",
+            font_size, problem, size
+        ),
+        None => eprintln!("Warning: at font size {} glyphs {}", font_size, problem),
     });
+}
 
-    // Add the original frames, adjusting timestamps
-    let time_offset = 1500; // 1.5 seconds of intro time
-    for frame in frames {
-        enhanced.push(RecordedFrame {
-            content: frame.content,
+// Frame the recording for export with whichever enhancements are enabled.
+// Returns the frames and how far the recording was shifted to make room
+// for the intro.
+fn enhance_recording(
+    frames: Vec<RecordedFrame>,
+    enhance: Enhancements,
+) -> (Vec<RecordedFrame>, u128) {
+    let time_offset = if enhance.time_offset { INTRO_OFFSET } else { 0 };
+    let mut enhanced = Vec::with_capacity(frames.len() + 3);
+
+    if enhance.intro {
+        enhanced.push(intro_frame());
+    }
+    if enhance.intro_prompt {
+        enhanced.push(intro_prompt_frame(time_offset));
+    }
+    enhanced.extend(offset_frames(frames, time_offset));
+    if enhance.outro {
+        let outro = outro_frame(enhanced.last().map_or(0, |f| f.timestamp));
+        enhanced.push(outro);
+    }
+
+    (enhanced, time_offset)
+}
+
+// Title card shown before the recording starts
+fn intro_frame() -> RecordedFrame {
+    RecordedFrame::new(
+        "\x1B[H\x1B[2J\x1B[1;32m# Terminal Recording\x1B[0m\n\n".to_string(),
+        0,
+    )
+}
+
+// Colored prompt shown a second after the title, or as the recording
+// starts if it isn't delayed that long
+fn intro_prompt_frame(time_offset: u128) -> RecordedFrame {
+    RecordedFrame::new(
+        "\x1B[1;34m$ \x1B[0m".to_string(),
+        INTRO_PROMPT_DELAY.min(time_offset),
+    )
+}
+
+fn offset_frames(frames: Vec<RecordedFrame>, time_offset: u128) -> Vec<RecordedFrame> {
+    frames
+        .into_iter()
+        .map(|frame| RecordedFrame {
             timestamp: frame.timestamp + time_offset,
-        });
+            ..frame
+        })
+        .collect()
+}
+
+// Closing banner shown a second after the last frame
+fn outro_frame(last_timestamp: u128) -> RecordedFrame {
+    RecordedFrame::new(
+        "\n\n\x1B[1;32m# End of Recording\x1B[0m\n".to_string(),
+        last_timestamp + OUTRO_DELAY,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Frames that each show a different line of text, a second apart
+    fn distinct_frames(count: usize) -> Vec<RecordedFrame> {
+        (0..count)
+            .map(|i| RecordedFrame::new(format!("line {}\r\n", i), i as u128 * 1000))
+            .collect()
     }
 
-    // Add outro frame
-    let last_timestamp = enhanced.last().map(|f| f.timestamp).unwrap_or(0);
-    enhanced.push(RecordedFrame {
-        content: "\n\n\x1B[1;32m# End of Recording\x1B[0m\n".to_string(),
-        timestamp: last_timestamp + 1000, // 1 second after the last frame
-    });
+    fn small_options() -> ExportOptions {
+        ExportOptions {
+            width: Some(20),
+            height: Some(4),
+            font_size: 4,
+            ..ExportOptions::default()
+        }
+    }
+
+    // Delay of every frame in a GIF, in centiseconds
+    fn gif_frame_delays(path: &Path) -> Vec<u16> {
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(File::open(path).unwrap()).unwrap();
+        let mut delays = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            delays.push(frame.delay);
+        }
+        delays
+    }
+
+    fn gif_frame_count(path: &Path) -> usize {
+        gif_frame_delays(path).len()
+    }
+
+    #[test]
+    fn exports_tiny_terminals() {
+        for size in [1, 2] {
+            let path = utils::temp_path(&format!("tiny-{}.gif", size));
+            let options = ExportOptions {
+                width: Some(size),
+                height: Some(size),
+                ..small_options()
+            };
+            export_frames_to_gif(distinct_frames(3), path.to_str().unwrap(), &options).unwrap();
+
+            assert!(gif_frame_count(&path) > 0);
+            let _ = fs::remove_file(&path);
+        }
+    }
+
+    #[test]
+    fn oversized_output_is_refused_before_writing() {
+        let path = utils::temp_path("oversized.gif");
+        let options = ExportOptions {
+            width: Some(5000),
+            height: Some(2),
+            font_size: 16,
+            ..ExportOptions::default()
+        };
+        let err =
+            export_frames_to_gif(distinct_frames(1), path.to_str().unwrap(), &options).unwrap_err();
 
-    enhanced
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("80000x64"), "{}", err);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn empty_output_is_refused_before_writing() {
+        let path = utils::temp_path("empty.gif");
+        let options = ExportOptions {
+            font_size: 0,
+            ..small_options()
+        };
+        let err =
+            export_frames_to_gif(distinct_frames(1), path.to_str().unwrap(), &options).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("at least 1"), "{}", err);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn tiny_speeds_give_bounded_frame_delays() {
+        let path = utils::temp_path("slow.gif");
+        let options = ExportOptions {
+            speed: Some(0.001),
+            ..small_options()
+        };
+        export_frames_to_gif(distinct_frames(3), path.to_str().unwrap(), &options).unwrap();
+
+        for delay in gif_frame_delays(&path) {
+            assert!(delay <= 500, "delay of {} centiseconds", delay);
+        }
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn max_idle_caps_long_pauses() {
+        let path = utils::temp_path("max-idle.gif");
+        let frames = vec![
+            RecordedFrame::new("a".to_string(), 1000),
+            RecordedFrame::new("b".to_string(), 61_000),
+            RecordedFrame::new("c".to_string(), 61_500),
+        ];
+        let options = ExportOptions {
+            max_idle: Some(2.0),
+            ..small_options()
+        };
+        export_frames_to_gif(frames, path.to_str().unwrap(), &options).unwrap();
+
+        assert_eq!(gif_frame_delays(&path), vec![10, 200, 50]);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn optimized_gif_is_smaller_and_keeps_every_frame() {
+        let frames: Vec<RecordedFrame> = (0..8)
+            .map(|i| {
+                RecordedFrame::new(
+                    format!("\x1B[3{}m$ step {}\x1B[0m\r\n", 1 + i % 6, i),
+                    i as u128 * 200,
+                )
+            })
+            .collect();
+        let naive = utils::temp_path("naive.gif");
+        let optimized = utils::temp_path("optimized.gif");
+        let options = ExportOptions {
+            width: Some(30),
+            height: Some(10),
+            font_size: 8,
+            ..ExportOptions::default()
+        };
+        export_frames_to_gif(frames.clone(), naive.to_str().unwrap(), &options).unwrap();
+        let optimize = ExportOptions {
+            optimize: true,
+            ..options
+        };
+        export_frames_to_gif(frames, optimized.to_str().unwrap(), &optimize).unwrap();
+
+        let size = |path: &Path| fs::metadata(path).unwrap().len();
+        assert!(size(&optimized) < size(&naive));
+        assert_eq!(gif_frame_delays(&optimized), gif_frame_delays(&naive));
+
+        let _ = fs::remove_file(&naive);
+        let _ = fs::remove_file(&optimized);
+    }
+
+    #[test]
+    fn identical_frames_pass_their_delay_to_the_next_change() {
+        let path = utils::temp_path("dedup.gif");
+        let frames = vec![
+            RecordedFrame::new("a".to_string(), 1000),
+            // Changes nothing on screen
+            RecordedFrame::new("\x1B[0m".to_string(), 1500),
+            RecordedFrame::new("b".to_string(), 2200),
+        ];
+        export_frames_to_gif(frames, path.to_str().unwrap(), &small_options()).unwrap();
+
+        assert_eq!(gif_frame_delays(&path), vec![10, 120]);
+
+        // With no change after them, the last frame keeps their delay
+        let frames = vec![
+            RecordedFrame::new("a".to_string(), 1000),
+            RecordedFrame::new("b".to_string(), 1500),
+            RecordedFrame::new("\x1B[0m".to_string(), 2000),
+        ];
+        export_frames_to_gif(frames, path.to_str().unwrap(), &small_options()).unwrap();
+
+        assert_eq!(gif_frame_delays(&path), vec![10, 100]);
+        let _ = fs::remove_file(&path);
+    }
+
+    fn first_frame_pixels(path: &Path) -> Vec<u8> {
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let mut decoder = options.read_info(File::open(path).unwrap()).unwrap();
+        decoder.read_next_frame().unwrap().unwrap().buffer.to_vec()
+    }
+
+    #[test]
+    fn unenhanced_export_starts_with_the_recording() {
+        let input = utils::temp_path("raw-source.json");
+        let frames = vec![RecordedFrame::new("$ echo hi\r\nhi\r\n".to_string(), 0)];
+        Recording::from_frames(frames.clone()).save(&input).unwrap();
+
+        // Generated frames are written without any enhancements, so they
+        // show what the recording's own first frame looks like
+        let expected = utils::temp_path("raw-expected.gif");
+        export_frames_to_gif(frames, expected.to_str().unwrap(), &small_options()).unwrap();
+
+        let raw = utils::temp_path("raw.gif");
+        let options = ExportOptions {
+            enhance: Enhancements::none(),
+            ..small_options()
+        };
+        export_to_gif(input.to_str().unwrap(), raw.to_str().unwrap(), &options).unwrap();
+        assert_eq!(first_frame_pixels(&raw), first_frame_pixels(&expected));
+
+        let enhanced = utils::temp_path("enhanced.gif");
+        export_to_gif(
+            input.to_str().unwrap(),
+            enhanced.to_str().unwrap(),
+            &small_options(),
+        )
+        .unwrap();
+        assert_ne!(first_frame_pixels(&enhanced), first_frame_pixels(&expected));
+
+        for path in [input, expected, raw, enhanced] {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    #[test]
+    fn centering_offset_centers_what_is_drawn() {
+        let frames = vec![RecordedFrame::new("ab".to_string(), 0)];
+        assert_eq!(
+            centering_offset(&frames, 10, 3, &ExportOptions::default()),
+            (4, 1)
+        );
+    }
+
+    #[test]
+    fn max_frames_caps_the_frames_written() {
+        let path = utils::temp_path("max-frames.gif");
+        let options = ExportOptions {
+            max_frames: Some(3),
+            ..small_options()
+        };
+        export_frames_to_gif(distinct_frames(6), path.to_str().unwrap(), &options).unwrap();
+
+        assert_eq!(gif_frame_count(&path), 3);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn timestamps_going_backwards_give_the_shortest_delay() {
+        let path = utils::temp_path("backwards.gif");
+        let frames = vec![
+            RecordedFrame::new("a".to_string(), 1000),
+            RecordedFrame::new("b".to_string(), 400),
+            RecordedFrame::new("c".to_string(), 900),
+        ];
+        export_frames_to_gif(frames, path.to_str().unwrap(), &small_options()).unwrap();
+
+        assert_eq!(gif_frame_delays(&path), vec![10, 2, 50]);
+        let _ = fs::remove_file(&path);
+    }
 }
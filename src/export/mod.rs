@@ -1,2 +1,14 @@
+pub mod ascii;
 pub mod bitmap;
+pub mod cast;
+pub mod embed;
 pub mod gif;
+pub mod overlay;
+pub mod palette;
+pub mod png;
+pub mod preview;
+pub mod processor;
+pub mod prompt;
+pub mod split;
+pub mod timing;
+pub mod video;
@@ -0,0 +1,614 @@
+use crate::terminal::virtual_term::TermCell;
+use crate::terminal::{TermColor, VirtualTerminal};
+use image::{ImageBuffer, Rgb};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+// Corner of the output image used to anchor an overlay
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl FromStr for Corner {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "top-left" => Ok(Corner::TopLeft),
+            "top-right" => Ok(Corner::TopRight),
+            "bottom-left" => Ok(Corner::BottomLeft),
+            "bottom-right" => Ok(Corner::BottomRight),
+            _ => Err(format!(
+                "Invalid position '{}' (expected top-left, top-right, bottom-left or bottom-right)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Corner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(match self {
+            Corner::TopLeft => "top-left",
+            Corner::TopRight => "top-right",
+            Corner::BottomLeft => "bottom-left",
+            Corner::BottomRight => "bottom-right",
+        })
+    }
+}
+
+// A rectangle of terminal cells, written as x,y,width,height
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CellRect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl FromStr for CellRect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|part| part.trim().parse::<u16>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Invalid rectangle '{}': {}", s, e))?;
+
+        match values[..] {
+            [x, y, width, height] if width > 0 && height > 0 => Ok(CellRect {
+                x,
+                y,
+                width,
+                height,
+            }),
+            [_, _, _, _] => Err(format!(
+                "Invalid rectangle '{}' (width and height must be at least 1)",
+                s
+            )),
+            _ => Err(format!(
+                "Invalid rectangle '{}' (expected x,y,width,height)",
+                s
+            )),
+        }
+    }
+}
+
+// An output aspect ratio, written as width:height
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AspectRatio {
+    width: u32,
+    height: u32,
+}
+
+impl AspectRatio {
+    // Smallest size at exactly this ratio that fits an image of the given
+    // size. Sizes too large to represent saturate, for the caller to reject.
+    pub fn letterbox_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let factor = width
+            .div_ceil(self.width)
+            .max(height.div_ceil(self.height))
+            .max(1);
+        (
+            self.width.saturating_mul(factor),
+            self.height.saturating_mul(factor),
+        )
+    }
+}
+
+impl FromStr for AspectRatio {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (width, height) = s
+            .split_once(':')
+            .ok_or_else(|| format!("Invalid aspect ratio '{}' (expected width:height)", s))?;
+        let parse = |part: &str| {
+            part.trim()
+                .parse::<u32>()
+                .map_err(|e| format!("Invalid aspect ratio '{}': {}", s, e))
+        };
+        let (width, height) = (parse(width)?, parse(height)?);
+        if width == 0 || height == 0 {
+            return Err(format!(
+                "Invalid aspect ratio '{}' (both sides must be at least 1)",
+                s
+            ));
+        }
+
+        // Reduce 32:18 to 16:9 so sizes are only padded as much as needed
+        let divisor = gcd(width, height);
+        Ok(AspectRatio {
+            width: width / divisor,
+            height: height / divisor,
+        })
+    }
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+// Format a millisecond timestamp as MM:SS.mmm
+pub fn format_clock(timestamp: u128) -> String {
+    format!(
+        "{:02}:{:02}.{:03}",
+        timestamp / 60_000,
+        (timestamp / 1000) % 60,
+        timestamp % 1000
+    )
+}
+
+// Draw a text label in one corner of the image, using the terminal's
+// inverted default colors so it stands out from the session content
+pub fn draw_corner_label(
+    terminal: &VirtualTerminal,
+    img: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+    text: &str,
+    corner: Corner,
+    font_size: u8,
+) {
+    let cell_width = font_size as u32;
+    let cell_height = (font_size as f32 * 2.0) as u32;
+    let label_width = text.chars().count() as u32 * cell_width;
+
+    let x = match corner {
+        Corner::TopLeft | Corner::BottomLeft => 0,
+        Corner::TopRight | Corner::BottomRight => img.width().saturating_sub(label_width),
+    };
+    let y = match corner {
+        Corner::TopLeft | Corner::TopRight => 0,
+        Corner::BottomLeft | Corner::BottomRight => img.height().saturating_sub(cell_height),
+    };
+
+    let (fg, bg) = terminal.default_colors();
+    terminal.draw_text(img, text, (x, y), font_size, (bg, fg));
+}
+
+// Draw a box of text in one corner of the image. The box is blended over the
+// session content so what's underneath stays visible, and each line of the
+// text gets its own row.
+pub fn draw_callout(
+    terminal: &VirtualTerminal,
+    img: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+    text: &str,
+    corner: Corner,
+    font_size: u8,
+    opacity: f32,
+) {
+    let cell_width = font_size as u32;
+    let cell_height = (font_size as f32 * 2.0) as u32;
+    let columns = (img.width() / cell_width) as usize;
+
+    // One blank cell of padding either side, clipped to the image width
+    let lines: Vec<String> = text
+        .lines()
+        .map(|line| line.chars().take(columns.saturating_sub(2)).collect())
+        .collect();
+    let box_columns = lines
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0)
+        + 2;
+    let box_width = box_columns as u32 * cell_width;
+    let box_height = lines.len() as u32 * cell_height;
+
+    let x = match corner {
+        Corner::TopLeft | Corner::BottomLeft => 0,
+        Corner::TopRight | Corner::BottomRight => img.width().saturating_sub(box_width),
+    };
+    let y = match corner {
+        Corner::TopLeft | Corner::TopRight => 0,
+        Corner::BottomLeft | Corner::BottomRight => img.height().saturating_sub(box_height),
+    };
+
+    let (fg, bg) = terminal.default_colors();
+    for py in y..(y + box_height).min(img.height()) {
+        for px in x..(x + box_width).min(img.width()) {
+            let pixel = img.get_pixel_mut(px, py);
+            pixel[0] = blend(pixel[0], fg.r, opacity);
+            pixel[1] = blend(pixel[1], fg.g, opacity);
+            pixel[2] = blend(pixel[2], fg.b, opacity);
+        }
+    }
+
+    for (row, line) in lines.iter().enumerate() {
+        let origin = (x + cell_width, y + row as u32 * cell_height);
+        terminal.draw_glyphs(img, line, origin, font_size, bg);
+    }
+}
+
+// Return the image with a one-row strip added on top showing `text`
+// centered, like a window's title bar, drawn in the terminal's inverted
+// default colors
+pub fn add_caption_strip(
+    terminal: &VirtualTerminal,
+    img: &ImageBuffer<Rgb<u8>, Vec<u8>>,
+    text: &str,
+    font_size: u8,
+) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    let cell_height = (font_size as f32 * 2.0) as u32;
+    let (fg, bg) = terminal.default_colors();
+
+    let mut captioned = ImageBuffer::from_fn(img.width(), img.height() + cell_height, |x, y| {
+        if y < cell_height {
+            fg.to_rgb()
+        } else {
+            *img.get_pixel(x, y - cell_height)
+        }
+    });
+
+    // Titles too long for the strip are cut, keeping a column of margin
+    let columns = (img.width() / font_size as u32) as usize;
+    let caption: String = text.chars().take(columns.saturating_sub(2)).collect();
+    let caption_width = caption.chars().count() as u32 * font_size as u32;
+    let x = (img.width() - caption_width) / 2;
+    terminal.draw_text(&mut captioned, &caption, (x, 0), font_size, (bg, fg));
+    captioned
+}
+
+// Tint a rectangle of cells by blending the given color over them
+pub fn highlight_cells(
+    img: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+    cells: (usize, usize, usize, usize),
+    font_size: u8,
+    color: TermColor,
+    opacity: f32,
+) {
+    let (x, y, width, height) = cells;
+    let cell_width = font_size as u32;
+    let cell_height = (font_size as f32 * 2.0) as u32;
+
+    let px_start = x as u32 * cell_width;
+    let py_start = y as u32 * cell_height;
+    let px_end = (px_start + width as u32 * cell_width).min(img.width());
+    let py_end = (py_start + height as u32 * cell_height).min(img.height());
+
+    for py in py_start..py_end {
+        for px in px_start..px_end {
+            let pixel = img.get_pixel_mut(px, py);
+            pixel[0] = blend(pixel[0], color.r, opacity);
+            pixel[1] = blend(pixel[1], color.g, opacity);
+            pixel[2] = blend(pixel[2], color.b, opacity);
+        }
+    }
+}
+
+// Blend 1-pixel lines along every cell boundary, including the outer edges
+pub fn draw_grid(
+    img: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+    font_size: u8,
+    color: TermColor,
+    opacity: f32,
+) {
+    let cell_width = font_size as u32;
+    let cell_height = (font_size as f32 * 2.0) as u32;
+    let (width, height) = img.dimensions();
+
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let on_line =
+            x % cell_width == 0 || y % cell_height == 0 || x == width - 1 || y == height - 1;
+        if on_line {
+            pixel[0] = blend(pixel[0], color.r, opacity);
+            pixel[1] = blend(pixel[1], color.g, opacity);
+            pixel[2] = blend(pixel[2], color.b, opacity);
+        }
+    }
+}
+
+fn blend(base: u8, tint: u8, opacity: f32) -> u8 {
+    (base as f32 * (1.0 - opacity) + tint as f32 * opacity).round() as u8
+}
+
+// Move the image contents by a pixel offset, filling uncovered space
+pub fn shift_image(
+    img: &ImageBuffer<Rgb<u8>, Vec<u8>>,
+    offset: (i64, i64),
+    fill: TermColor,
+) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    let (dx, dy) = offset;
+    ImageBuffer::from_fn(img.width(), img.height(), |x, y| {
+        let src_x = x as i64 - dx;
+        let src_y = y as i64 - dy;
+
+        if src_x >= 0 && src_y >= 0 && src_x < img.width() as i64 && src_y < img.height() as i64 {
+            *img.get_pixel(src_x as u32, src_y as u32)
+        } else {
+            fill.to_rgb()
+        }
+    })
+}
+
+// Cut a rectangle of cells out of a rendered screen
+pub fn crop_cells(
+    img: &ImageBuffer<Rgb<u8>, Vec<u8>>,
+    rect: CellRect,
+    font_size: u8,
+) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    let cell_width = font_size as u32;
+    let cell_height = (font_size as f32 * 2.0) as u32;
+
+    image::imageops::crop_imm(
+        img,
+        rect.x as u32 * cell_width,
+        rect.y as u32 * cell_height,
+        rect.width as u32 * cell_width,
+        rect.height as u32 * cell_height,
+    )
+    .to_image()
+}
+
+// Center the image on a canvas of the given size, filling the bars around it
+pub fn letterbox(
+    img: &ImageBuffer<Rgb<u8>, Vec<u8>>,
+    size: (u32, u32),
+    fill: TermColor,
+) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    let (width, height) = size;
+    let mut canvas = ImageBuffer::from_pixel(width, height, fill.to_rgb());
+    image::imageops::replace(
+        &mut canvas,
+        img,
+        ((width - img.width()) / 2) as i64,
+        ((height - img.height()) / 2) as i64,
+    );
+    canvas
+}
+
+// Place the image at the top left of a canvas of the given size, for a
+// terminal smaller than the largest one in the recording
+pub fn pad(
+    img: &ImageBuffer<Rgb<u8>, Vec<u8>>,
+    size: (u32, u32),
+    fill: TermColor,
+) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    let (width, height) = size;
+    let mut canvas = ImageBuffer::from_pixel(width, height, fill.to_rgb());
+    image::imageops::replace(&mut canvas, img, 0, 0);
+    canvas
+}
+
+// Tint for cells changed by recent frames with --onion-skin, and how
+// strongly it shows on the frame that made the change and the ones after
+const ONION_COLOR: TermColor = TermColor {
+    r: 255,
+    g: 0,
+    b: 200,
+};
+const ONION_FADE: [f32; 3] = [0.45, 0.25, 0.1];
+
+// Remembers how many frames ago each cell last changed, so recent changes
+// can be tinted and fade out over the following frames
+pub struct OnionSkin {
+    previous: Vec<Vec<TermCell>>,
+    ages: Vec<Vec<usize>>,
+    // Whitespace markers only count as a change when they're drawn
+    show_whitespace: bool,
+}
+
+impl OnionSkin {
+    pub fn new(terminal: &VirtualTerminal, show_whitespace: bool) -> Self {
+        let previous = terminal.cells().to_vec();
+        let ages = previous
+            .iter()
+            .map(|row| vec![ONION_FADE.len(); row.len()])
+            .collect();
+        OnionSkin {
+            previous,
+            ages,
+            show_whitespace,
+        }
+    }
+
+    // Compare the terminal with the previous frame's cells. After a resize
+    // every cell counts as changed.
+    pub fn update(&mut self, terminal: &VirtualTerminal) {
+        let cells = terminal.cells();
+        if cells.len() != self.previous.len() || cells[0].len() != self.previous[0].len() {
+            self.previous = cells.to_vec();
+            self.ages = cells.iter().map(|row| vec![0; row.len()]).collect();
+            return;
+        }
+
+        for (y, row) in terminal.cells().iter().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                let age = &mut self.ages[y][x];
+                let previous = &self.previous[y][x];
+                let changed = cell.character != previous.character
+                    || cell.fg_color != previous.fg_color
+                    || cell.bg_color != previous.bg_color
+                    || cell.bold != previous.bold
+                    || cell.italic != previous.italic
+                    || cell.underline != previous.underline
+                    || cell.blink != previous.blink
+                    || cell.reverse != previous.reverse
+                    || cell.strikethrough != previous.strikethrough
+                    || (self.show_whitespace && cell.whitespace != previous.whitespace);
+                *age = if changed {
+                    0
+                } else {
+                    (*age + 1).min(ONION_FADE.len())
+                };
+            }
+        }
+        self.previous.clone_from_slice(terminal.cells());
+    }
+
+    pub fn draw(&self, img: &mut ImageBuffer<Rgb<u8>, Vec<u8>>, font_size: u8) {
+        for (y, row) in self.ages.iter().enumerate() {
+            for (x, &age) in row.iter().enumerate() {
+                if let Some(&opacity) = ONION_FADE.get(age) {
+                    highlight_cells(img, (x, y, 1, 1), font_size, ONION_COLOR, opacity);
+                }
+            }
+        }
+    }
+}
+
+// Space left around the window for its shadow, in pixels
+const SHADOW_PADDING: u32 = 24;
+// How far the shadow falls below and to the right of the window
+const SHADOW_OFFSET: (u32, u32) = (6, 8);
+// Box blur radius; three passes approximate a gaussian
+const SHADOW_BLUR_RADIUS: usize = 6;
+const SHADOW_OPACITY: f32 = 0.45;
+// Color of the canvas the window floats on
+pub const SHADOW_CANVAS: TermColor = TermColor {
+    r: 255,
+    g: 255,
+    b: 255,
+};
+
+// Soft drop shadow for a window of a fixed size. The blurred mask is worked
+// out once and reused for every frame.
+pub struct DropShadow {
+    width: u32,
+    height: u32,
+    // Shadow strength for each pixel of the padded canvas
+    mask: Vec<f32>,
+}
+
+impl DropShadow {
+    pub fn new(window_width: u32, window_height: u32) -> Self {
+        let width = window_width + 2 * SHADOW_PADDING;
+        let height = window_height + 2 * SHADOW_PADDING;
+        let mut mask = vec![0.0; (width * height) as usize];
+
+        let (dx, dy) = SHADOW_OFFSET;
+        for y in SHADOW_PADDING + dy..(SHADOW_PADDING + dy + window_height).min(height) {
+            for x in SHADOW_PADDING + dx..(SHADOW_PADDING + dx + window_width).min(width) {
+                mask[(y * width + x) as usize] = 1.0;
+            }
+        }
+
+        for _ in 0..3 {
+            box_blur(
+                &mut mask,
+                width as usize,
+                height as usize,
+                SHADOW_BLUR_RADIUS,
+            );
+        }
+
+        DropShadow {
+            width,
+            height,
+            mask,
+        }
+    }
+
+    // Size of the image with the padding added around the window
+    pub fn padded_size(window_width: u32, window_height: u32) -> (u32, u32) {
+        (
+            window_width + 2 * SHADOW_PADDING,
+            window_height + 2 * SHADOW_PADDING,
+        )
+    }
+
+    // Place the window on the canvas with the shadow behind it
+    pub fn apply(&self, img: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+        let pad = SHADOW_PADDING;
+        ImageBuffer::from_fn(self.width, self.height, |x, y| {
+            if x >= pad && y >= pad && x - pad < img.width() && y - pad < img.height() {
+                return *img.get_pixel(x - pad, y - pad);
+            }
+
+            let shade = self.mask[(y * self.width + x) as usize] * SHADOW_OPACITY;
+            Rgb([
+                blend(SHADOW_CANVAS.r, 0, shade),
+                blend(SHADOW_CANVAS.g, 0, shade),
+                blend(SHADOW_CANVAS.b, 0, shade),
+            ])
+        })
+    }
+}
+
+// Average each value with its neighbours within `radius`, first along rows
+// and then along columns
+fn box_blur(values: &mut [f32], width: usize, height: usize, radius: usize) {
+    let mut line = Vec::new();
+
+    for y in 0..height {
+        line.clear();
+        line.extend((0..width).map(|x| values[y * width + x]));
+        for (x, value) in blur_line(&line, radius).into_iter().enumerate() {
+            values[y * width + x] = value;
+        }
+    }
+
+    for x in 0..width {
+        line.clear();
+        line.extend((0..height).map(|y| values[y * width + x]));
+        for (y, value) in blur_line(&line, radius).into_iter().enumerate() {
+            values[y * width + x] = value;
+        }
+    }
+}
+
+fn blur_line(line: &[f32], radius: usize) -> Vec<f32> {
+    let window = (2 * radius + 1) as f32;
+    let mut sums = Vec::with_capacity(line.len() + 1);
+    sums.push(0.0);
+    for value in line {
+        sums.push(sums.last().unwrap() + value);
+    }
+
+    (0..line.len())
+        .map(|i| {
+            let start = i.saturating_sub(radius);
+            let end = (i + radius + 1).min(line.len());
+            (sums[end] - sums[start]) / window
+        })
+        .collect()
+}
+
+// Enlarge the image by a whole factor, turning each pixel into a square block
+pub fn scale_image(
+    img: &ImageBuffer<Rgb<u8>, Vec<u8>>,
+    factor: u32,
+) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    ImageBuffer::from_fn(img.width() * factor, img.height() * factor, |x, y| {
+        *img.get_pixel(x / factor, y / factor)
+    })
+}
+
+// Replace every pixel with its luminance (Rec. 709 weights)
+pub fn grayscale(img: &mut ImageBuffer<Rgb<u8>, Vec<u8>>) {
+    for pixel in img.pixels_mut() {
+        let [r, g, b] = pixel.0;
+        let luma = (0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32).round() as u8;
+        *pixel = Rgb([luma, luma, luma]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caption_strip_adds_a_row_above_the_terminal() {
+        let mut terminal = VirtualTerminal::new(12, 2, true);
+        terminal.process_content("\x1B]0;title\x07$ ls");
+        let img = terminal.render_to_image(8, 0);
+        let captioned = add_caption_strip(&terminal, &img, terminal.title().unwrap(), 8);
+
+        assert_eq!(captioned.dimensions(), (img.width(), img.height() + 16));
+        // The strip is drawn in the inverted default colors, and the
+        // terminal is moved down below it unchanged
+        let (fg, _) = terminal.default_colors();
+        assert_eq!(*captioned.get_pixel(0, 0), fg.to_rgb());
+        assert_eq!(captioned.get_pixel(20, 16 + 5), img.get_pixel(20, 5));
+    }
+}
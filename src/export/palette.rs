@@ -0,0 +1,180 @@
+use crate::utils;
+use image::{ImageBuffer, Rgb};
+use std::collections::HashMap;
+use std::io;
+
+// Number of colors taken from a brand image. Kept small so every frame is
+// drawn from the same handful of colors.
+pub const PALETTE_SIZE: usize = 16;
+
+// Most colors a GIF palette can hold
+pub const MAX_COLORS: usize = 256;
+
+// Upper bound on the pixels sampled from the source image
+const MAX_SAMPLES: usize = 100_000;
+
+// A fixed set of colors used as the GIF's global palette, with every pixel
+// snapped to its nearest entry
+pub struct Palette {
+    colors: Vec<[u8; 3]>,
+    // Nearest entry for each color seen so far; frames reuse few colors
+    nearest: HashMap<[u8; 3], u8>,
+}
+
+impl Palette {
+    // Extract a palette from an image file using median-cut quantization
+    pub fn from_image(file: &str, size: usize) -> io::Result<Self> {
+        let path = utils::get_absolute_path(file);
+        let img = image::open(&path)
+            .map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Failed to read palette image {}: {}", path.display(), e),
+                )
+            })?
+            .to_rgb8();
+
+        let step = (img.pixels().len() / MAX_SAMPLES).max(1);
+        let samples: Vec<[u8; 3]> = img.pixels().step_by(step).map(|p| p.0).collect();
+        if samples.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Palette image {} has no pixels", path.display()),
+            ));
+        }
+
+        Ok(Palette {
+            colors: median_cut(samples, size),
+            nearest: HashMap::new(),
+        })
+    }
+
+    // A palette shared by all the frames. Terminal output rarely uses more
+    // than a GIF can hold, in which case every color is kept exactly;
+    // otherwise the frames are sampled and quantized with median cut.
+    pub fn from_frames(frames: &[&ImageBuffer<Rgb<u8>, Vec<u8>>]) -> Self {
+        let mut counts: HashMap<[u8; 3], u64> = HashMap::new();
+        for img in frames {
+            for pixel in img.pixels() {
+                *counts.entry(pixel.0).or_insert(0) += 1;
+            }
+        }
+
+        let colors = if counts.len() <= MAX_COLORS {
+            let mut colors: Vec<([u8; 3], u64)> = counts.into_iter().collect();
+            colors.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+            colors.into_iter().map(|(color, _)| color).collect()
+        } else {
+            let total: usize = frames.iter().map(|img| img.pixels().len()).sum();
+            let step = (total / MAX_SAMPLES).max(1);
+            let samples: Vec<[u8; 3]> = frames
+                .iter()
+                .flat_map(|img| img.pixels())
+                .step_by(step)
+                .map(|p| p.0)
+                .collect();
+            median_cut(samples, MAX_COLORS)
+        };
+
+        Palette {
+            colors,
+            nearest: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    // Palette as the flat RGB triples the GIF encoder expects
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.colors.iter().flatten().copied().collect()
+    }
+
+    // Palette index for every pixel of the image
+    pub fn index_image(&mut self, img: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> Vec<u8> {
+        img.pixels().map(|p| self.index_of(p.0)).collect()
+    }
+
+    fn index_of(&mut self, color: [u8; 3]) -> u8 {
+        let colors = &self.colors;
+        *self.nearest.entry(color).or_insert_with(|| {
+            let distance = |entry: &[u8; 3]| -> u32 {
+                (0..3)
+                    .map(|i| {
+                        let d = entry[i] as i32 - color[i] as i32;
+                        (d * d) as u32
+                    })
+                    .sum()
+            };
+
+            colors
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, entry)| distance(entry))
+                .map_or(0, |(index, _)| index as u8)
+        })
+    }
+}
+
+// Repeatedly split the box of colors with the widest channel range at its
+// median until there are `size` boxes, then average each box
+fn median_cut(samples: Vec<[u8; 3]>, size: usize) -> Vec<[u8; 3]> {
+    let mut boxes = vec![samples];
+
+    while boxes.len() < size {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .map(|(index, colors)| {
+                let (channel, range) = widest_channel(colors);
+                (index, channel, range)
+            })
+            .filter(|&(_, _, range)| range > 0)
+            .max_by_key(|&(_, _, range)| range);
+
+        // Every box is a single color, so there's nothing left to split
+        let Some((index, channel, _)) = widest else {
+            break;
+        };
+
+        let mut colors = boxes.swap_remove(index);
+        colors.sort_unstable_by_key(|color| color[channel]);
+        let upper = colors.split_off(colors.len() / 2);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+
+    boxes.iter().map(|colors| average(colors)).collect()
+}
+
+fn widest_channel(colors: &[[u8; 3]]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let min = colors.iter().map(|c| c[channel]).min().unwrap_or(0);
+            let max = colors.iter().map(|c| c[channel]).max().unwrap_or(0);
+            (channel, max - min)
+        })
+        .max_by_key(|&(_, range)| range)
+        .unwrap_or((0, 0))
+}
+
+fn average(colors: &[[u8; 3]]) -> [u8; 3] {
+    let mut sum = [0u64; 3];
+    for color in colors {
+        for channel in 0..3 {
+            sum[channel] += color[channel] as u64;
+        }
+    }
+
+    let count = colors.len().max(1) as u64;
+    [
+        (sum[0] / count) as u8,
+        (sum[1] / count) as u8,
+        (sum[2] / count) as u8,
+    ]
+}
@@ -0,0 +1,49 @@
+use image::{ImageBuffer, Rgb};
+use png::{BitDepth, ColorType, Encoder, SrgbRenderingIntent};
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+// Save an image as a PNG tagged as sRGB, so color-managed viewers on
+// wide-gamut displays show the terminal colors as they were chosen rather
+// than stretching them to the display's gamut. Still-image exports should
+// be written through this rather than `ImageBuffer::save`, which leaves
+// the color space unstated.
+pub fn save_srgb_png(img: &ImageBuffer<Rgb<u8>, Vec<u8>>, path: &Path) -> io::Result<()> {
+    let file = File::create(path)?;
+    let mut encoder = Encoder::new(BufWriter::new(file), img.width(), img.height());
+    encoder.set_color(ColorType::Rgb);
+    encoder.set_depth(BitDepth::Eight);
+    encoder.set_source_srgb(SrgbRenderingIntent::Perceptual);
+
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer
+        .write_image_data(img.as_raw())
+        .map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils;
+    use std::fs;
+
+    #[test]
+    fn png_is_tagged_as_srgb() {
+        let path = utils::temp_path("srgb.png");
+        let img = ImageBuffer::from_pixel(4, 2, Rgb([30, 30, 30]));
+        save_srgb_png(&img, &path).unwrap();
+
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let reader = decoder.read_info().unwrap();
+        let info = reader.info();
+        assert_eq!(info.srgb, Some(SrgbRenderingIntent::Perceptual));
+        assert_eq!((info.width, info.height), (4, 2));
+        drop(reader);
+
+        let decoded = image::open(&path).unwrap().to_rgb8();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(decoded, img);
+    }
+}
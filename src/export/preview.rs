@@ -0,0 +1,84 @@
+use crate::export::png::save_srgb_png;
+use crate::terminal::VirtualTerminal;
+use crate::utils;
+use std::fs;
+use std::io;
+
+// Width of the widest color swatch, in cells. The screen is a little wider
+// so full swatch rows don't wrap.
+const SWATCH_WIDTH: usize = 72;
+const PREVIEW_WIDTH: usize = SWATCH_WIDTH + 2;
+const PREVIEW_HEIGHT: usize = 16;
+const PREVIEW_FONT_SIZE: u8 = 16;
+
+// The built-in themes, by the name their preview is saved under, and
+// whether each is the dark one
+const THEMES: [(&str, bool); 2] = [("light", false), ("dark", true)];
+
+// A screen exercising everything a theme affects: the default colors, the
+// 16 ANSI colors, the 256-color palette and true color
+fn sample_screen() -> String {
+    let mut screen = String::new();
+
+    screen.push_str("$ ls --color\r\n");
+    screen.push_str(
+        "README.md  \x1B[1;34msrc\x1B[0m  \x1B[1;32mbuild.sh\x1B[0m  \x1B[36mlink\x1B[0m  \
+         \x1B[31marchive.tar\x1B[0m  \x1B[35mlogo.png\x1B[0m  \x1B[33mMakefile\x1B[0m\r\n",
+    );
+    screen.push_str("\x1B[1mbold\x1B[0m  \x1B[3mitalic\x1B[0m  \x1B[4munderline\x1B[0m\r\n");
+    screen.push_str("\r\n");
+
+    // The 16 ANSI colors, then the 6x6x6 cube and the gray ramp
+    screen.push_str("256 colors\r\n");
+    for index in 0..16 {
+        screen.push_str(&format!("\x1B[48;5;{}m   ", index));
+    }
+    screen.push_str("\x1B[0m\r\n");
+    for red in 0..6 {
+        for index in 0..36 {
+            screen.push_str(&format!("\x1B[48;5;{}m  ", 16 + red * 36 + index));
+        }
+        screen.push_str("\x1B[0m\r\n");
+    }
+    for index in 232..256 {
+        screen.push_str(&format!("\x1B[48;5;{}m  ", index));
+    }
+    screen.push_str("\x1B[0m\r\n");
+
+    // Red through green to blue across the full width
+    screen.push_str("True color\r\n");
+    let last = (SWATCH_WIDTH - 1) as u32;
+    for x in 0..=last {
+        let t = x * 510 / last;
+        let (r, g, b) = if t <= 255 {
+            (255 - t, t, 0)
+        } else {
+            (0, 510 - t, t - 255)
+        };
+        screen.push_str(&format!("\x1B[48;2;{};{};{}m ", r, g, b));
+    }
+    screen.push_str("\x1B[0m\r\n");
+
+    screen.push_str("$ ");
+    screen
+}
+
+// Render the sample screen in every built-in theme, saving each as a PNG
+// named after the theme
+pub fn preview_themes(output_dir: &str) -> io::Result<()> {
+    let dir = utils::get_absolute_path(output_dir);
+    fs::create_dir_all(&dir)?;
+    let screen = sample_screen();
+
+    for (name, dark_theme) in THEMES {
+        let mut terminal = VirtualTerminal::new(PREVIEW_WIDTH, PREVIEW_HEIGHT, dark_theme);
+        terminal.process_content(&screen);
+
+        let path = dir.join(format!("{}.png", name));
+        save_srgb_png(&terminal.render_to_image(PREVIEW_FONT_SIZE, 0), &path)
+            .map_err(|e| io::Error::other(format!("Failed to save {}: {}", path.display(), e)))?;
+        println!("Saved the {} theme to {}", name, path.display());
+    }
+
+    Ok(())
+}
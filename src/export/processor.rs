@@ -0,0 +1,80 @@
+use crate::export::overlay::{self, Corner};
+use crate::terminal::{TermColor, VirtualTerminal};
+use image::{ImageBuffer, Rgb};
+
+// Hook for drawing onto each frame of an export, for overlays the renderer
+// doesn't have built in. Processors see the terminal window after rcrd's own
+// overlays and caption, before the shadow, letterbox and scaling are added,
+// and run in the order they're given.
+pub trait FrameProcessor {
+    // `frame_index` counts the recording frames drawn so far; a frame held
+    // over several blink beats is processed once per beat with the same
+    // index. `timestamp_ms` is the frame's time in the exported timeline.
+    fn process(
+        &mut self,
+        img: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+        frame_index: usize,
+        timestamp_ms: u128,
+    );
+}
+
+// A fixed text label in one corner of every frame
+pub struct Watermark {
+    text: String,
+    corner: Corner,
+    font_size: u8,
+    // Only used for its glyphs and default colors
+    terminal: VirtualTerminal,
+}
+
+impl Watermark {
+    pub fn new(text: &str, corner: Corner, font_size: u8, dark_theme: bool) -> Self {
+        Watermark {
+            text: text.to_string(),
+            corner,
+            font_size,
+            terminal: VirtualTerminal::new(1, 1, dark_theme),
+        }
+    }
+}
+
+impl FrameProcessor for Watermark {
+    fn process(&mut self, img: &mut ImageBuffer<Rgb<u8>, Vec<u8>>, _: usize, _: u128) {
+        overlay::draw_corner_label(&self.terminal, img, &self.text, self.corner, self.font_size);
+    }
+}
+
+// A bar along the bottom of the frame that fills up as the recording plays
+pub struct ProgressBar {
+    duration_ms: u128,
+    color: TermColor,
+    height: u32,
+}
+
+impl ProgressBar {
+    pub fn new(duration_ms: u128, color: TermColor, height: u32) -> Self {
+        ProgressBar {
+            duration_ms,
+            color,
+            height: height.max(1),
+        }
+    }
+}
+
+impl FrameProcessor for ProgressBar {
+    fn process(&mut self, img: &mut ImageBuffer<Rgb<u8>, Vec<u8>>, _: usize, timestamp_ms: u128) {
+        let progress = if self.duration_ms == 0 {
+            1.0
+        } else {
+            (timestamp_ms as f64 / self.duration_ms as f64).min(1.0)
+        };
+        let width = (img.width() as f64 * progress).round() as u32;
+        let top = img.height().saturating_sub(self.height);
+
+        for y in top..img.height() {
+            for x in 0..width {
+                img.put_pixel(x, y, self.color.to_rgb());
+            }
+        }
+    }
+}
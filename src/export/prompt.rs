@@ -0,0 +1,73 @@
+use crate::recording::RecordedFrame;
+use regex::{NoExpand, Regex};
+use std::env;
+use std::io;
+
+// Matches a line ending in a typical shell prompt character, including any
+// color escapes the prompt was drawn with
+pub const DEFAULT_PROMPT_PATTERN: &str = r"(?m)^[^\n]*[$#%>] $";
+
+// Replaces the recorded shell prompt with a clean templated one.
+//
+// The template can use {cwd}, {dir} (last component of cwd), {user} and
+// {host}, which are filled in from the environment at export time.
+#[derive(Clone, Debug)]
+pub struct PromptRewrite {
+    pattern: Regex,
+    prompt: String,
+}
+
+impl PromptRewrite {
+    pub fn new(template: &str, pattern: &str) -> io::Result<Self> {
+        let pattern = Regex::new(pattern).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid prompt pattern: {}", e),
+            )
+        })?;
+
+        Ok(PromptRewrite {
+            pattern,
+            prompt: expand_template(template),
+        })
+    }
+
+    pub fn apply(&self, frames: Vec<RecordedFrame>) -> Vec<RecordedFrame> {
+        frames
+            .into_iter()
+            .map(|frame| RecordedFrame {
+                content: self
+                    .pattern
+                    .replace_all(&frame.content, NoExpand(&self.prompt))
+                    .into_owned(),
+                raw: None,
+                ..frame
+            })
+            .collect()
+    }
+}
+
+fn expand_template(template: &str) -> String {
+    let cwd = env::current_dir()
+        .map(|dir| dir.display().to_string())
+        .unwrap_or_default();
+    let dir = env::current_dir()
+        .ok()
+        .and_then(|dir| {
+            dir.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        })
+        .unwrap_or_default();
+    let user = env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_default();
+    let host = env::var("HOSTNAME")
+        .or_else(|_| env::var("COMPUTERNAME"))
+        .unwrap_or_default();
+
+    template
+        .replace("{cwd}", &cwd)
+        .replace("{dir}", &dir)
+        .replace("{user}", &user)
+        .replace("{host}", &host)
+}
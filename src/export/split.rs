@@ -0,0 +1,204 @@
+use crate::export::gif::{self, ExportOptions};
+use crate::recording::annotations::{self, Chapter};
+use crate::recording::{RecordedFrame, Recording};
+use crate::utils;
+use std::io;
+use std::str::FromStr;
+
+// Where to cut a recording into separate GIFs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SplitMode {
+    // Start a new chunk once this many seconds have elapsed in the current one
+    Duration(f32),
+    // Put this many frames in each chunk
+    Frames(usize),
+    // Start a new chunk at each chapter in the chapters sidecar
+    Chapters,
+}
+
+impl FromStr for SplitMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid split mode '{}' (expected duration:<seconds>, frames:<count> or chapters)",
+                s
+            )
+        };
+
+        if s == "chapters" || s == "marker" {
+            return Ok(SplitMode::Chapters);
+        }
+
+        match s.split_once(':') {
+            Some(("duration", value)) => match value.parse::<f32>() {
+                Ok(seconds) if seconds > 0.0 => Ok(SplitMode::Duration(seconds)),
+                _ => Err(invalid()),
+            },
+            Some(("frames", value)) => match value.parse::<usize>() {
+                Ok(count) if count > 0 => Ok(SplitMode::Frames(count)),
+                _ => Err(invalid()),
+            },
+            _ => Err(invalid()),
+        }
+    }
+}
+
+// Export a recording as a numbered series of GIFs, e.g. prefix_01.gif
+pub fn split_to_gifs(
+    input_file: &str,
+    output_prefix: &str,
+    mode: SplitMode,
+    options: &ExportOptions,
+) -> io::Result<()> {
+    let input_path = utils::get_absolute_path(input_file);
+    println!("Loading recording from {}", input_path.display());
+
+    let mut recording = Recording::open_with_limits(&input_path, options.limits)?;
+    let frames = recording.take_output();
+    if frames.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "No frames found in recording file",
+        ));
+    }
+
+    let chapters = match mode {
+        SplitMode::Chapters => {
+            let chapters = annotations::load_chapters(&input_path)?;
+            if chapters.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Splitting by chapters needs chapters to split at (see 'rcrd chapters')",
+                ));
+            }
+            chapters
+        }
+        _ => Vec::new(),
+    };
+
+    let boundaries = chunk_starts(&frames, mode, &chapters);
+    println!(
+        "Splitting {} frames into {} parts",
+        frames.len(),
+        boundaries.len()
+    );
+
+    let options = &ExportOptions {
+        speed: options.speed.or(recording.metadata.default_speed),
+        width: options.width.or(recording.metadata.width),
+        height: options.height.or(recording.metadata.height),
+        ..options.clone()
+    };
+
+    for (index, &start) in boundaries.iter().enumerate() {
+        let end = boundaries.get(index + 1).copied().unwrap_or(frames.len());
+        let output_file = format!("{}_{:02}.gif", output_prefix, index + 1);
+
+        println!("\nPart {}: frames {}..{}", index + 1, start, end);
+        gif::export_frames_to_gif(Recording::slice(&frames, start..end), &output_file, options)?;
+    }
+
+    Ok(())
+}
+
+// Index of the first frame of each chunk
+fn chunk_starts(frames: &[RecordedFrame], mode: SplitMode, chapters: &[Chapter]) -> Vec<usize> {
+    match mode {
+        SplitMode::Frames(count) => (0..frames.len()).step_by(count).collect(),
+        SplitMode::Duration(seconds) => {
+            let chunk_ms = (seconds * 1000.0) as u128;
+            let mut starts = vec![0];
+            let mut chunk_start = frames[0].timestamp;
+
+            for (index, frame) in frames.iter().enumerate().skip(1) {
+                if frame.timestamp.saturating_sub(chunk_start) >= chunk_ms {
+                    starts.push(index);
+                    chunk_start = frame.timestamp;
+                }
+            }
+            starts
+        }
+        SplitMode::Chapters => {
+            // The first chunk holds anything before the first chapter, and
+            // chapters without frames of their own are merged into the next
+            let mut starts = vec![0];
+            starts.extend(
+                chapters
+                    .iter()
+                    .map(|chapter| chapter.frame_range(frames).start)
+                    .filter(|&start| start > 0 && start < frames.len()),
+            );
+            starts.sort_unstable();
+            starts.dedup();
+            starts
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames_at(times: &[u128]) -> Vec<RecordedFrame> {
+        times
+            .iter()
+            .map(|&t| RecordedFrame::new(format!("{}\r\n", t), t))
+            .collect()
+    }
+
+    fn chapter(name: &str, start_ms: u128, end_ms: u128) -> Chapter {
+        Chapter {
+            name: name.to_string(),
+            start_ms,
+            end_ms,
+        }
+    }
+
+    #[test]
+    fn parses_split_modes() {
+        assert_eq!("duration:2.5".parse(), Ok(SplitMode::Duration(2.5)));
+        assert_eq!("frames:3".parse(), Ok(SplitMode::Frames(3)));
+        assert_eq!("chapters".parse(), Ok(SplitMode::Chapters));
+        assert_eq!("marker".parse(), Ok(SplitMode::Chapters));
+        for invalid in ["frames:0", "duration:-1", "chapters:2", "seconds:5"] {
+            assert!(invalid.parse::<SplitMode>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn chunks_by_frame_count() {
+        let frames = frames_at(&[0, 100, 200, 300, 400]);
+        assert_eq!(
+            chunk_starts(&frames, SplitMode::Frames(2), &[]),
+            vec![0, 2, 4]
+        );
+    }
+
+    #[test]
+    fn chunks_by_duration_from_each_chunk_start() {
+        let frames = frames_at(&[500, 900, 1600, 2000, 2700, 5000]);
+        assert_eq!(
+            chunk_starts(&frames, SplitMode::Duration(1.0), &[]),
+            vec![0, 2, 4, 5]
+        );
+    }
+
+    #[test]
+    fn chunks_at_chapter_starts() {
+        let frames = frames_at(&[0, 1000, 2000, 3000, 4000]);
+        let chapters = [
+            chapter("setup", 0, 1500),
+            chapter("build", 1500, 3500),
+            // No frames of its own, so it joins the next chapter
+            chapter("empty", 3500, 3600),
+            chapter("test", 3600, 5000),
+            chapter("after the end", 9000, 9500),
+        ];
+        assert_eq!(
+            chunk_starts(&frames, SplitMode::Chapters, &chapters),
+            vec![0, 2, 4]
+        );
+    }
+}
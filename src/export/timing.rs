@@ -0,0 +1,156 @@
+use crate::recording::RecordedFrame;
+
+// Retime the frames so every section between the given boundaries lasts
+// `duration_ms`, stretching or squeezing each one evenly. The start and the
+// last frame are always boundaries; ones outside the recording are ignored.
+pub fn pace_sections(
+    frames: Vec<RecordedFrame>,
+    boundaries: &[u128],
+    duration_ms: u128,
+) -> Vec<RecordedFrame> {
+    let end = frames.last().map_or(0, |f| f.timestamp);
+    let mut points: Vec<u128> = boundaries
+        .iter()
+        .copied()
+        .filter(|&b| b > 0 && b < end)
+        .collect();
+    points.push(0);
+    points.push(end);
+    points.sort_unstable();
+    points.dedup();
+
+    if points.len() < 2 {
+        return frames;
+    }
+
+    frames
+        .into_iter()
+        .map(|frame| {
+            let section = points
+                .partition_point(|&p| p <= frame.timestamp)
+                .saturating_sub(1)
+                .min(points.len() - 2);
+            let (start, stop) = (points[section], points[section + 1]);
+            let into = frame.timestamp.saturating_sub(start) * duration_ms / (stop - start);
+            RecordedFrame {
+                timestamp: section as u128 * duration_ms + into,
+                ..frame
+            }
+        })
+        .collect()
+}
+
+// Shorten a gap between frames to at most `max_idle` seconds, so a session
+// left idle doesn't drag when played back. The cast saturates, so a huge
+// limit leaves every gap as it is.
+pub fn cap_idle(gap_ms: u128, max_idle: Option<f32>) -> u128 {
+    match max_idle {
+        Some(seconds) => gap_ms.min((seconds * 1000.0) as u128),
+        None => gap_ms,
+    }
+}
+
+// Stretch gaps between frames so every frame stays up for at least `min_ms`.
+// With `preserve_duration`, the time added is won back by shortening later
+// gaps that are longer than the minimum, so the total length is unchanged
+// wherever there is enough idle time to absorb it.
+pub fn enforce_min_visible(
+    frames: Vec<RecordedFrame>,
+    min_ms: u128,
+    preserve_duration: bool,
+) -> Vec<RecordedFrame> {
+    let mut adjusted = Vec::with_capacity(frames.len());
+    let mut debt: u128 = 0;
+    let mut previous: Option<(u128, u128)> = None;
+
+    for frame in frames {
+        let timestamp = match previous {
+            None => frame.timestamp,
+            Some((original, retimed)) => {
+                let gap = frame.timestamp.saturating_sub(original);
+                let new_gap = if gap < min_ms {
+                    debt += min_ms - gap;
+                    min_ms
+                } else if preserve_duration && debt > 0 {
+                    let cut = debt.min(gap - min_ms);
+                    debt -= cut;
+                    gap - cut
+                } else {
+                    gap
+                };
+                retimed + new_gap
+            }
+        };
+
+        previous = Some((frame.timestamp, timestamp));
+        adjusted.push(RecordedFrame { timestamp, ..frame });
+    }
+
+    adjusted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames_at(times: &[u128]) -> Vec<RecordedFrame> {
+        times
+            .iter()
+            .map(|&t| RecordedFrame::new(t.to_string(), t))
+            .collect()
+    }
+
+    fn times(frames: &[RecordedFrame]) -> Vec<u128> {
+        frames.iter().map(|f| f.timestamp).collect()
+    }
+
+    #[test]
+    fn sections_are_paced_to_the_same_duration() {
+        let frames = frames_at(&[0, 500, 1000, 1500, 3000]);
+        let paced = pace_sections(frames, &[1000], 2000);
+
+        // The first second is stretched, the next two squeezed, and the
+        // last frame ends the last section
+        assert_eq!(times(&paced), vec![0, 1000, 2000, 2500, 4000]);
+    }
+
+    #[test]
+    fn boundaries_outside_the_recording_are_ignored() {
+        let frames = frames_at(&[0, 1000, 4000]);
+        let paced = pace_sections(frames, &[0, 4000, 9000], 2000);
+
+        assert_eq!(times(&paced), vec![0, 500, 2000]);
+    }
+
+    #[test]
+    fn cap_idle_shortens_only_long_gaps() {
+        assert_eq!(cap_idle(60_000, Some(2.0)), 2000);
+        assert_eq!(cap_idle(500, Some(2.0)), 500);
+        assert_eq!(cap_idle(60_000, None), 60_000);
+    }
+
+    #[test]
+    fn short_gaps_are_stretched_to_the_minimum() {
+        let frames = frames_at(&[0, 50, 100, 2000]);
+        let adjusted = enforce_min_visible(frames, 200, false);
+
+        assert_eq!(times(&adjusted), vec![0, 200, 400, 2300]);
+    }
+
+    #[test]
+    fn preserve_duration_pays_back_from_long_gaps() {
+        let frames = frames_at(&[0, 50, 100, 2000]);
+        let adjusted = enforce_min_visible(frames, 200, true);
+
+        assert_eq!(times(&adjusted), vec![0, 200, 400, 2000]);
+    }
+
+    #[test]
+    fn preserve_duration_never_cuts_below_the_minimum() {
+        let frames = frames_at(&[0, 50, 300, 1000]);
+        let adjusted = enforce_min_visible(frames, 200, true);
+
+        // The 250ms gap can only give back 50ms; the rest comes from the last
+        assert_eq!(times(&adjusted), vec![0, 200, 400, 1000]);
+    }
+}
@@ -0,0 +1,191 @@
+use crate::export::gif::{check_glyph_fit, ExportOptions};
+use crate::export::{overlay, timing};
+use crate::recording::Recording;
+use crate::terminal::font::OutlineFont;
+use crate::terminal::VirtualTerminal;
+use crate::utils;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+// How long the last screen stays up at the end of the video
+const FINAL_HOLD_MS: u128 = 1000;
+
+// Render a recording to a video by piping raw RGB frames to ffmpeg. Frames
+// are recorded at irregular times, so each one is repeated until the next
+// is due at the fixed frame rate. The codec follows the output extension:
+// VP9 for .webm and H.264 for anything else.
+pub fn export_to_video(
+    input_file: &str,
+    output_file: &str,
+    options: &ExportOptions,
+    fps: u32,
+) -> io::Result<()> {
+    let input_path = utils::get_absolute_path(input_file);
+    let output_path = utils::get_absolute_path(output_file);
+
+    println!("Loading recording from {}", input_path.display());
+
+    let mut recording = Recording::open_with_limits(&input_path, options.limits)?;
+    let resizes = recording.resizes();
+    let frames = recording.take_output();
+    let speed = options
+        .speed
+        .or(recording.metadata.default_speed)
+        .unwrap_or(1.0);
+    let (width, height) = ExportOptions {
+        width: options.width.or(recording.metadata.width),
+        height: options.height.or(recording.metadata.height),
+        ..options.clone()
+    }
+    .size();
+
+    if frames.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "No frames found in recording file",
+        ));
+    }
+    println!("Loaded {} frames", frames.len());
+
+    let mut terminal = VirtualTerminal::new(width as usize, height as usize, options.dark_theme);
+    terminal.set_default_colors(options.fg_color, options.bg_color);
+    if let Some(path) = &options.font {
+        terminal.set_font(OutlineFont::load(path)?);
+    }
+    terminal.set_show_whitespace(options.show_whitespace);
+    check_glyph_fit(&terminal, options.font_size);
+
+    // As with GIFs, frames are drawn at the largest size the terminal
+    // reaches. Most encoders need even dimensions, so round up.
+    let (grid_width, grid_height) = resizes.iter().fold((width, height), |(w, h), r| {
+        (w.max(r.width), h.max(r.height))
+    });
+    let cell_width = options.font_size as u32;
+    let cell_height = (options.font_size as f32 * 2.0) as u32;
+    let scale = options.scale.max(1) as u32;
+    let frame_size = (
+        (grid_width as u32 * cell_width * scale).next_multiple_of(2),
+        (grid_height as u32 * cell_height * scale).next_multiple_of(2),
+    );
+
+    let mut ffmpeg = spawn_ffmpeg(&output_path, frame_size, fps)?;
+    let mut stdin = ffmpeg.stdin.take().expect("ffmpeg stdin is piped");
+
+    println!(
+        "Creating video with dimensions {}x{} at {} fps",
+        frame_size.0, frame_size.1, fps
+    );
+
+    // Output time of each frame, after capping idle time and applying speed
+    let mut output_ms: Vec<u128> = Vec::with_capacity(frames.len());
+    let mut elapsed = 0;
+    for (i, frame) in frames.iter().enumerate() {
+        if i > 0 {
+            let gap = timing::cap_idle(
+                frame.timestamp.saturating_sub(frames[i - 1].timestamp),
+                options.max_idle,
+            );
+            elapsed += (gap as f32 / speed) as u128;
+        }
+        output_ms.push(elapsed);
+    }
+
+    let mut resizes = resizes.iter().peekable();
+    let mut ticks_written: u128 = 0;
+    let result = (|| -> io::Result<()> {
+        for (i, frame) in frames.iter().enumerate() {
+            while let Some(resize) = resizes.next_if(|r| r.timestamp <= frame.timestamp) {
+                terminal.resize(resize.width as usize, resize.height as usize);
+            }
+            terminal.process_content(&frame.content);
+
+            // Every tick before the next frame is due shows this screen
+            let until = output_ms
+                .get(i + 1)
+                .copied()
+                .unwrap_or(output_ms[i] + FINAL_HOLD_MS);
+            let ticks_due = (until * fps as u128).div_ceil(1000);
+            if ticks_due <= ticks_written {
+                continue;
+            }
+
+            let now = ticks_written * 1000 / fps as u128;
+            let mut img = terminal.render_to_image(options.font_size, now);
+            terminal.draw_cursor(&mut img, options.font_size, options.cursor);
+            if scale > 1 {
+                img = overlay::scale_image(&img, scale);
+            }
+            if img.dimensions() != frame_size {
+                img = overlay::pad(&img, frame_size, terminal.default_colors().1);
+            }
+
+            for _ in ticks_written..ticks_due {
+                stdin.write_all(img.as_raw())?;
+            }
+            ticks_written = ticks_due;
+
+            if (i + 1) % 10 == 0 {
+                print!(".");
+                io::stdout().flush()?;
+            }
+        }
+        Ok(())
+    })();
+
+    // Closing stdin tells ffmpeg the stream is over
+    drop(stdin);
+    let status = ffmpeg.wait()?;
+
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "ffmpeg failed to encode {} ({})",
+            output_path.display(),
+            status
+        )));
+    }
+    result?;
+
+    println!("\nVideo successfully created at {}", output_path.display());
+    println!("Frames written: {}", ticks_written);
+
+    Ok(())
+}
+
+fn spawn_ffmpeg(
+    output_path: &Path,
+    (width, height): (u32, u32),
+    fps: u32,
+) -> io::Result<std::process::Child> {
+    let webm = output_path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("webm"));
+    let codec: &[&str] = if webm {
+        &["-c:v", "libvpx-vp9", "-b:v", "0", "-crf", "32"]
+    } else {
+        &["-c:v", "libx264", "-movflags", "+faststart"]
+    };
+
+    Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error"])
+        .args(["-f", "rawvideo", "-pix_fmt", "rgb24"])
+        .args(["-s", &format!("{}x{}", width, height)])
+        .args(["-r", &fps.to_string()])
+        .args(["-i", "-"])
+        .args(codec)
+        .args(["-pix_fmt", "yuv420p"])
+        .arg(output_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .map_err(|e| {
+            if e.kind() == io::ErrorKind::NotFound {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "ffmpeg was not found; install it and make sure it's on your PATH to export videos",
+                )
+            } else {
+                io::Error::new(e.kind(), format!("Failed to start ffmpeg: {}", e))
+            }
+        })
+}
@@ -0,0 +1,6 @@
+pub mod cli;
+pub mod config;
+pub mod export;
+pub mod recording;
+pub mod terminal;
+mod utils;
@@ -1,20 +1,90 @@
-mod cli;
-mod export;
-mod recording;
-mod terminal;
-mod utils;
-
-use cli::Cli;
-use recording::{playback, recorder};
+use rcrd::cli::{AnnotateAction, Cli};
+use rcrd::config::{self, Config};
+use rcrd::export;
+use rcrd::export::gif::{Enhancements, ExportOptions};
+use rcrd::export::overlay::Corner;
+use rcrd::export::processor::{FrameProcessor, Watermark};
+use rcrd::export::prompt::{PromptRewrite, DEFAULT_PROMPT_PATTERN};
+use rcrd::recording::playback::{self, PlaybackOptions};
+use rcrd::recording::recorder::RecordOptions;
+use rcrd::recording::{
+    self, annotations, commands, diff, escapes, fifo, recorder, trim, typing, Metadata,
+    RecordingFormat,
+};
 use std::io;
+use std::path::Path;
+use std::process;
 use structopt::StructOpt;
 
 fn main() -> io::Result<()> {
     let opt = Cli::from_args();
+    let config = Config::load()?;
 
     match opt {
-        Cli::Record { output } => recorder::record_session(&output)?,
-        Cli::Play { file, speed } => playback::play_session(&file, speed)?,
+        Cli::Record {
+            output,
+            fifo,
+            format,
+            default_speed,
+            status,
+            append,
+            compact_json,
+            pretty_json,
+            command,
+            shell,
+        } => {
+            let output = append
+                .clone()
+                .or(output)
+                .or(config.record.output)
+                .unwrap_or_else(|| "demo.json".to_string());
+            let format = format
+                .or(config.record.format)
+                .unwrap_or_else(|| RecordingFormat::from_path(Path::new(&output)));
+
+            let options = RecordOptions {
+                format,
+                metadata: Metadata {
+                    default_speed,
+                    ..Metadata::default()
+                },
+                show_status: status,
+                append: append.is_some(),
+                compact_json: config::switch(compact_json, pretty_json, config.record.compact_json),
+                command,
+                shell: shell.or(config.record.shell),
+            };
+
+            match fifo {
+                Some(fifo) => fifo::record_fifo(&output, &fifo, &options)?,
+                None => recorder::record_session(&output, &options)?,
+            }
+        }
+        Cli::Play {
+            file,
+            speed,
+            reverse,
+            show_time,
+            interactive,
+            max_idle,
+            repair,
+            width,
+            height,
+            limits,
+        } => playback::play_session(
+            &file,
+            &PlaybackOptions {
+                speed,
+                reverse,
+                repair,
+                width,
+                height,
+                max_idle,
+                show_time,
+                interactive,
+                limits: limits.limits(),
+            },
+        )?,
         Cli::Export {
             input,
             output,
@@ -23,9 +93,291 @@ fn main() -> io::Result<()> {
             height,
             font_size,
             dark_theme,
-        } => export::gif::export_to_gif(
-            &input, &output, speed, width, height, font_size, dark_theme,
+            light_theme,
+            bg_color,
+            fg_color,
+            clock,
+            no_clock,
+            clock_position,
+            watermark,
+            max_frames,
+            reverse,
+            prompt,
+            prompt_pattern,
+            center,
+            no_center,
+            embed_source,
+            no_embed_source,
+            ascii_only,
+            no_ascii_only,
+            reveal,
+            min_visible,
+            section_duration,
+            max_idle,
+            preserve_duration,
+            chapter,
+            title_caption,
+            on_change_only,
+            strict,
+            grayscale,
+            no_grayscale,
+            with_callouts,
+            palette_from,
+            optimize,
+            no_optimize,
+            loop_count,
+            font,
+            scale,
+            no_enhance,
+            no_intro,
+            no_intro_prompt,
+            no_time_offset,
+            no_outro,
+            crop,
+            shadow,
+            trim_trailing,
+            show_whitespace,
+            cursor,
+            grid,
+            onion_skin,
+            aspect,
+            bar_color,
+            limits,
+        } => {
+            let defaults = config.export;
+            let clock = config::switch(clock, no_clock, defaults.clock);
+            let prompt_pattern = prompt_pattern
+                .or(defaults.prompt_pattern)
+                .unwrap_or_else(|| DEFAULT_PROMPT_PATTERN.to_string());
+
+            let output = output.unwrap_or_else(|| match &chapter {
+                Some(name) => annotations::chapter_file_name(name),
+                None => "output.gif".to_string(),
+            });
+            let font_size = font_size.or(defaults.font_size).unwrap_or(16);
+            let dark_theme = config::switch(dark_theme, light_theme, defaults.dark_theme);
+
+            let mut processors: Vec<Box<dyn FrameProcessor>> = Vec::new();
+            if let Some(text) = watermark {
+                processors.push(Box::new(Watermark::new(
+                    &text,
+                    Corner::BottomLeft,
+                    font_size,
+                    dark_theme,
+                )));
+            }
+
+            export::gif::export_to_gif_with(
+                &input,
+                &output,
+                &ExportOptions {
+                    speed: speed.or(defaults.speed),
+                    width: width.or(defaults.width),
+                    height: height.or(defaults.height),
+                    font_size,
+                    dark_theme,
+                    fg_color,
+                    bg_color,
+                    clock: if clock {
+                        Some(
+                            clock_position
+                                .or(defaults.clock_position)
+                                .unwrap_or(Corner::BottomRight),
+                        )
+                    } else {
+                        None
+                    },
+                    max_frames,
+                    reverse,
+                    prompt: prompt
+                        .or(defaults.prompt)
+                        .map(|template| PromptRewrite::new(&template, &prompt_pattern))
+                        .transpose()?,
+                    center: config::switch(center, no_center, defaults.center),
+                    embed_source: config::switch(
+                        embed_source,
+                        no_embed_source,
+                        defaults.embed_source,
+                    ),
+                    ascii_only: config::switch(ascii_only, no_ascii_only, defaults.ascii_only),
+                    reveal,
+                    min_visible,
+                    section_duration,
+                    max_idle,
+                    preserve_duration,
+                    chapter,
+                    title_caption,
+                    on_change_only,
+                    strict,
+                    grayscale: config::switch(grayscale, no_grayscale, defaults.grayscale),
+                    with_callouts,
+                    palette_from: palette_from.or(defaults.palette_from),
+                    optimize: config::switch(optimize, no_optimize, defaults.optimize),
+                    loop_count: loop_count.or(defaults.loop_count).unwrap_or(0),
+                    font: font.or(defaults.font),
+                    scale: scale.or(defaults.scale).unwrap_or(1),
+                    enhance: Enhancements {
+                        intro: !(no_enhance || no_intro),
+                        intro_prompt: !(no_enhance || no_intro_prompt),
+                        time_offset: !(no_enhance || no_time_offset),
+                        outro: !(no_enhance || no_outro),
+                    },
+                    crop,
+                    shadow,
+                    trim_trailing,
+                    show_whitespace,
+                    cursor,
+                    grid,
+                    onion_skin,
+                    aspect,
+                    bar_color,
+                    limits: limits.limits(),
+                },
+                &mut processors,
+            )?
+        }
+        Cli::Cast {
+            input,
+            output,
+            width,
+            height,
+        } => export::cast::export_to_cast(&input, &output, width, height)?,
+        Cli::Video {
+            input,
+            output,
+            fps,
+            speed,
+            width,
+            height,
+            font_size,
+            dark_theme,
+            light_theme,
+            font,
+            scale,
+            max_idle,
+            cursor,
+            limits,
+        } => {
+            let defaults = config.export;
+            export::video::export_to_video(
+                &input,
+                &output,
+                &ExportOptions {
+                    speed: speed.or(defaults.speed),
+                    width: width.or(defaults.width),
+                    height: height.or(defaults.height),
+                    font_size: font_size.or(defaults.font_size).unwrap_or(16),
+                    dark_theme: config::switch(dark_theme, light_theme, defaults.dark_theme),
+                    font: font.or(defaults.font),
+                    scale: scale.or(defaults.scale).unwrap_or(1),
+                    max_idle,
+                    cursor,
+                    limits: limits.limits(),
+                    ..ExportOptions::default()
+                },
+                fps,
+            )?
+        }
+        Cli::Extract { gif, output } => export::embed::extract_to_file(&gif, &output)?,
+        Cli::ExtractCommands { input, output } => commands::extract_commands(&input, &output)?,
+        Cli::Type {
+            text,
+            output,
+            file,
+            delay,
+            hold,
+            width,
+            height,
+            font_size,
+            dark_theme,
+        } => {
+            let text = match file {
+                Some(file) => typing::load_text(&file)?,
+                None => text.unwrap_or_default(),
+            };
+            let frames = typing::typing_frames(&text, delay as u128, hold as u128);
+
+            export::gif::export_frames_to_gif(
+                frames,
+                &output,
+                &ExportOptions {
+                    width: Some(width),
+                    height: Some(height),
+                    font_size,
+                    dark_theme,
+                    ..ExportOptions::default()
+                },
+            )?
+        }
+        Cli::Split {
+            input,
+            output_prefix,
+            by,
+            speed,
+            width,
+            height,
+            font_size,
+            dark_theme,
+            limits,
+        } => export::split::split_to_gifs(
+            &input,
+            &output_prefix,
+            by,
+            &ExportOptions {
+                speed,
+                width,
+                height,
+                font_size,
+                dark_theme,
+                limits: limits.limits(),
+                ..ExportOptions::default()
+            },
         )?,
+        Cli::Chapters { file } => annotations::list_chapters(&file)?,
+        Cli::Annotate { file, action } => match action {
+            AnnotateAction::Add {
+                start,
+                end,
+                text,
+                position,
+            } => annotations::add_callout(
+                &file,
+                annotations::Callout {
+                    start_ms: start,
+                    end_ms: end,
+                    text: text.replace("\\n", "\n"),
+                    position,
+                },
+            )?,
+            AnnotateAction::List => annotations::list_callouts(&file)?,
+            AnnotateAction::Remove { number } => annotations::remove_callout(&file, number)?,
+        },
+        Cli::ThemePreview { output_dir } => export::preview::preview_themes(&output_dir)?,
+        Cli::Escapes { file } => escapes::list_escapes(&file)?,
+        Cli::Info { file, time_format } => recording::print_info(&file, time_format)?,
+        Cli::Edit {
+            file,
+            default_speed,
+            clear_default_speed,
+        } => recording::edit_metadata(&file, default_speed, clear_default_speed)?,
+        Cli::Trim {
+            file,
+            output,
+            start,
+            end,
+            trailing,
+        } => trim::trim_file(&file, output.as_deref(), start, end, trailing)?,
+        Cli::Diff {
+            a,
+            b,
+            color,
+            width,
+            height,
+        } => {
+            if !diff::diff_recordings(&a, &b, width as usize, height as usize, color)? {
+                process::exit(1);
+            }
+        }
     }
 
     Ok(())
@@ -0,0 +1,224 @@
+use crate::export::overlay::{self, Corner};
+use crate::recording::{RecordedFrame, Recording};
+use crate::utils;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+// A rectangle of cells to call attention to during part of a recording.
+// Times are milliseconds from the start of the recording, and the region
+// is given in terminal cells.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Highlight {
+    pub start_ms: u128,
+    pub end_ms: u128,
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Highlight {
+    pub fn is_active(&self, timestamp: u128) -> bool {
+        timestamp >= self.start_ms && timestamp < self.end_ms
+    }
+}
+
+// A box of explanatory text shown over a recording for a while, stored in
+// the recording itself. Times are milliseconds from the start of the
+// recording.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Callout {
+    pub start_ms: u128,
+    pub end_ms: u128,
+    pub text: String,
+    #[serde(default = "default_callout_position")]
+    pub position: Corner,
+}
+
+impl Callout {
+    pub fn is_active(&self, timestamp: u128) -> bool {
+        timestamp >= self.start_ms && timestamp < self.end_ms
+    }
+}
+
+fn default_callout_position() -> Corner {
+    Corner::BottomLeft
+}
+
+// Add a callout to a recording, keeping them ordered by start time
+pub fn add_callout(file: &str, callout: Callout) -> io::Result<()> {
+    if callout.end_ms <= callout.start_ms {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Callout must end after it starts",
+        ));
+    }
+
+    let path = utils::get_absolute_path(file);
+    let mut recording = Recording::open(&path)?;
+    let callouts = &mut recording.metadata.annotations;
+    let index = callouts.partition_point(|c| c.start_ms <= callout.start_ms);
+    callouts.insert(index, callout);
+    recording.save_in_place(&path)?;
+
+    println!("Added callout {}", index + 1);
+    Ok(())
+}
+
+// Print the callouts stored in a recording, numbered for remove_callout
+pub fn list_callouts(file: &str) -> io::Result<()> {
+    let recording = Recording::open(&utils::get_absolute_path(file))?;
+    let callouts = &recording.metadata.annotations;
+
+    if callouts.is_empty() {
+        println!(
+            "No callouts defined (add them with rcrd annotate {} add)",
+            file
+        );
+        return Ok(());
+    }
+
+    for (index, callout) in callouts.iter().enumerate() {
+        println!(
+            "{:>3}  {} - {}  {:<12} {}",
+            index + 1,
+            overlay::format_clock(callout.start_ms),
+            overlay::format_clock(callout.end_ms),
+            callout.position,
+            callout.text.replace('\n', " / ")
+        );
+    }
+    Ok(())
+}
+
+// Remove the callout with the given number, as shown by list_callouts
+pub fn remove_callout(file: &str, number: usize) -> io::Result<()> {
+    let path = utils::get_absolute_path(file);
+    let mut recording = Recording::open(&path)?;
+    let callouts = &mut recording.metadata.annotations;
+
+    if number == 0 || number > callouts.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "No callout {} in {} ({} defined)",
+                number,
+                file,
+                callouts.len()
+            ),
+        ));
+    }
+
+    let removed = callouts.remove(number - 1);
+    recording.save_in_place(&path)?;
+
+    println!(
+        "Removed callout {}: {}",
+        number,
+        removed.text.replace('\n', " / ")
+    );
+    Ok(())
+}
+
+// Sidecar file holding highlights for a recording, e.g. demo.highlights.json
+pub fn highlights_path(recording_path: &Path) -> PathBuf {
+    recording_path.with_extension("highlights.json")
+}
+
+// Load the highlights sidecar for a recording, if there is one
+pub fn load_highlights(recording_path: &Path) -> io::Result<Vec<Highlight>> {
+    load_sidecar(&highlights_path(recording_path))
+}
+
+// A named section of a recording, in milliseconds from its start
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Chapter {
+    pub name: String,
+    pub start_ms: u128,
+    pub end_ms: u128,
+}
+
+impl Chapter {
+    // Index range of the frames that fall inside the chapter
+    pub fn frame_range(&self, frames: &[RecordedFrame]) -> Range<usize> {
+        let start = frames.partition_point(|f| f.timestamp < self.start_ms);
+        let end = frames.partition_point(|f| f.timestamp < self.end_ms);
+        start..end.max(start)
+    }
+}
+
+// Default GIF name for an exported chapter, e.g. "Build & Test" becomes
+// build-test.gif
+pub fn chapter_file_name(name: &str) -> String {
+    let slug: String = name
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+
+    if slug.is_empty() {
+        "chapter.gif".to_string()
+    } else {
+        format!("{}.gif", slug)
+    }
+}
+
+// Sidecar file holding chapters for a recording, e.g. demo.chapters.json
+pub fn chapters_path(recording_path: &Path) -> PathBuf {
+    recording_path.with_extension("chapters.json")
+}
+
+// Load the chapters sidecar for a recording, if there is one
+pub fn load_chapters(recording_path: &Path) -> io::Result<Vec<Chapter>> {
+    load_sidecar(&chapters_path(recording_path))
+}
+
+// Print the chapters defined for a recording
+pub fn list_chapters(file: &str) -> io::Result<()> {
+    let recording_path = utils::get_absolute_path(file);
+    let chapters = load_chapters(&recording_path)?;
+
+    if chapters.is_empty() {
+        println!(
+            "No chapters defined (add them to {})",
+            chapters_path(&recording_path).display()
+        );
+        return Ok(());
+    }
+
+    for chapter in &chapters {
+        println!(
+            "{:<24} {} - {}",
+            chapter.name,
+            overlay::format_clock(chapter.start_ms),
+            overlay::format_clock(chapter.end_ms)
+        );
+    }
+    Ok(())
+}
+
+// A missing sidecar is the same as an empty one
+fn load_sidecar<T: DeserializeOwned>(path: &Path) -> io::Result<Vec<T>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let contents = fs::read_to_string(path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Failed to read {}: {}", path.display(), e),
+        )
+    })?;
+
+    serde_json::from_str(&contents).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid JSON in {}: {}", path.display(), e),
+        )
+    })
+}
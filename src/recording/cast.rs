@@ -0,0 +1,155 @@
+use super::{FrameOrigin, Metadata, RecordedFrame};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+// First line of an asciinema cast. Only the fields rcrd has a place for are
+// read; the rest (title, theme, ...) are ignored.
+#[derive(Deserialize)]
+struct CastHeader {
+    version: u32,
+    width: u16,
+    height: u16,
+    timestamp: Option<u64>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+}
+
+// Whether a path is named as an asciinema cast
+pub fn is_cast_path(path: &Path) -> bool {
+    path.extension().and_then(|ext| ext.to_str()) == Some("cast")
+}
+
+// Parse an asciinema cast, or return None if the contents don't start with a
+// cast header. Output, input and resize events become frames of the same
+// origin; other events, such as markers, are skipped. As with JSON Lines
+// recordings, a bad final line is dropped with a warning.
+pub fn parse_cast(
+    contents: &str,
+    path: &Path,
+) -> io::Result<Option<(Metadata, Vec<RecordedFrame>)>> {
+    let lines: Vec<&str> = contents.lines().collect();
+    let header = match lines
+        .first()
+        .map(|line| serde_json::from_str::<CastHeader>(line))
+    {
+        Some(Ok(header)) => header,
+        _ => return Ok(None),
+    };
+
+    // The header of an rcrd JSON Lines recording has the same version, width
+    // and height keys, so it only counts as a cast by the file's extension or
+    // by its events being arrays rather than rcrd's frame objects
+    let first_event = lines
+        .iter()
+        .skip(1)
+        .map(|line| line.trim_start())
+        .find(|line| !line.is_empty());
+    let is_cast =
+        is_cast_path(path) || first_event.map_or(header.version == 2, |line| line.starts_with('['));
+    if !is_cast {
+        return Ok(None);
+    }
+
+    if header.version != 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Unsupported asciinema cast version {} in {} (only version 2 can be read)",
+                header.version,
+                path.display()
+            ),
+        ));
+    }
+
+    let mut metadata = Metadata {
+        width: Some(header.width).filter(|&w| w > 0),
+        height: Some(header.height).filter(|&h| h > 0),
+        created: header.timestamp,
+        ..Metadata::default()
+    };
+    for (name, value) in header.env {
+        if name == "SHELL" {
+            metadata.shell = Some(value);
+        } else {
+            metadata.env.insert(name, value);
+        }
+    }
+
+    let mut frames = Vec::with_capacity(lines.len());
+    for (index, line) in lines.iter().enumerate().skip(1) {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (time, code, data) = match serde_json::from_str::<(f64, String, String)>(line) {
+            Ok(event) => event,
+            Err(_) if index + 1 == lines.len() => {
+                eprintln!(
+                    "Warning: Ignoring incomplete last line of {}",
+                    path.display()
+                );
+                continue;
+            }
+            Err(e) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Invalid asciinema event on line {} of {}: {}",
+                        index + 1,
+                        path.display(),
+                        e
+                    ),
+                ))
+            }
+        };
+
+        let origin = match code.as_str() {
+            "o" => FrameOrigin::Output,
+            "i" => FrameOrigin::Input,
+            "r" => FrameOrigin::Resize,
+            _ => continue,
+        };
+        frames.push(RecordedFrame {
+            content: data,
+            timestamp: (time.max(0.0) * 1000.0).round() as u128,
+            origin,
+            raw: None,
+        });
+    }
+
+    Ok(Some((metadata, frames)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cast_by_its_events() {
+        let contents = "{\"version\":2,\"width\":40,\"height\":10}\n[0.5,\"o\",\"hi\"]\n";
+        let (metadata, frames) = parse_cast(contents, Path::new("session.txt"))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(metadata.width, Some(40));
+        assert_eq!(frames[0].content, "hi");
+        assert_eq!(frames[0].timestamp, 500);
+    }
+
+    #[test]
+    fn leaves_rcrd_jsonl_header_alone() {
+        let contents =
+            "{\"version\":1,\"width\":80,\"height\":24}\n{\"content\":\"hi\",\"timestamp\":0}\n";
+        assert!(parse_cast(contents, Path::new("demo.jsonl"))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn rejects_other_cast_versions() {
+        let contents = "{\"version\":1,\"width\":80,\"height\":24}\n[0.0,\"o\",\"hi\"]\n";
+        assert!(parse_cast(contents, Path::new("old.cast")).is_err());
+    }
+}
@@ -0,0 +1,240 @@
+use crate::recording::Recording;
+use crate::terminal::parser::{self, Token};
+use crate::utils;
+use std::fs;
+use std::io;
+
+// A line being typed at a readline-style prompt
+#[derive(Default)]
+struct LineEditor {
+    line: Vec<char>,
+    cursor: usize,
+}
+
+impl LineEditor {
+    fn insert(&mut self, c: char) {
+        self.line.insert(self.cursor, c);
+        self.cursor += 1;
+    }
+
+    fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.line.remove(self.cursor);
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.cursor < self.line.len() {
+            self.line.remove(self.cursor);
+        }
+    }
+
+    // Start of the word before the cursor, skipping any spaces first
+    fn word_start(&self) -> usize {
+        let mut start = self.cursor;
+        while start > 0 && self.line[start - 1] == ' ' {
+            start -= 1;
+        }
+        while start > 0 && self.line[start - 1] != ' ' {
+            start -= 1;
+        }
+        start
+    }
+
+    fn word_end(&self) -> usize {
+        let mut end = self.cursor;
+        while end < self.line.len() && self.line[end] == ' ' {
+            end += 1;
+        }
+        while end < self.line.len() && self.line[end] != ' ' {
+            end += 1;
+        }
+        end
+    }
+
+    fn kill_word(&mut self) {
+        let start = self.word_start();
+        self.line.drain(start..self.cursor);
+        self.cursor = start;
+    }
+
+    fn take(&mut self) -> String {
+        self.cursor = 0;
+        self.line.drain(..).collect()
+    }
+
+    // Keys sent as CSI or SS3 sequences, e.g. the arrow keys
+    fn key(&mut self, params: &str, command: char) {
+        match (params, command) {
+            (_, 'D') => self.cursor = self.cursor.saturating_sub(1),
+            (_, 'C') => self.cursor = (self.cursor + 1).min(self.line.len()),
+            (_, 'H') | ("1" | "7", '~') => self.cursor = 0,
+            (_, 'F') | ("4" | "8", '~') => self.cursor = self.line.len(),
+            ("3", '~') => self.delete(),
+            _ => {}
+        }
+    }
+}
+
+// Replay the keystrokes in the input track through a line editor and
+// return each line that was submitted. History recall and tab completion
+// are filled in by the shell, so their results can't be recovered here.
+fn typed_commands(input: &str) -> Vec<String> {
+    let mut editor = LineEditor::default();
+    let mut commands = Vec::new();
+    // ESC O is followed by the key in application cursor mode
+    let mut ss3 = false;
+
+    for (token, _) in parser::tokenize(input) {
+        if ss3 {
+            ss3 = false;
+            if let Token::Text(c) = token {
+                editor.key("", c);
+                continue;
+            }
+        }
+
+        match token {
+            Token::Text(c) => editor.insert(c),
+            Token::Control('\r' | '\n') => {
+                let line = editor.take();
+                if !line.trim().is_empty() {
+                    commands.push(line);
+                }
+            }
+            Token::Control('\x7F' | '\x08') => editor.backspace(),
+            Token::Control('\x01') => editor.cursor = 0,
+            Token::Control('\x05') => editor.cursor = editor.line.len(),
+            Token::Control('\x02') => editor.key("", 'D'),
+            Token::Control('\x06') => editor.key("", 'C'),
+            Token::Control('\x04') => editor.delete(),
+            Token::Control('\x0B') => editor.line.truncate(editor.cursor),
+            Token::Control('\x15') => {
+                editor.line.drain(..editor.cursor);
+                editor.cursor = 0;
+            }
+            Token::Control('\x17') | Token::Esc("\x7F") => editor.kill_word(),
+            // ^C abandons the line
+            Token::Control('\x03') => {
+                editor.take();
+            }
+            Token::Csi { params, command } => editor.key(params, command),
+            Token::Esc("O") => ss3 = true,
+            Token::Esc("b") => editor.cursor = editor.word_start(),
+            Token::Esc("f") => editor.cursor = editor.word_end(),
+            _ => {}
+        }
+    }
+
+    commands
+}
+
+// Write everything typed during a recording out as a shell script
+pub fn extract_commands(file: &str, output_file: &str) -> io::Result<()> {
+    let recording = Recording::open(&utils::get_absolute_path(file))?;
+    let input: String = recording
+        .input_events()
+        .map(|f| f.content.as_str())
+        .collect();
+
+    if input.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "No input events in {}; record it again to capture keystrokes",
+                file
+            ),
+        ));
+    }
+
+    let commands = typed_commands(&input);
+    let mut script = String::from("#!/bin/sh\n");
+    for command in &commands {
+        script.push_str(command);
+        script.push('\n');
+    }
+
+    let output_path = utils::get_absolute_path(output_file);
+    fs::write(&output_path, script)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&output_path, fs::Permissions::from_mode(0o755))?;
+    }
+
+    println!(
+        "Extracted {} commands to {}",
+        commands.len(),
+        output_path.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn submitted_lines_become_commands() {
+        assert_eq!(
+            typed_commands("ls -la\r\r  \recho hi\n"),
+            vec!["ls -la", "echo hi"]
+        );
+    }
+
+    #[test]
+    fn backspace_removes_the_character_before_the_cursor() {
+        assert_eq!(typed_commands("lss\x7F\rpwdd\x08\r"), vec!["ls", "pwd"]);
+    }
+
+    #[test]
+    fn ctrl_u_kills_to_the_start_of_the_line() {
+        assert_eq!(typed_commands("rm -rf /\x15echo safe\r"), vec!["echo safe"]);
+        // Only what's before the cursor goes
+        assert_eq!(typed_commands("abc\x1B[D\x15\r"), vec!["c"]);
+    }
+
+    #[test]
+    fn ctrl_w_and_alt_backspace_kill_the_previous_word() {
+        assert_eq!(
+            typed_commands("git comit  \x17commit\r"),
+            vec!["git commit"]
+        );
+        assert_eq!(typed_commands("cat foo\x1B\x7Fbar\r"), vec!["cat bar"]);
+    }
+
+    #[test]
+    fn arrow_keys_move_the_cursor() {
+        assert_eq!(typed_commands("cho\x1B[D\x1B[D\x1B[De\r"), vec!["echo"]);
+        assert_eq!(typed_commands("ac\x1B[D\x1B[C\x1B[Cb\r"), vec!["acb"]);
+        assert_eq!(typed_commands("s\x1B[Hl\x1B[F -a\r"), vec!["ls -a"]);
+    }
+
+    #[test]
+    fn ss3_arrow_keys_move_the_cursor() {
+        assert_eq!(typed_commands("cho\x1BOD\x1BOD\x1BODe\r"), vec!["echo"]);
+        assert_eq!(typed_commands("s\x1BOHl\x1BOF -a\r"), vec!["ls -a"]);
+    }
+
+    #[test]
+    fn alt_b_and_alt_f_move_by_words() {
+        assert_eq!(
+            typed_commands("world\x1Bb\x1Bbhello \x1Bf!\r"),
+            vec!["hello world!"]
+        );
+    }
+
+    #[test]
+    fn emacs_editing_keys() {
+        assert_eq!(typed_commands("xabc\x01\x04\x05d\r"), vec!["abcd"]);
+        assert_eq!(typed_commands("abcd\x02\x02\x0B\r"), vec!["ab"]);
+        assert_eq!(typed_commands("ac\x02\x06\x02b\r"), vec!["abc"]);
+    }
+
+    #[test]
+    fn ctrl_c_abandons_the_line() {
+        assert_eq!(typed_commands("oops\x03ls\r"), vec!["ls"]);
+    }
+}
@@ -0,0 +1,203 @@
+use crate::export::timing;
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// How often a wait between frames checks for key presses
+const TICK: Duration = Duration::from_millis(20);
+
+// Each + or - press multiplies or divides the speed by this much
+const SPEED_STEP: f32 = 1.5;
+
+// Range the speed can be adjusted within
+const MIN_SPEED: f32 = 0.1;
+const MAX_SPEED: f32 = 16.0;
+
+// Keyboard controls for interactive playback. A background thread reads keys
+// from the terminal and updates the shared state, which the playback loop
+// checks while it waits between frames:
+//
+//   space  pause or resume
+//   right  show the next frame while paused
+//   + -    speed playback up or slow it down
+//   q      stop playback (as does Ctrl+C)
+pub struct Controls {
+    state: Arc<State>,
+    // Restores the terminal when playback ends, however it ends
+    _raw_mode: RawMode,
+}
+
+struct State {
+    paused: AtomicBool,
+    quit: AtomicBool,
+    // Frames to step forward by while paused
+    steps: AtomicUsize,
+    // Speed multiplier, stored as the bits of an f32
+    speed: AtomicU32,
+}
+
+impl Controls {
+    // Take keys from the terminal on stdin, starting at the given speed
+    pub fn start(speed: f32) -> io::Result<Self> {
+        let raw_mode = RawMode::enable()?;
+        let state = Arc::new(State {
+            paused: AtomicBool::new(false),
+            quit: AtomicBool::new(false),
+            steps: AtomicUsize::new(0),
+            speed: AtomicU32::new(speed.clamp(MIN_SPEED, MAX_SPEED).to_bits()),
+        });
+
+        // The thread stays blocked on stdin after playback ends, and goes
+        // away when rcrd exits
+        let keys = state.clone();
+        thread::spawn(move || read_keys(&keys));
+
+        Ok(Controls {
+            state,
+            _raw_mode: raw_mode,
+        })
+    }
+
+    pub fn quit(&self) -> bool {
+        self.state.quit.load(Ordering::SeqCst)
+    }
+
+    // Wait out `delay_ms` of recording time at the current speed, holding
+    // while paused. Returns false if the user quit in the meantime.
+    pub fn wait(&self, delay_ms: u128, max_idle: Option<f32>) -> bool {
+        let mut remaining = timing::cap_idle(delay_ms, max_idle) as f32;
+
+        loop {
+            if self.quit() {
+                return false;
+            }
+            if self.state.paused.load(Ordering::SeqCst) {
+                let step =
+                    self.state
+                        .steps
+                        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |steps| {
+                            steps.checked_sub(1)
+                        });
+                if step.is_ok() {
+                    return true;
+                }
+            } else if remaining <= 0.0 {
+                return true;
+            } else {
+                remaining -= TICK.as_millis() as f32 * self.state.speed();
+            }
+            thread::sleep(TICK);
+        }
+    }
+}
+
+impl State {
+    fn speed(&self) -> f32 {
+        f32::from_bits(self.speed.load(Ordering::SeqCst))
+    }
+
+    fn change_speed(&self, factor: f32) {
+        let speed = (self.speed() * factor).clamp(MIN_SPEED, MAX_SPEED);
+        self.speed.store(speed.to_bits(), Ordering::SeqCst);
+    }
+}
+
+fn read_keys(state: &State) {
+    let mut stdin = io::stdin();
+    let mut buffer = [0; 16];
+
+    while !state.quit.load(Ordering::SeqCst) {
+        let n = match stdin.read(&mut buffer) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => n,
+        };
+
+        // Arrow keys arrive as escape sequences, anything else one byte at a
+        // time unless keys are pressed faster than they're read
+        let mut keys = &buffer[..n];
+        while let Some(&key) = keys.first() {
+            match keys {
+                [0x1B, b'[', b'C', ..] | [0x1B, b'O', b'C', ..] => {
+                    if state.paused.load(Ordering::SeqCst) {
+                        state.steps.fetch_add(1, Ordering::SeqCst);
+                    }
+                    keys = &keys[3..];
+                    continue;
+                }
+                _ => {}
+            }
+
+            match key {
+                b' ' => {
+                    state.paused.fetch_xor(true, Ordering::SeqCst);
+                    state.steps.store(0, Ordering::SeqCst);
+                }
+                b'+' | b'=' => state.change_speed(SPEED_STEP),
+                b'-' | b'_' => state.change_speed(1.0 / SPEED_STEP),
+                // Ctrl+C doesn't raise SIGINT in raw mode
+                b'q' | b'Q' | 0x03 => state.quit.store(true, Ordering::SeqCst),
+                _ => {}
+            }
+            keys = &keys[1..];
+        }
+    }
+}
+
+// Terminal on stdin switched to reading single keys without echoing them,
+// switched back when dropped. Output processing is left alone so the
+// recording still prints as it would otherwise.
+#[cfg(unix)]
+struct RawMode {
+    original: libc::termios,
+}
+
+#[cfg(unix)]
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        if unsafe { libc::isatty(libc::STDIN_FILENO) } != 1 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Interactive playback needs a terminal on stdin",
+            ));
+        }
+
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(RawMode { original })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+struct RawMode;
+
+#[cfg(not(unix))]
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Interactive playback is only supported on Unix",
+        ))
+    }
+}
@@ -0,0 +1,55 @@
+use crate::recording::Recording;
+use crate::terminal::VirtualTerminal;
+use crate::utils;
+use similar::TextDiff;
+use std::io;
+
+// Replay two recordings and print a unified diff of their final screens.
+// Returns whether the screens match. With `compare_style`, colors and text
+// attributes have to match as well as the text.
+pub fn diff_recordings(
+    file_a: &str,
+    file_b: &str,
+    width: usize,
+    height: usize,
+    compare_style: bool,
+) -> io::Result<bool> {
+    let screen_a = final_screen(file_a, width, height, compare_style)?;
+    let screen_b = final_screen(file_b, width, height, compare_style)?;
+
+    if screen_a == screen_b {
+        println!("Final screens are identical");
+        return Ok(true);
+    }
+
+    let diff = TextDiff::from_lines(&screen_a, &screen_b);
+    print!(
+        "{}",
+        diff.unified_diff().context_radius(3).header(file_a, file_b)
+    );
+    Ok(false)
+}
+
+fn final_screen(
+    file: &str,
+    width: usize,
+    height: usize,
+    compare_style: bool,
+) -> io::Result<String> {
+    let frames = Recording::load(&utils::get_absolute_path(file))?;
+
+    let mut terminal = VirtualTerminal::new(width, height, true);
+    for frame in &frames {
+        terminal.process_content(&frame.content);
+    }
+
+    // Trailing newline keeps the last line from being reported as changed
+    // just because it has no line ending
+    let mut screen = if compare_style {
+        terminal.styled_screen_text()
+    } else {
+        terminal.screen_text()
+    };
+    screen.push('\n');
+    Ok(screen)
+}
@@ -0,0 +1,84 @@
+use crate::recording::Recording;
+use crate::terminal::parser::{self, Token};
+use crate::terminal::VirtualTerminal;
+use crate::utils;
+use std::collections::HashMap;
+use std::io;
+
+// Occurrences of one kind of escape sequence
+struct Tally {
+    count: usize,
+    // Occurrences the virtual terminal handled without complaint
+    supported: usize,
+    // First raw sequence seen, as an example
+    example: String,
+}
+
+// Print every kind of escape sequence in a recording with how often it
+// occurs and whether the virtual terminal supports it, most frequent first.
+// The frames are joined before scanning, so sequences split across frames
+// are counted once.
+pub fn list_escapes(file: &str) -> io::Result<()> {
+    let frames = Recording::load(&utils::get_absolute_path(file))?;
+    let content: String = frames.iter().map(|f| f.content.as_str()).collect();
+
+    // Each sequence is fed to a strict terminal on its own to see if it
+    // gets reported as unsupported
+    let mut terminal = VirtualTerminal::new(80, 24, true);
+    terminal.set_strict(true);
+    let mut tallies: HashMap<String, Tally> = HashMap::new();
+
+    for (token, raw) in parser::tokenize(&content) {
+        let Some(name) = parser::sequence_name(&token) else {
+            continue;
+        };
+
+        let unhandled_before = unhandled_total(&terminal);
+        if !matches!(token, Token::Incomplete(_)) {
+            terminal.process_content(raw);
+        }
+        let supported = unhandled_total(&terminal) == unhandled_before
+            && !matches!(token, Token::Incomplete(_));
+
+        let tally = tallies.entry(name).or_insert_with(|| Tally {
+            count: 0,
+            supported: 0,
+            example: raw.escape_debug().to_string(),
+        });
+        tally.count += 1;
+        if supported {
+            tally.supported += 1;
+        }
+    }
+
+    if tallies.is_empty() {
+        println!("No escape sequences in {}", file);
+        return Ok(());
+    }
+
+    let mut tallies: Vec<(String, Tally)> = tallies.into_iter().collect();
+    tallies.sort_by(|(name_a, a), (name_b, b)| b.count.cmp(&a.count).then(name_a.cmp(name_b)));
+
+    println!(
+        "{:>8}  {:<9} {:<16} EXAMPLE",
+        "COUNT", "SUPPORTED", "SEQUENCE"
+    );
+    for (name, tally) in &tallies {
+        let supported = if tally.supported == tally.count {
+            "yes"
+        } else if tally.supported == 0 {
+            "no"
+        } else {
+            "partly"
+        };
+        println!(
+            "{:>8}  {:<9} {:<16} {}",
+            tally.count, supported, name, tally.example
+        );
+    }
+    Ok(())
+}
+
+fn unhandled_total(terminal: &VirtualTerminal) -> usize {
+    terminal.unhandled_sequences().values().sum()
+}
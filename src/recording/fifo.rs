@@ -0,0 +1,176 @@
+use crate::recording::recorder::RecordOptions;
+use std::io;
+#[cfg(unix)]
+use std::time::Duration;
+
+// How long to wait for a new writer after the previous one disconnects
+#[cfg(unix)]
+const RECONNECT_GRACE: Duration = Duration::from_secs(5);
+
+// Poll interval while the FIFO has no data available
+#[cfg(unix)]
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+// Record everything written to a named pipe (Unix only).
+//
+// The FIFO is created if it doesn't exist yet. Recording starts once the
+// first writer connects and keeps going across writer reconnects, so a
+// producer can close and reopen the pipe without ending the session. The
+// recording ends when no writer has been connected for a few seconds, or
+// when the user presses Ctrl+C.
+#[cfg(unix)]
+pub fn record_fifo(output_file: &str, fifo_file: &str, options: &RecordOptions) -> io::Result<()> {
+    use crate::recording::recorder::{
+        finish_with_gif, install_interrupt_handler, open_recording, spawn_status_thread,
+    };
+    use crate::recording::status::StatusLine;
+    use crate::recording::utf8::Utf8Stream;
+    use crate::utils;
+    use std::fs::OpenOptions;
+    use std::io::Read;
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+
+    let output_path = utils::get_absolute_path(output_file);
+    let fifo_path = utils::get_absolute_path(fifo_file);
+
+    create_fifo(&fifo_path)?;
+
+    println!("Recording from named pipe: {}", fifo_path.display());
+    println!("Press Ctrl+C to end the recording");
+    println!("Output will be saved to: {}", output_path.display());
+
+    let recording = Arc::new(Mutex::new(open_recording(&output_path, options)?));
+    let running = Arc::new(AtomicBool::new(true));
+    let status = Arc::new(StatusLine::new(options.show_status));
+
+    install_interrupt_handler(
+        recording.clone(),
+        output_path.clone(),
+        output_file.to_string(),
+        running.clone(),
+    );
+
+    // Opening non-blocking lets us tell "no writer" (EOF) apart from
+    // "writer connected but idle" (WouldBlock) without reopening the pipe
+    let mut fifo = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(&fifo_path)?;
+
+    let mut buffer = [0; 1024];
+    let mut stream = Utf8Stream::new();
+    let mut writer_connected = false;
+    let mut disconnected_at: Option<Instant> = None;
+
+    println!("Waiting for a writer to connect...");
+
+    let status_handle = options
+        .show_status
+        .then(|| spawn_status_thread(&recording, &status, &running));
+
+    while running.load(Ordering::SeqCst) {
+        match fifo.read(&mut buffer) {
+            Ok(0) => {
+                if writer_connected {
+                    status.clear();
+                    println!("\nWriter disconnected, waiting for it to reconnect...");
+                    writer_connected = false;
+                    disconnected_at = Some(Instant::now());
+                }
+
+                if let Some(since) = disconnected_at {
+                    if since.elapsed() >= RECONNECT_GRACE {
+                        status.clear();
+                        println!("No writer reconnected, ending recording...");
+                        break;
+                    }
+                }
+
+                std::thread::sleep(POLL_INTERVAL);
+            }
+            Ok(n) => {
+                if !writer_connected {
+                    writer_connected = true;
+                    disconnected_at = None;
+                }
+
+                let (content, raw) = stream.decode(&buffer[0..n]);
+                status.echo(&content, false);
+                recording.lock().unwrap().add_output((content, raw));
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                writer_connected = true;
+                disconnected_at = None;
+                std::thread::sleep(POLL_INTERVAL);
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => {
+                eprintln!("Error reading from FIFO: {}", e);
+                break;
+            }
+        }
+    }
+
+    recording.lock().unwrap().add_output(stream.finish());
+
+    running.store(false, Ordering::SeqCst);
+    if let Some(handle) = status_handle {
+        handle.thread().unpark();
+        let _ = handle.join();
+    }
+    status.clear();
+
+    let final_recording = recording.lock().unwrap().clone();
+    final_recording.save(&output_path)?;
+
+    finish_with_gif(&output_path, output_file);
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn record_fifo(
+    _output_file: &str,
+    _fifo_file: &str,
+    _options: &RecordOptions,
+) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Recording from a FIFO is only supported on Unix",
+    ))
+}
+
+#[cfg(unix)]
+fn create_fifo(path: &std::path::Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::FileTypeExt;
+
+    if let Ok(metadata) = std::fs::metadata(path) {
+        if metadata.file_type().is_fifo() {
+            return Ok(());
+        }
+
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists and is not a FIFO", path.display()),
+        ));
+    }
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+        let err = io::Error::last_os_error();
+        return Err(io::Error::new(
+            err.kind(),
+            format!("Failed to create FIFO {}: {}", path.display(), err),
+        ));
+    }
+
+    println!("Created FIFO: {}", path.display());
+    Ok(())
+}
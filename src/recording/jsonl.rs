@@ -0,0 +1,113 @@
+use super::{Metadata, RecordedFrame};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// Buffered frames are pushed to disk at least this often
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+// Appends frames to a JSON Lines recording as they are captured, so a
+// crash loses at most the last flush interval
+pub struct FrameLog {
+    writer: BufWriter<File>,
+    last_flush: Instant,
+}
+
+impl FrameLog {
+    // Start a new file, beginning with the metadata header line, followed by
+    // any frames already captured
+    pub fn create(path: &Path, metadata: &Metadata, frames: &[RecordedFrame]) -> io::Result<Self> {
+        let file = File::create(path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Failed to create {}: {}", path.display(), e),
+            )
+        })?;
+
+        let mut writer = BufWriter::new(file);
+        write_lines(&mut writer, metadata, frames)?;
+
+        Ok(FrameLog {
+            writer,
+            last_flush: Instant::now(),
+        })
+    }
+
+    pub fn append(&mut self, frame: &RecordedFrame) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, frame)?;
+        self.writer.write_all(b"\n")?;
+
+        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.last_flush = Instant::now();
+        self.writer.flush()
+    }
+}
+
+// One compact JSON object per line, after the metadata header
+pub fn write_lines<W: Write>(
+    mut writer: W,
+    metadata: &Metadata,
+    frames: &[RecordedFrame],
+) -> io::Result<()> {
+    serde_json::to_writer(&mut writer, &metadata.versioned())?;
+    writer.write_all(b"\n")?;
+    for frame in frames {
+        serde_json::to_writer(&mut writer, frame)?;
+        writer.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+// Parse a JSON Lines recording. The first line is a metadata header, except in
+// recordings saved before one was always written. A bad final line is taken
+// to be a write cut short by a crash and is dropped with a warning.
+pub fn parse_lines(contents: &str, path: &Path) -> io::Result<(Metadata, Vec<RecordedFrame>)> {
+    let lines: Vec<&str> = contents.lines().collect();
+    let mut frames = Vec::with_capacity(lines.len());
+    let mut metadata = Metadata::default();
+
+    for (index, line) in lines.iter().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        // Any object parses as metadata, so the header is told apart by not
+        // being a frame
+        if index == 0 && serde_json::from_str::<RecordedFrame>(line).is_err() {
+            if let Ok(header) = serde_json::from_str::<Metadata>(line) {
+                metadata = header;
+                continue;
+            }
+        }
+
+        match serde_json::from_str(line) {
+            Ok(frame) => frames.push(frame),
+            Err(_) if index + 1 == lines.len() => {
+                eprintln!(
+                    "Warning: Ignoring incomplete last line of {}",
+                    path.display()
+                );
+            }
+            Err(e) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Invalid JSON on line {} of {}: {}",
+                        index + 1,
+                        path.display(),
+                        e
+                    ),
+                ))
+            }
+        }
+    }
+
+    Ok((metadata, frames))
+}
@@ -1,39 +1,462 @@
+pub mod annotations;
+pub mod cast;
+pub mod commands;
+pub mod controls;
+pub mod diff;
+pub mod escapes;
+pub mod fifo;
+pub mod jsonl;
 pub mod playback;
 pub mod recorder;
+pub mod status;
+pub mod trim;
+pub mod typing;
+pub mod utf8;
 
+use crate::terminal::parser;
+use crate::terminal::VirtualTerminal;
+use crate::utils;
+use annotations::Callout;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use jsonl::FrameLog;
 use serde::{Deserialize, Serialize};
-use std::fs;
-use std::io;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::ops::Range;
 use std::path::Path;
-use std::time::Instant;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Attempts made to move the temporary file into place before falling back
+const RENAME_ATTEMPTS: u32 = 5;
+
+// First bytes of every gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+// Size of the terminal used to measure how much room a recording's content
+// needs; anything reaching it is reported as "or more"
+const MEASURE_SIZE: usize = 500;
+
+// Terminal size assumed when a recording doesn't store its own, matching
+// the export defaults
+pub(crate) const DEFAULT_WIDTH: u16 = 80;
+pub(crate) const DEFAULT_HEIGHT: u16 = 24;
+
+// Version of the recording file layout written by this build. Bump it when
+// older builds would misread new files, so they refuse them instead.
+pub const SCHEMA_VERSION: u32 = 1;
+
+// Limits applied when loading a recording, so a crafted file shared online
+// can't exhaust memory in the loader or the renderer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoadLimits {
+    // Largest content a single frame may hold, in bytes
+    pub max_frame_bytes: u64,
+    // Largest recording file that will be read, in bytes
+    pub max_total_bytes: u64,
+}
+
+pub const DEFAULT_MAX_FRAME_BYTES: u64 = 16 * 1024 * 1024;
+pub const DEFAULT_MAX_TOTAL_BYTES: u64 = 512 * 1024 * 1024;
+
+impl Default for LoadLimits {
+    fn default() -> Self {
+        LoadLimits {
+            max_frame_bytes: DEFAULT_MAX_FRAME_BYTES,
+            max_total_bytes: DEFAULT_MAX_TOTAL_BYTES,
+        }
+    }
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct RecordedFrame {
     pub content: String,
     pub timestamp: u128,
+    // Older recordings only hold output, so frames without an origin are output
+    #[serde(default, skip_serializing_if = "FrameOrigin::is_output")]
+    pub origin: FrameOrigin,
+    // The bytes as captured, stored (as base64) only when they weren't valid
+    // UTF-8 and `content` holds a lossy copy
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "raw_bytes")]
+    pub raw: Option<Vec<u8>>,
+}
+
+impl RecordedFrame {
+    pub fn new(content: String, timestamp: u128) -> Self {
+        RecordedFrame {
+            content,
+            timestamp,
+            origin: FrameOrigin::Output,
+            raw: None,
+        }
+    }
+
+    // The frame exactly as it was captured
+    pub fn bytes(&self) -> &[u8] {
+        self.raw.as_deref().unwrap_or(self.content.as_bytes())
+    }
+}
+
+mod raw_bytes {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        raw: &Option<Vec<u8>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match raw {
+            Some(bytes) => serializer.serialize_str(&STANDARD.encode(bytes)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<u8>>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|encoded| STANDARD.decode(encoded).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+// Which stream a frame was captured from. Input frames hold what was typed
+// into the session and resize frames the new terminal size as "WxH"; neither
+// is drawn on the terminal.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameOrigin {
+    #[default]
+    Output,
+    Input,
+    Resize,
+}
+
+impl FrameOrigin {
+    pub fn is_output(&self) -> bool {
+        *self == FrameOrigin::Output
+    }
+}
+
+// A change in the size of the terminal the session was recorded in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Resize {
+    pub timestamp: u128,
+    pub width: u16,
+    pub height: u16,
+}
+
+// Settings and annotations stored with a recording. Unknown fields are
+// ignored, so metadata added by newer versions doesn't stop a recording from
+// loading.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Metadata {
+    // Layout version of the file the recording was read from; recordings
+    // saved before it was stored have none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<u32>,
+    // When the recording was started, in seconds since the Unix epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<u64>,
+    // Speed multiplier used when the viewer doesn't pick one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_speed: Option<f32>,
+    // Text callouts to show over the recording when exporting
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<Callout>,
+    // Size of the terminal the session was recorded in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u16>,
+    // Shell the session ran in, resolved through PATH
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shell: Option<String>,
+    // Environment variables that affect how the session's output looks
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+}
+
+impl Metadata {
+    // The metadata as it's written to disk, stamped with the current version
+    pub fn versioned(&self) -> Metadata {
+        Metadata {
+            version: Some(SCHEMA_VERSION),
+            ..self.clone()
+        }
+    }
+}
+
+// Recordings are stored as an object holding the metadata and the frames.
+// Older recordings without metadata are a plain frame array.
+#[derive(Deserialize)]
+struct RecordingFile {
+    metadata: Metadata,
+    frames: Vec<RecordedFrame>,
+}
+
+// Borrowed form of RecordingFile for saving without copying the frames
+#[derive(Serialize)]
+struct RecordingFileRef<'a> {
+    metadata: &'a Metadata,
+    frames: &'a [RecordedFrame],
+}
+
+// How a recording is stored on disk
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordingFormat {
+    // A single JSON array of frames
+    Json,
+    // One JSON object per line, appended as frames are captured
+    Jsonl,
+}
+
+impl RecordingFormat {
+    // Pick the format from the file extension, defaulting to JSON
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("jsonl") => RecordingFormat::Jsonl,
+            _ => RecordingFormat::Json,
+        }
+    }
+}
+
+impl FromStr for RecordingFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(RecordingFormat::Json),
+            "jsonl" => Ok(RecordingFormat::Jsonl),
+            _ => Err(format!("Invalid format '{}' (expected json or jsonl)", s)),
+        }
+    }
+}
+
+// How `rcrd info` shows frame times
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeFormat {
+    // Wall-clock time, from when the recording was made
+    Absolute,
+    // Time since the previous frame
+    Relative,
+    // Time since the recording started, as stored
+    Offset,
+}
+
+impl TimeFormat {
+    // Format the time of `frames[index]`. Absolute times need the creation
+    // time, in seconds since the Unix epoch.
+    fn format(self, frames: &[RecordedFrame], index: usize, created: Option<u64>) -> String {
+        let timestamp = frames[index].timestamp;
+        match self {
+            TimeFormat::Absolute => {
+                let ms = created.unwrap_or(0) * 1000 + timestamp as u64;
+                utils::format_utc_millis(ms)
+            }
+            TimeFormat::Relative => {
+                let previous = index
+                    .checked_sub(1)
+                    .map_or(timestamp, |i| frames[i].timestamp);
+                format!(
+                    "+{:.3}s",
+                    timestamp.saturating_sub(previous) as f64 / 1000.0
+                )
+            }
+            TimeFormat::Offset => format!("{:.3}s", timestamp as f64 / 1000.0),
+        }
+    }
+}
+
+impl FromStr for TimeFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "absolute" => Ok(TimeFormat::Absolute),
+            "relative" => Ok(TimeFormat::Relative),
+            "offset" => Ok(TimeFormat::Offset),
+            _ => Err(format!(
+                "Invalid time format '{}' (expected absolute, relative or offset)",
+                s
+            )),
+        }
+    }
+}
+
+// Monotonic time source for frame timestamps. Only differences between its
+// readings matter, so it can start from any point. Production uses the wall
+// clock; tests can drive their own for reproducible timestamps.
+pub type Clock = Arc<dyn Fn() -> Duration + Send + Sync>;
+
+// Clock that reads real time elapsed since it was created
+pub fn wall_clock() -> Clock {
+    let epoch = Instant::now();
+    Arc::new(move || epoch.elapsed())
 }
 
 #[derive(Clone)]
 pub struct Recording {
     pub frames: Vec<RecordedFrame>,
-    pub start_time: Instant,
+    pub format: RecordingFormat,
+    pub metadata: Metadata,
+    // Save JSON without indentation
+    pub compact: bool,
+    // Time source, and its reading when capture started
+    clock: Clock,
+    start: Duration,
+    // Added to new frames' timestamps when continuing an earlier recording
+    time_offset: u128,
+    // Open output file that frames are streamed to in JSONL mode
+    log: Option<Arc<Mutex<FrameLog>>>,
+    // Loaded from an asciinema cast, which can't be saved back as one
+    imported: bool,
+}
+
+impl Default for Recording {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Recording {
     pub fn new() -> Self {
+        Self::with_clock(wall_clock())
+    }
+
+    // An empty recording timed by the given clock
+    pub fn with_clock(clock: Clock) -> Self {
         Recording {
             frames: Vec::new(),
-            start_time: Instant::now(),
+            format: RecordingFormat::Json,
+            metadata: Metadata::default(),
+            compact: false,
+            start: clock(),
+            clock,
+            time_offset: 0,
+            log: None,
+            imported: false,
         }
     }
 
+    // A recording holding the given frames. Frames added later are timed
+    // from the end of the last one.
+    pub fn from_frames(frames: Vec<RecordedFrame>) -> Self {
+        Recording {
+            time_offset: frames.last().map_or(0, |f| f.timestamp),
+            frames,
+            ..Recording::new()
+        }
+    }
+
+    // Time since capture started (or resumed)
+    pub fn elapsed(&self) -> Duration {
+        (self.clock)().saturating_sub(self.start)
+    }
+
+    // A recording that will be saved to `output_path` in the given format.
+    // JSONL recordings start writing to the file immediately.
+    pub fn with_format(
+        format: RecordingFormat,
+        metadata: Metadata,
+        output_path: &Path,
+    ) -> io::Result<Self> {
+        // A compressed file can't be appended to, so it's written on save
+        let log = match format {
+            RecordingFormat::Jsonl if !is_gzip_path(output_path) => Some(Arc::new(Mutex::new(
+                FrameLog::create(output_path, &metadata, &[])?,
+            ))),
+            _ => None,
+        };
+
+        Ok(Recording {
+            format,
+            metadata,
+            log,
+            ..Recording::new()
+        })
+    }
+
+    // Pick up a loaded recording where it left off. New frames are
+    // timestamped after the last existing one, and JSONL recordings go back
+    // to streaming to `output_path`.
+    pub fn resume(mut self, output_path: &Path) -> io::Result<Self> {
+        self.time_offset = self.frames.last().map_or(0, |f| f.timestamp);
+        self.start = (self.clock)();
+
+        // Rewriting the file also drops any line left half-written by a crash
+        if self.format == RecordingFormat::Jsonl && !is_gzip_path(output_path) {
+            let log = FrameLog::create(output_path, &self.metadata, &self.frames)?;
+            self.log = Some(Arc::new(Mutex::new(log)));
+        }
+
+        Ok(self)
+    }
+
     pub fn add_frame(&mut self, content: String) {
+        self.push_frame(content, FrameOrigin::Output, None);
+    }
+
+    // Record output decoded by a Utf8Stream, keeping the captured bytes if
+    // they weren't valid UTF-8
+    pub fn add_output(&mut self, (content, raw): (String, Option<Vec<u8>>)) {
+        self.push_frame(content, FrameOrigin::Output, raw);
+    }
+
+    // Record a group of keystrokes sent to the session, kept as typed
+    pub fn add_input_frame(&mut self, content: String) {
+        self.push_frame(content, FrameOrigin::Input, None);
+    }
+
+    // Record that the terminal changed size
+    pub fn add_resize(&mut self, width: u16, height: u16) {
+        self.push_frame(format!("{}x{}", width, height), FrameOrigin::Resize, None);
+    }
+
+    fn push_frame(&mut self, content: String, origin: FrameOrigin, raw: Option<Vec<u8>>) {
         if !content.is_empty() {
-            let timestamp = self.start_time.elapsed().as_millis();
-            self.frames.push(RecordedFrame { content, timestamp });
+            let timestamp = self.time_offset + self.elapsed().as_millis();
+            let frame = RecordedFrame {
+                content,
+                timestamp,
+                origin,
+                raw,
+            };
+
+            if let Some(log) = &self.log {
+                if let Err(e) = log.lock().unwrap().append(&frame) {
+                    eprintln!("Error appending frame to recording: {}", e);
+                }
+            }
+
+            self.frames.push(frame);
         }
     }
 
+    // Save over the file the recording was opened from. An imported cast is
+    // refused, since it would be replaced by a recording in rcrd's format.
+    pub fn save_in_place(&self, path: &Path) -> io::Result<()> {
+        if self.imported {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} is an asciinema cast, which rcrd can't save changes back to; make an rcrd copy first with `rcrd trim {} --start 0 --output <file>.json`",
+                    path.display(),
+                    path.display()
+                ),
+            ));
+        }
+        self.save(path)
+    }
+
     pub fn save(&self, output_path: &Path) -> io::Result<()> {
         println!("Attempting to save recording to: {}", output_path.display());
 
@@ -41,6 +464,17 @@ impl Recording {
             println!("Warning: No frames recorded. Creating empty file anyway.");
         }
 
+        // A streamed JSONL recording is already complete on disk
+        if let Some(log) = &self.log {
+            log.lock().unwrap().flush()?;
+            println!(
+                "Successfully saved {} frames to {}",
+                self.frames.len(),
+                output_path.display()
+            );
+            return Ok(());
+        }
+
         if let Some(parent) = output_path.parent() {
             if !parent.exists() {
                 println!("Creating directory: {}", parent.display());
@@ -48,61 +482,842 @@ impl Recording {
             }
         }
 
+        // Serialize straight into the temporary file, so a long recording
+        // never has to fit in memory twice
         let temp_path = output_path.with_extension("json.tmp");
-        let json = serde_json::to_string_pretty(&self.frames).map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::Other,
-                format!("JSON serialization error: {}", e),
-            )
-        })?;
+        self.write_to_file(&temp_path, is_gzip_path(output_path))
+            .map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!(
+                        "Failed to write to temporary file {}: {}",
+                        temp_path.display(),
+                        e
+                    ),
+                )
+            })?;
 
-        fs::write(&temp_path, &json).map_err(|e| {
-            io::Error::new(
-                e.kind(),
-                format!(
-                    "Failed to write to temporary file {}: {}",
-                    temp_path.display(),
-                    e
-                ),
-            )
-        })?;
+        if let Err(e) = rename_with_retry(&temp_path, output_path) {
+            // Renaming keeps failing (typically a lock held on Windows), so
+            // give up on atomicity rather than losing the recording
+            eprintln!(
+                "Failed to rename temporary file to {}: {}. Writing directly instead.",
+                output_path.display(),
+                e
+            );
 
-        fs::rename(&temp_path, output_path).map_err(|e| {
-            io::Error::new(
-                e.kind(),
-                format!(
-                    "Failed to rename temporary file to {}: {}",
-                    output_path.display(),
-                    e
-                ),
-            )
-        })?;
+            fs::copy(&temp_path, output_path).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("Failed to write to {}: {}", output_path.display(), e),
+                )
+            })?;
+            let _ = fs::remove_file(&temp_path);
+        }
 
         println!(
             "Successfully saved {} frames ({} bytes) to {}",
             self.frames.len(),
-            json.len(),
+            fs::metadata(output_path)?.len(),
             output_path.display()
         );
 
         Ok(())
     }
 
+    // Write the recording, gzip-compressed if asked to
+    fn write_to_file(&self, path: &Path, gzip: bool) -> io::Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+
+        if gzip {
+            let mut encoder = GzEncoder::new(writer, Compression::default());
+            self.write_contents(&mut encoder)?;
+            encoder.finish()?.flush()
+        } else {
+            let mut writer = writer;
+            self.write_contents(&mut writer)?;
+            writer.flush()
+        }
+    }
+
+    fn write_contents<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        match self.format {
+            RecordingFormat::Json => write_json(
+                &mut writer,
+                &RecordingFileRef {
+                    metadata: &self.metadata.versioned(),
+                    frames: &self.frames,
+                },
+                self.compact,
+            )?,
+            RecordingFormat::Jsonl => {
+                jsonl::write_lines(&mut writer, &self.metadata, &self.frames)?
+            }
+        }
+
+        Ok(())
+    }
+
+    // The input track: everything typed into the session, with timestamps
+    pub fn input_events(&self) -> impl Iterator<Item = &RecordedFrame> {
+        self.frames
+            .iter()
+            .filter(|frame| frame.origin == FrameOrigin::Input)
+    }
+
+    // Every time the terminal changed size, skipping any malformed entries
+    pub fn resizes(&self) -> Vec<Resize> {
+        self.frames
+            .iter()
+            .filter(|frame| frame.origin == FrameOrigin::Resize)
+            .filter_map(|frame| {
+                let (width, height) = frame.content.split_once('x')?;
+                Some(Resize {
+                    timestamp: frame.timestamp,
+                    width: width.parse().ok().filter(|&w| w > 0)?,
+                    height: height.parse().ok().filter(|&h| h > 0)?,
+                })
+            })
+            .collect()
+    }
+
+    // Remove and return the frames that draw the terminal, dropping the
+    // input track and resize events
+    pub fn take_output(&mut self) -> Vec<RecordedFrame> {
+        let mut frames = std::mem::take(&mut self.frames);
+        frames.retain(|frame| frame.origin.is_output());
+        frames
+    }
+
+    // Load the output frames of a recording
     pub fn load(path: &Path) -> io::Result<Vec<RecordedFrame>> {
-        let contents = fs::read_to_string(path).map_err(|e| {
+        Ok(Self::open(path)?.take_output())
+    }
+
+    // Load a recording along with its metadata and the format it's stored in
+    pub fn open(path: &Path) -> io::Result<Recording> {
+        Self::open_with_limits(path, LoadLimits::default())
+    }
+
+    // Load a recording, refusing files and frames larger than the limits
+    pub fn open_with_limits(path: &Path, limits: LoadLimits) -> io::Result<Recording> {
+        let read_error = |e: io::Error| {
             io::Error::new(
                 e.kind(),
                 format!("Failed to read {}: {}", path.display(), e),
             )
-        })?;
+        };
+
+        // Reading one byte past the limit tells an oversized file apart
+        // without trusting its reported size (pipes don't have one). Gzipped
+        // recordings are recognized by their magic bytes, and the limit
+        // applies to the decompressed contents.
+        let mut contents = String::new();
+        File::open(path)
+            .and_then(|file| {
+                let mut reader = BufReader::new(file);
+                let reader: Box<dyn Read> = if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+                    Box::new(GzDecoder::new(reader))
+                } else {
+                    Box::new(reader)
+                };
+                reader
+                    .take(limits.max_total_bytes.saturating_add(1))
+                    .read_to_string(&mut contents)
+            })
+            .map_err(read_error)?;
+        if contents.len() as u64 > limits.max_total_bytes {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} is larger than the limit of {} bytes (raise it with --max-total-bytes)",
+                    path.display(),
+                    limits.max_total_bytes
+                ),
+            ));
+        }
 
-        let frames: Vec<RecordedFrame> = serde_json::from_str(&contents).map_err(|e| {
+        let invalid = |e: serde_json::Error| {
             io::Error::new(
                 io::ErrorKind::InvalidData,
                 format!("Invalid JSON in {}: {}", path.display(), e),
             )
-        })?;
+        };
+
+        // A bare array is the original format. An object is either the
+        // container with metadata, or the first line of a JSON Lines file.
+        // Asciinema casts are recognized by their header and saved back in
+        // rcrd's own JSON format.
+        let trimmed = contents.trim_start();
+        let mut imported = false;
+        let (format, metadata, frames) =
+            if let Some((metadata, frames)) = cast::parse_cast(&contents, path)? {
+                imported = true;
+                (RecordingFormat::Json, metadata, frames)
+            } else if RecordingFormat::from_path(path) == RecordingFormat::Jsonl {
+                let (metadata, frames) = jsonl::parse_lines(&contents, path)?;
+                (RecordingFormat::Jsonl, metadata, frames)
+            } else if trimmed.starts_with('[') {
+                let frames = serde_json::from_str(&contents).map_err(invalid)?;
+                (RecordingFormat::Json, Metadata::default(), frames)
+            } else if let Ok(file) = serde_json::from_str::<RecordingFile>(&contents) {
+                (RecordingFormat::Json, file.metadata, file.frames)
+            } else {
+                let (metadata, frames) = jsonl::parse_lines(&contents, path)?;
+                (RecordingFormat::Jsonl, metadata, frames)
+            };
 
-        Ok(frames)
+        if let Some(version) = metadata.version.filter(|&v| v > SCHEMA_VERSION) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} was saved in recording format version {}, but this build of rcrd only reads up to version {}; upgrade rcrd to open it",
+                    path.display(),
+                    version,
+                    SCHEMA_VERSION
+                ),
+            ));
+        }
+
+        let oversized = frames
+            .iter()
+            .position(|frame: &RecordedFrame| frame.content.len() as u64 > limits.max_frame_bytes);
+        if let Some(index) = oversized {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Frame {} of {} holds {} bytes, over the limit of {} (raise it with --max-frame-bytes)",
+                    index + 1,
+                    path.display(),
+                    frames[index].content.len(),
+                    limits.max_frame_bytes
+                ),
+            ));
+        }
+
+        Ok(Recording {
+            frames,
+            format,
+            metadata,
+            imported,
+            ..Recording::new()
+        })
+    }
+
+    // Replay frames into a headless terminal and return the text left on screen
+    pub fn final_screen(frames: &[RecordedFrame], width: usize, height: usize) -> String {
+        let mut terminal = VirtualTerminal::new(width, height, true);
+        for frame in frames {
+            terminal.process_content(&frame.content);
+        }
+        terminal.screen_text()
+    }
+
+    // The frames in `range` as a standalone recording. The output before the
+    // range is replayed in an initial frame so the terminal starts out in the
+    // same state, along with the last resize, and timestamps are rebased to
+    // start at zero.
+    pub fn slice(frames: &[RecordedFrame], range: Range<usize>) -> Vec<RecordedFrame> {
+        let start_time = frames.get(range.start).map_or(0, |f| f.timestamp);
+        let mut sliced = Vec::with_capacity(range.len() + 2);
+
+        let before = &frames[..range.start];
+        if let Some(resize) = before.iter().rfind(|f| f.origin == FrameOrigin::Resize) {
+            sliced.push(RecordedFrame {
+                timestamp: 0,
+                ..resize.clone()
+            });
+        }
+        let prior: String = before
+            .iter()
+            .filter(|f| f.origin.is_output())
+            .map(|f| f.content.as_str())
+            .collect();
+        if !prior.is_empty() {
+            sliced.push(RecordedFrame::new(prior, 0));
+        }
+
+        sliced.extend(frames[range].iter().map(|f| RecordedFrame {
+            timestamp: f.timestamp.saturating_sub(start_time),
+            ..f.clone()
+        }));
+        sliced
+    }
+}
+
+// Builds a recording with chosen timings instead of the clock, one frame
+// of output at a time
+#[derive(Debug, Default)]
+pub struct RecordingBuilder {
+    frames: Vec<RecordedFrame>,
+    timestamp: u128,
+}
+
+impl RecordingBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Add a frame shown `delay_ms` after the previous one (or the start)
+    pub fn push_content(&mut self, content: impl Into<String>, delay_ms: u128) -> &mut Self {
+        self.timestamp += delay_ms;
+        self.frames
+            .push(RecordedFrame::new(content.into(), self.timestamp));
+        self
+    }
+
+    // Add content to the last frame without a delay, starting the first
+    // frame if there is none yet
+    pub fn extend_last(&mut self, content: &str) -> &mut Self {
+        match self.frames.last_mut() {
+            Some(last) => last.content.push_str(content),
+            None => self
+                .frames
+                .push(RecordedFrame::new(content.to_string(), self.timestamp)),
+        }
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    // Timestamp of the last frame added, in milliseconds
+    pub fn timestamp(&self) -> u128 {
+        self.timestamp
+    }
+
+    pub fn into_frames(self) -> Vec<RecordedFrame> {
+        self.frames
+    }
+
+    pub fn build(self) -> Recording {
+        Recording::from_frames(self.frames)
+    }
+}
+
+// Update the metadata stored in a recording file, leaving its frames and
+// format as they are
+pub fn edit_metadata(
+    file: &str,
+    default_speed: Option<f32>,
+    clear_default_speed: bool,
+) -> io::Result<()> {
+    let path = utils::get_absolute_path(file);
+    let mut recording = Recording::open(&path)?;
+
+    if clear_default_speed {
+        recording.metadata.default_speed = None;
+    } else if default_speed.is_some() {
+        recording.metadata.default_speed = default_speed;
+    }
+
+    recording.save_in_place(&path)?;
+
+    match recording.metadata.default_speed {
+        Some(speed) => println!("Default speed: {}x", speed),
+        None => println!("Default speed: not set"),
+    }
+    Ok(())
+}
+
+// Time from the first frame to the last, which needn't start at 0
+fn duration(frames: &[RecordedFrame]) -> u128 {
+    match (frames.first(), frames.last()) {
+        (Some(first), Some(last)) => last.timestamp.saturating_sub(first.timestamp),
+        _ => 0,
+    }
+}
+
+// Print a summary of the recording, followed by the time of every frame
+// when a time format is given
+pub fn print_info(file: &str, time_format: Option<TimeFormat>) -> io::Result<()> {
+    let mut path = utils::get_absolute_path(file);
+    if !path.exists() {
+        let autosave_path = path.with_extension("json.autosave");
+        if !autosave_path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("File not found: {}", path.display()),
+            ));
+        }
+        println!(
+            "Original file not found, but found autosave: {}",
+            autosave_path.display()
+        );
+        path = autosave_path;
+    }
+
+    let recording = Recording::open(&path)?;
+    if recording.frames.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} holds no frames", path.display()),
+        ));
+    }
+    let metadata = &recording.metadata;
+    if time_format == Some(TimeFormat::Absolute) && metadata.created.is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} doesn't store when it was recorded, so its times can't be shown as absolute",
+                path.display()
+            ),
+        ));
+    }
+    let duration = duration(&recording.frames);
+
+    println!("File:          {}", path.display());
+    println!(
+        "Format:        {}",
+        match recording.format {
+            RecordingFormat::Json => "json",
+            RecordingFormat::Jsonl => "jsonl",
+        }
+    );
+    match metadata.version {
+        Some(version) => println!("Version:       {}", version),
+        None => println!("Version:       none (saved before versions were stored)"),
+    }
+    println!("Frames:        {}", recording.frames.len());
+    let inputs = recording.input_events().count();
+    if inputs > 0 {
+        println!("Input events:  {}", inputs);
+    }
+    let resizes = recording.resizes();
+    if !resizes.is_empty() {
+        let sizes: Vec<String> = resizes
+            .iter()
+            .map(|r| format!("{}x{}", r.width, r.height))
+            .collect();
+        println!("Resizes:       {}", sizes.join(", "));
+    }
+    println!("Duration:      {:.3}s", duration as f64 / 1000.0);
+
+    if let Some(created) = metadata.created {
+        println!("Recorded:      {}", utils::format_utc(created));
+    }
+    if let (Some(width), Some(height)) = (metadata.width, metadata.height) {
+        println!("Terminal size: {}x{}", width, height);
+    }
+    if let Some(shell) = &metadata.shell {
+        println!("Shell:         {}", shell);
+    }
+    for (name, value) in &metadata.env {
+        println!("{:<14} {}", format!("{}:", name), value);
+    }
+    if let Some(speed) = metadata.default_speed {
+        println!("Default speed: {}x", speed);
+    }
+    if !metadata.annotations.is_empty() {
+        println!("Callouts:      {}", metadata.annotations.len());
+    }
+
+    let output: Vec<&RecordedFrame> = recording
+        .frames
+        .iter()
+        .filter(|frame| frame.origin.is_output())
+        .collect();
+    let bytes: usize = output.iter().map(|frame| frame.bytes().len()).sum();
+    println!("Output bytes:  {}", bytes);
+    if let [first, .., last] = output.as_slice() {
+        let average =
+            last.timestamp.saturating_sub(first.timestamp) as f64 / (output.len() - 1) as f64;
+        println!(
+            "Average delay: {:.3}s between output frames",
+            average / 1000.0
+        );
+    }
+
+    // Joined first, so a sequence split across frames is counted once
+    let content: String = output.iter().map(|frame| frame.content.as_str()).collect();
+    let sequences: HashSet<String> = parser::tokenize(&content)
+        .filter_map(|(token, _)| parser::sequence_name(&token))
+        .collect();
+    println!(
+        "Escapes:       {} kinds of escape sequence (list them with `rcrd escapes`)",
+        sequences.len()
+    );
+
+    // How large an export has to be to show everything the session drew
+    let width = metadata.width.unwrap_or(DEFAULT_WIDTH) as usize;
+    println!(
+        "Content size:  {} without wrapping",
+        format_extent(content_extent(&output, MEASURE_SIZE))
+    );
+    println!(
+        "               {} wrapped at {} columns",
+        format_extent(content_extent(&output, width)),
+        width
+    );
+
+    if let Some(time_format) = time_format {
+        println!();
+        for (index, frame) in recording.frames.iter().enumerate() {
+            let kind = match frame.origin {
+                FrameOrigin::Output => "output",
+                FrameOrigin::Input => "input",
+                FrameOrigin::Resize => "resize",
+            };
+            println!(
+                "{:>6}  {:>24}  {:<6}  {} bytes",
+                index,
+                time_format.format(&recording.frames, index, metadata.created),
+                kind,
+                frame.bytes().len()
+            );
+        }
+    }
+    Ok(())
+}
+
+// Columns and rows reached when the frames are replayed into a terminal of
+// the given width, tall enough that nothing scrolls away
+fn content_extent(frames: &[&RecordedFrame], width: usize) -> (usize, usize) {
+    let mut terminal = VirtualTerminal::new(width, MEASURE_SIZE, true);
+    for frame in frames {
+        terminal.process_content(&frame.content);
+    }
+    terminal.content_extent()
+}
+
+fn format_extent((columns, rows): (usize, usize)) -> String {
+    let format = |cells: usize| {
+        if cells >= MEASURE_SIZE {
+            format!("{}+", MEASURE_SIZE)
+        } else {
+            cells.to_string()
+        }
+    };
+    format!("{}x{}", format(columns), format(rows))
+}
+
+// Recordings saved under a .gz name are gzip-compressed
+fn is_gzip_path(path: &Path) -> bool {
+    path.extension().and_then(|ext| ext.to_str()) == Some("gz")
+}
+
+fn write_json<W: Write, T: Serialize>(writer: W, value: &T, compact: bool) -> io::Result<()> {
+    if compact {
+        serde_json::to_writer(writer, value)
+    } else {
+        serde_json::to_writer_pretty(writer, value)
+    }
+    .map_err(|e| io::Error::other(format!("JSON serialization error: {}", e)))
+}
+
+// Rename a file, backing off and retrying when the target is briefly locked
+// (antivirus scanners and editors on Windows commonly hold it open)
+fn rename_with_retry(from: &Path, to: &Path) -> io::Result<()> {
+    let mut delay = Duration::from_millis(50);
+
+    for attempt in 1..RENAME_ATTEMPTS {
+        match fs::rename(from, to) {
+            Ok(()) => return Ok(()),
+            Err(e) => {
+                eprintln!(
+                    "Rename to {} failed (attempt {}/{}): {}. Retrying in {}ms...",
+                    to.display(),
+                    attempt,
+                    RENAME_ATTEMPTS,
+                    e,
+                    delay.as_millis()
+                );
+                thread::sleep(delay);
+                delay *= 2;
+            }
+        }
+    }
+
+    fs::rename(from, to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn sample_frames() -> Vec<RecordedFrame> {
+        vec![
+            RecordedFrame::new("$ ls\r\n".to_string(), 0),
+            RecordedFrame::new("src\r\n".to_string(), 250),
+        ]
+    }
+
+    fn assert_frames_match(frames: &[RecordedFrame], expected: &[RecordedFrame]) {
+        let texts = |frames: &[RecordedFrame]| -> Vec<(String, u128)> {
+            frames
+                .iter()
+                .map(|f| (f.content.clone(), f.timestamp))
+                .collect()
+        };
+        assert_eq!(texts(frames), texts(expected));
+    }
+
+    fn save_sample(name: &str, format: RecordingFormat) -> PathBuf {
+        let path = utils::temp_path(name);
+        let mut recording = Recording::from_frames(sample_frames());
+        recording.format = format;
+        recording.metadata = Metadata {
+            width: Some(100),
+            height: Some(30),
+            created: Some(1_700_000_000),
+            ..Metadata::default()
+        };
+        recording.save(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn frames_are_timed_by_the_recording_clock() {
+        let now = Arc::new(Mutex::new(Duration::from_secs(100)));
+        let clock: Clock = {
+            let now = now.clone();
+            Arc::new(move || *now.lock().unwrap())
+        };
+        let mut recording = Recording::with_clock(clock);
+
+        recording.add_frame("first".to_string());
+        *now.lock().unwrap() += Duration::from_millis(1250);
+        recording.add_frame("second".to_string());
+        // Empty output isn't recorded
+        recording.add_frame(String::new());
+
+        let times: Vec<u128> = recording.frames.iter().map(|f| f.timestamp).collect();
+        assert_eq!(times, vec![0, 1250]);
+        assert_eq!(recording.elapsed(), Duration::from_millis(1250));
+    }
+
+    #[test]
+    fn builder_accumulates_delays() {
+        let mut builder = RecordingBuilder::new();
+        builder
+            .extend_last("$ ")
+            .push_content("l", 100)
+            .push_content("s", 150)
+            .extend_last("\r\n");
+        assert_eq!(builder.timestamp(), 250);
+
+        let frames = builder.into_frames();
+        let texts: Vec<(&str, u128)> = frames
+            .iter()
+            .map(|f| (f.content.as_str(), f.timestamp))
+            .collect();
+        assert_eq!(texts, vec![("$ ", 0), ("l", 100), ("s\r\n", 250)]);
+    }
+
+    #[test]
+    fn builder_builds_a_recording_of_its_frames() {
+        let mut builder = RecordingBuilder::new();
+        builder.push_content("a", 40).push_content("b", 60);
+        let recording = builder.build();
+
+        let texts: Vec<(&str, u128)> = recording
+            .frames
+            .iter()
+            .map(|f| (f.content.as_str(), f.timestamp))
+            .collect();
+        assert_eq!(texts, vec![("a", 40), ("b", 100)]);
+        assert_eq!(recording.format, RecordingFormat::Json);
+    }
+
+    #[test]
+    fn gzip_round_trip() {
+        let path = save_sample("sample.json.gz", RecordingFormat::Json);
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(bytes[..2], GZIP_MAGIC);
+
+        let loaded = Recording::open(&path).unwrap();
+        assert_frames_match(&loaded.frames, &sample_frames());
+        assert_eq!(loaded.metadata.width, Some(100));
+
+        // Compressed data is recognized by its magic bytes whatever the name
+        let renamed = utils::temp_path("compressed-without-extension.json");
+        fs::write(&renamed, &bytes).unwrap();
+        let loaded = Recording::open(&renamed).unwrap();
+        assert_frames_match(&loaded.frames, &sample_frames());
+
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(&renamed);
+    }
+
+    #[test]
+    fn duration_runs_from_first_frame_to_last() {
+        let frames = vec![
+            RecordedFrame::new("a".to_string(), 1500),
+            RecordedFrame::new("b".to_string(), 4000),
+        ];
+        assert_eq!(duration(&frames), 2500);
+        assert_eq!(duration(&frames[..1]), 0);
+        assert_eq!(duration(&[]), 0);
+    }
+
+    #[test]
+    fn time_formats_show_offsets_deltas_and_wall_clock() {
+        let frames = vec![
+            RecordedFrame::new("a".to_string(), 500),
+            RecordedFrame::new("b".to_string(), 1750),
+        ];
+        let created = Some(1_700_000_000);
+
+        assert_eq!(TimeFormat::Offset.format(&frames, 1, created), "1.750s");
+        assert_eq!(TimeFormat::Relative.format(&frames, 0, created), "+0.000s");
+        assert_eq!(TimeFormat::Relative.format(&frames, 1, created), "+1.250s");
+        assert_eq!(
+            TimeFormat::Absolute.format(&frames, 1, created),
+            "2023-11-14 22:13:21.750 UTC"
+        );
+        assert!("elapsed".parse::<TimeFormat>().is_err());
+    }
+
+    #[test]
+    fn final_screen_replays_every_frame() {
+        let frames = vec![
+            RecordedFrame::new("\x1B[32m$\x1B[0m echo hi\r\n".to_string(), 0),
+            RecordedFrame::new("hi\r\n\x1B[32m$\x1B[0m ".to_string(), 100),
+        ];
+
+        assert_eq!(
+            Recording::final_screen(&frames, 20, 4),
+            "$ echo hi\nhi\n$\n"
+        );
+    }
+
+    #[test]
+    fn json_round_trip_stores_version_and_metadata() {
+        let path = save_sample("round-trip.json", RecordingFormat::Json);
+        let recording = Recording::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(recording.format, RecordingFormat::Json);
+        assert_eq!(recording.metadata.version, Some(SCHEMA_VERSION));
+        assert_eq!(recording.metadata.width, Some(100));
+        assert_eq!(recording.metadata.height, Some(30));
+        assert_eq!(recording.metadata.created, Some(1_700_000_000));
+        assert_frames_match(&recording.frames, &sample_frames());
+    }
+
+    #[test]
+    fn jsonl_round_trip_is_not_mistaken_for_a_cast() {
+        let path = save_sample("round-trip.jsonl", RecordingFormat::Jsonl);
+        let recording = Recording::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(recording.format, RecordingFormat::Jsonl);
+        assert_eq!(recording.metadata.version, Some(SCHEMA_VERSION));
+        assert_eq!(recording.metadata.width, Some(100));
+        assert_frames_match(&recording.frames, &sample_frames());
+    }
+
+    #[test]
+    fn loads_legacy_bare_array() {
+        let path = utils::temp_path("legacy.json");
+        fs::write(
+            &path,
+            r#"[{"content":"$ ls\r\n","timestamp":0},{"content":"src\r\n","timestamp":250}]"#,
+        )
+        .unwrap();
+        let recording = Recording::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(recording.metadata, Metadata::default());
+        assert_frames_match(&recording.frames, &sample_frames());
+    }
+
+    #[test]
+    fn loads_metadata_saved_before_versions() {
+        let path = utils::temp_path("unversioned.json");
+        fs::write(
+            &path,
+            r#"{"metadata":{"default_speed":2.0},"frames":[{"content":"hi","timestamp":5}]}"#,
+        )
+        .unwrap();
+        let recording = Recording::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(recording.metadata.version, None);
+        assert_eq!(recording.metadata.default_speed, Some(2.0));
+        assert_eq!(recording.frames.len(), 1);
+    }
+
+    #[test]
+    fn refuses_newer_version_with_unknown_fields() {
+        let path = utils::temp_path("newer.json");
+        fs::write(
+            &path,
+            format!(
+                r#"{{"metadata":{{"version":{},"future_field":true}},"frames":[]}}"#,
+                SCHEMA_VERSION + 1
+            ),
+        )
+        .unwrap();
+        let error = Recording::open(&path).err().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("upgrade rcrd"), "{}", error);
+    }
+
+    #[test]
+    fn ignores_unknown_metadata_fields_at_current_version() {
+        let path = utils::temp_path("extra-field.jsonl");
+        fs::write(
+            &path,
+            "{\"version\":1,\"future_field\":[1,2]}\n{\"content\":\"hi\",\"timestamp\":5}\n",
+        )
+        .unwrap();
+        let recording = Recording::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(recording.metadata.version, Some(1));
+        assert_eq!(recording.frames.len(), 1);
+    }
+
+    #[test]
+    fn slice_rebases_timestamps_that_go_backwards_to_zero() {
+        let frames = vec![
+            RecordedFrame::new("a".to_string(), 0),
+            RecordedFrame::new("b".to_string(), 2000),
+            RecordedFrame::new("c".to_string(), 1500),
+        ];
+        let sliced = Recording::slice(&frames, 1..3);
+
+        let texts: Vec<(&str, u128)> = sliced
+            .iter()
+            .map(|f| (f.content.as_str(), f.timestamp))
+            .collect();
+        assert_eq!(texts, vec![("a", 0), ("b", 0), ("c", 0)]);
+    }
+
+    #[test]
+    fn slice_replays_earlier_output_and_the_last_resize() {
+        let frames = vec![
+            RecordedFrame::new("\x1B[31m$ ls\r\n".to_string(), 0),
+            RecordedFrame {
+                origin: FrameOrigin::Resize,
+                ..RecordedFrame::new("100x30".to_string(), 500)
+            },
+            RecordedFrame {
+                origin: FrameOrigin::Input,
+                ..RecordedFrame::new("q".to_string(), 700)
+            },
+            RecordedFrame::new("src\r\n".to_string(), 1000),
+            RecordedFrame::new("$ ".to_string(), 1500),
+        ];
+        let sliced = Recording::slice(&frames, 3..5);
+
+        let events: Vec<(&str, u128, FrameOrigin)> = sliced
+            .iter()
+            .map(|f| (f.content.as_str(), f.timestamp, f.origin))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                ("100x30", 0, FrameOrigin::Resize),
+                ("\x1B[31m$ ls\r\n", 0, FrameOrigin::Output),
+                ("src\r\n", 0, FrameOrigin::Output),
+                ("$ ", 500, FrameOrigin::Output),
+            ]
+        );
     }
 }
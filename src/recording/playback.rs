@@ -1,10 +1,54 @@
-use crate::recording::Recording;
+use crate::export::timing;
+use crate::recording::controls::Controls;
+use crate::recording::{LoadLimits, RecordedFrame, Recording};
+use crate::terminal::VirtualTerminal;
 use crate::utils;
 use std::io::{self, Write};
 use std::path::Path;
 use std::time::Duration;
 
-pub fn play_session(file: &str, speed: f32) -> io::Result<()> {
+// Longest pause between two frames during playback
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+// Settings controlling how a recording is replayed in the terminal
+#[derive(Clone, Debug)]
+pub struct PlaybackOptions {
+    // Speed multiplier; None uses the recording's default speed, or 1.0
+    pub speed: Option<f32>,
+    // Replay the screen states back to front
+    pub reverse: bool,
+    // Redraw each frame from a virtual terminal instead of raw output
+    pub repair: bool,
+    // Size of the virtual screen used when frames are re-rendered
+    pub width: u16,
+    pub height: u16,
+    // Cap every pause between frames at this many seconds, before scaling
+    pub max_idle: Option<f32>,
+    // Keep the current frame's timestamp updated on stderr
+    pub show_time: bool,
+    // Take keys to pause, step through frames, change speed and quit
+    pub interactive: bool,
+    // Largest frame and recording file that will be loaded
+    pub limits: LoadLimits,
+}
+
+impl Default for PlaybackOptions {
+    fn default() -> Self {
+        PlaybackOptions {
+            speed: None,
+            reverse: false,
+            repair: false,
+            width: 80,
+            height: 24,
+            max_idle: None,
+            show_time: false,
+            interactive: false,
+            limits: LoadLimits::default(),
+        }
+    }
+}
+
+pub fn play_session(file: &str, options: &PlaybackOptions) -> io::Result<()> {
     let file_path = utils::get_absolute_path(file);
     println!("Loading recording from {}", file_path.display());
 
@@ -15,7 +59,7 @@ pub fn play_session(file: &str, speed: f32) -> io::Result<()> {
                 "Original file not found, but found autosave: {}",
                 autosave_path.display()
             );
-            return play_session_from_path(&autosave_path, speed);
+            return play_session_from_path(&autosave_path, options);
         }
 
         return Err(io::Error::new(
@@ -24,26 +68,230 @@ pub fn play_session(file: &str, speed: f32) -> io::Result<()> {
         ));
     }
 
-    play_session_from_path(&file_path, speed)
+    play_session_from_path(&file_path, options)
 }
 
-fn play_session_from_path(file_path: &Path, speed: f32) -> io::Result<()> {
-    let frames = Recording::load(&file_path)?;
+fn play_session_from_path(file_path: &Path, options: &PlaybackOptions) -> io::Result<()> {
+    let mut recording = Recording::open_with_limits(file_path, options.limits)?;
+    let frames = recording.take_output();
     println!("Loaded {} frames", frames.len());
 
+    let options = &PlaybackOptions {
+        speed: Some(
+            options
+                .speed
+                .or(recording.metadata.default_speed)
+                .unwrap_or(1.0),
+        ),
+        ..options.clone()
+    };
+
+    let damaged = frames
+        .iter()
+        .filter(|frame| frame.content.contains(char::REPLACEMENT_CHARACTER))
+        .count();
+    if damaged > 0 {
+        eprintln!(
+            "Warning: {} frames contain broken UTF-8 sequences{}",
+            damaged,
+            if options.repair {
+                ""
+            } else {
+                ", use --repair to redraw them cleanly"
+            }
+        );
+    }
+
+    let controls = options
+        .interactive
+        .then(|| Controls::start(options.speed.unwrap_or(1.0)))
+        .transpose()?;
+    let controls = controls.as_ref();
+    if controls.is_some() {
+        eprintln!("Space pauses, right arrow steps while paused, + and - change speed, q quits");
+    }
+
+    if options.reverse {
+        return play_reversed(&frames, options, controls);
+    }
+
+    if options.repair {
+        return play_repaired(&frames, options, controls);
+    }
+
     let mut last_timestamp: u128 = 0;
 
     for frame in frames {
-        if last_timestamp > 0 {
-            let delay = frame.timestamp - last_timestamp;
-            let sleep_time = Duration::from_millis((delay as f32 / speed) as u64);
-            std::thread::sleep(sleep_time);
+        if last_timestamp > 0
+            && !wait(
+                frame.timestamp.saturating_sub(last_timestamp),
+                options,
+                controls,
+            )
+        {
+            break;
         }
-        print!("{}", frame.content);
+        show_time(options, frame.timestamp)?;
+        io::stdout().write_all(frame.bytes())?;
         io::stdout().flush()?;
         last_timestamp = frame.timestamp;
     }
 
-    println!("\nPlayback complete");
+    finish_time(options);
+    println!("\nPlayback {}", outcome(controls));
     Ok(())
 }
+
+// Time to wait between two frames at the playback speed. Capped so a tiny
+// speed, or a stored speed of zero, can't stall playback for hours; the cast
+// saturates, so an infinite delay also ends up at the cap.
+fn scaled_delay(delay_ms: u128, options: &PlaybackOptions) -> Duration {
+    let delay_ms = timing::cap_idle(delay_ms, options.max_idle);
+    let millis = delay_ms as f64 / options.speed.unwrap_or(1.0) as f64;
+    Duration::from_millis(millis as u64).min(MAX_DELAY)
+}
+
+// Wait between two frames, under the user's control in interactive mode.
+// Returns false once the user has quit.
+fn wait(delay_ms: u128, options: &PlaybackOptions, controls: Option<&Controls>) -> bool {
+    match controls {
+        Some(controls) => controls.wait(delay_ms, options.max_idle),
+        None => {
+            std::thread::sleep(scaled_delay(delay_ms, options));
+            true
+        }
+    }
+}
+
+fn outcome(controls: Option<&Controls>) -> &'static str {
+    if controls.is_some_and(Controls::quit) {
+        "stopped"
+    } else {
+        "complete"
+    }
+}
+
+// Raw output only makes sense played forwards, so replay the frames into a
+// virtual terminal, snapshot the screen after each one and redraw those
+// snapshots from last to first
+fn play_reversed(
+    frames: &[RecordedFrame],
+    options: &PlaybackOptions,
+    controls: Option<&Controls>,
+) -> io::Result<()> {
+    let mut terminal = VirtualTerminal::new(options.width as usize, options.height as usize, true);
+    let mut snapshots = Vec::with_capacity(frames.len());
+
+    for frame in frames {
+        terminal.process_content(&frame.content);
+        snapshots.push((terminal.render_ansi(), frame.timestamp));
+    }
+
+    let mut last_timestamp: Option<u128> = None;
+
+    for (screen, timestamp) in snapshots.iter().rev() {
+        if let Some(last) = last_timestamp {
+            if !wait(last.saturating_sub(*timestamp), options, controls) {
+                break;
+            }
+        }
+        show_time(options, *timestamp)?;
+        print!("{}", screen);
+        io::stdout().flush()?;
+        last_timestamp = Some(*timestamp);
+    }
+
+    finish_time(options);
+    println!("\x1B[0m\nPlayback {}", outcome(controls));
+    Ok(())
+}
+
+// Feed each frame through a virtual terminal and redraw the whole screen
+// from its grid, so split multibyte sequences and stray bytes in the raw
+// output can't throw off cursor positioning or leave attributes dangling
+fn play_repaired(
+    frames: &[RecordedFrame],
+    options: &PlaybackOptions,
+    controls: Option<&Controls>,
+) -> io::Result<()> {
+    let mut terminal = VirtualTerminal::new(options.width as usize, options.height as usize, true);
+    let mut last_timestamp: u128 = 0;
+
+    print!("\x1B[H\x1B[2J");
+
+    for frame in frames {
+        if last_timestamp > 0
+            && !wait(
+                frame.timestamp.saturating_sub(last_timestamp),
+                options,
+                controls,
+            )
+        {
+            break;
+        }
+        terminal.process_content(&frame.content);
+        show_time(options, frame.timestamp)?;
+        // Ring the real terminal's bell, as raw playback would
+        print!("{}", "\x07".repeat(terminal.take_bells()));
+        print!("{}", terminal.render_ansi());
+        io::stdout().flush()?;
+        last_timestamp = frame.timestamp;
+    }
+
+    finish_time(options);
+    println!("\x1B[0m\nPlayback {}", outcome(controls));
+    Ok(())
+}
+
+// Overwrite the status line on stderr with the frame's timestamp. It goes to
+// stderr so redirecting stdout still captures just the replayed output.
+fn show_time(options: &PlaybackOptions, timestamp: u128) -> io::Result<()> {
+    if options.show_time {
+        eprint!("\r[{:.3}s]", timestamp as f64 / 1000.0);
+        io::stderr().flush()?;
+    }
+    Ok(())
+}
+
+// End the status line so later output starts on a fresh line
+fn finish_time(options: &PlaybackOptions) {
+    if options.show_time {
+        eprintln!();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at_speed(speed: f32) -> PlaybackOptions {
+        PlaybackOptions {
+            speed: Some(speed),
+            ..PlaybackOptions::default()
+        }
+    }
+
+    #[test]
+    fn delays_scale_with_speed() {
+        assert_eq!(
+            scaled_delay(1000, &at_speed(2.0)),
+            Duration::from_millis(500)
+        );
+        assert_eq!(scaled_delay(1000, &at_speed(0.5)), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn tiny_and_zero_speeds_wait_at_most_the_cap() {
+        assert_eq!(scaled_delay(1000, &at_speed(0.0001)), MAX_DELAY);
+        assert_eq!(scaled_delay(1000, &at_speed(0.0)), MAX_DELAY);
+    }
+
+    #[test]
+    fn max_idle_caps_pauses_before_scaling() {
+        let options = PlaybackOptions {
+            max_idle: Some(2.0),
+            ..at_speed(2.0)
+        };
+        assert_eq!(scaled_delay(60_000, &options), Duration::from_secs(1));
+    }
+}
@@ -1,76 +1,103 @@
 use crate::export;
-use crate::recording::Recording;
+use crate::export::gif::ExportOptions;
+use crate::recording::status::StatusLine;
+use crate::recording::utf8::Utf8Stream;
+use crate::recording::{Metadata, Recording, RecordingFormat};
 use crate::utils;
 use ctrlc;
-use std::fs;
+use std::env;
 use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+// Environment variables stored with a recording, as they affect how
+// programs in the session draw their output
+const RECORDED_ENV: &[&str] = &["TERM", "LANG"];
+
+// How often a copy of a JSON recording is saved while it's being made
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+// How often the resize thread checks for a SIGWINCH
+const RESIZE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// Settings controlling how a session is captured
+#[derive(Clone, Debug)]
+pub struct RecordOptions {
+    pub format: RecordingFormat,
+    // Metadata for a new recording; when appending, only the values set here
+    // replace the stored ones
+    pub metadata: Metadata,
+    // Keep elapsed time and frame count updated on stderr
+    pub show_status: bool,
+    // Continue the recording already in the output file
+    pub append: bool,
+    // Save JSON recordings without indentation
+    pub compact_json: bool,
+    // Run this in the shell instead of forwarding what the user types, and
+    // stop once it finishes
+    pub command: Option<String>,
+    // Shell to run the session in, instead of $SHELL or the platform default
+    pub shell: Option<String>,
+}
 
-pub fn record_session(output_file: &str) -> io::Result<()> {
+pub fn record_session(output_file: &str, options: &RecordOptions) -> io::Result<()> {
     let output_path = utils::get_absolute_path(output_file);
     println!("Starting terminal recording session");
-    println!("All input and output will be recorded");
-    println!("Type 'exit' or press Ctrl+C to end the recording");
+    match &options.command {
+        Some(command) => println!("Running: {}", command),
+        None => {
+            println!("All input and output will be recorded");
+            println!("Type 'exit' or press Ctrl+C to end the recording");
+        }
+    }
     println!("Output will be saved to: {}", output_path.display());
     println!("A GIF will be automatically created with default settings");
 
-    {
+    if !options.append {
         let _test_file = std::fs::File::create(&output_path)?;
         println!("Verified write permissions to output file");
     }
 
-    let recording = Arc::new(Mutex::new(Recording::new()));
-    let running = Arc::new(AtomicBool::new(true));
-
-    let output_file_clone = output_file.to_string();
-    let r_clone = recording.clone();
-    let path_clone = output_path.clone();
-    let running_clone = running.clone();
-
-    ctrlc::set_handler(move || {
-        println!("\nCtrl+C detected, saving recording and exiting...");
-        running_clone.store(false, Ordering::SeqCst);
-
-        thread::sleep(Duration::from_millis(500));
+    let shell = &choose_shell(options.shell.as_deref());
+    println!("Using shell: {}", shell);
+
+    // Note what the session ran in so it can be replayed faithfully
+    let options = &RecordOptions {
+        metadata: Metadata {
+            shell: Some(
+                utils::find_in_path(shell)
+                    .map_or_else(|| shell.to_string(), |path| path.display().to_string()),
+            ),
+            env: RECORDED_ENV
+                .iter()
+                .filter_map(|&name| Some((name.to_string(), env::var(name).ok()?)))
+                .collect(),
+            ..options.metadata.clone()
+        },
+        ..options.clone()
+    };
 
-        let rec = r_clone.lock().unwrap().clone();
-        if let Err(e) = rec.save(&path_clone) {
-            eprintln!("Error saving recording on Ctrl+C: {}", e);
-        } else {
-            // Auto-export to GIF after saving recording
-            let gif_output = path_clone.with_extension("gif");
-            println!("Automatically creating GIF from recording...");
-
-            if let Err(e) = export::gif::export_to_gif(
-                path_clone.to_str().unwrap_or(&output_file_clone),
-                gif_output.to_str().unwrap_or("output.gif"),
-                1.0,  // Default speed
-                80,   // Default width
-                24,   // Default height
-                16,   // Default font size
-                true, // Default to dark theme
-            ) {
-                eprintln!("Warning: Failed to create GIF automatically: {}", e);
-            } else {
-                println!("GIF exported to: {}", gif_output.display());
-            }
-        }
+    let recording = Arc::new(Mutex::new(open_recording(&output_path, options)?));
+    let running = Arc::new(AtomicBool::new(true));
+    let status = Arc::new(StatusLine::new(options.show_status));
 
-        std::process::exit(0);
-    })
-    .expect("Error setting Ctrl+C handler");
+    install_interrupt_handler(
+        recording.clone(),
+        output_path.clone(),
+        output_file.to_string(),
+        running.clone(),
+    );
 
-    let shell = if cfg!(target_os = "windows") {
-        "cmd"
-    } else {
-        "bash"
-    };
+    let mut shell_command = Command::new(shell);
+    if let Some(command) = &options.command {
+        shell_command.arg(command_flag(shell)).arg(command);
+    }
 
-    let mut child = Command::new(shell)
+    let mut child = shell_command
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
@@ -80,57 +107,35 @@ pub fn record_session(output_file: &str) -> io::Result<()> {
     let child_stdout = child.stdout.take().expect("Failed to open stdout");
     let child_stderr = child.stderr.take().expect("Failed to open stderr");
 
-    let running_stdout = running.clone();
-    let recording_stdout = recording.clone();
-
-    let stdout_handle = thread::spawn(move || {
-        let mut buffer = [0; 1024];
-        let mut stdout_reader = child_stdout;
-
-        while running_stdout.load(Ordering::SeqCst) {
-            match stdout_reader.read(&mut buffer) {
-                Ok(0) => break,
-                Ok(n) => {
-                    let content = String::from_utf8_lossy(&buffer[0..n]).to_string();
-                    if !content.is_empty() {
-                        print!("{}", content);
-                        io::stdout().flush().unwrap_or_default();
-                        recording_stdout.lock().unwrap().add_frame(content);
-                    }
+    // Everything captured goes through one channel and is appended by a
+    // single thread, so frames keep the order they arrived in rather than
+    // whichever reader won the lock
+    let (sender, receiver) = mpsc::channel();
+
+    let recording_writer = recording.clone();
+    let status_writer = status.clone();
+
+    let writer_handle = thread::spawn(move || {
+        for capture in receiver {
+            let mut recording = recording_writer.lock().unwrap();
+            match capture {
+                Capture::Stdout(content, raw) => {
+                    status_writer.echo(&content, false);
+                    recording.add_output((content, raw));
                 }
-                Err(e) => {
-                    eprintln!("Error reading from child stdout: {}", e);
-                    break;
+                Capture::Stderr(content, raw) => {
+                    status_writer.echo(&content, true);
+                    recording.add_output((content, raw));
                 }
+                Capture::Input(content) => recording.add_input_frame(content),
             }
         }
     });
 
-    let running_stderr = running.clone();
-    let recording_stderr = recording.clone();
-
-    let stderr_handle = thread::spawn(move || {
-        let mut buffer = [0; 1024];
-        let mut stderr_reader = child_stderr;
-
-        while running_stderr.load(Ordering::SeqCst) {
-            match stderr_reader.read(&mut buffer) {
-                Ok(0) => break,
-                Ok(n) => {
-                    let content = String::from_utf8_lossy(&buffer[0..n]).to_string();
-                    if !content.is_empty() {
-                        eprint!("{}", content);
-                        io::stderr().flush().unwrap_or_default();
-                        recording_stderr.lock().unwrap().add_frame(content);
-                    }
-                }
-                Err(e) => {
-                    eprintln!("Error reading from child stderr: {}", e);
-                    break;
-                }
-            }
-        }
-    });
+    let mut reader_handles = vec![
+        spawn_reader(child_stdout, Capture::Stdout, &sender, &running),
+        spawn_reader(child_stderr, Capture::Stderr, &sender, &running),
+    ];
 
     let stdin = io::stdin();
     let mut input = String::new();
@@ -143,8 +148,7 @@ pub fn record_session(output_file: &str) -> io::Result<()> {
 
     let autosave_handle = thread::spawn(move || {
         let mut counter = 0;
-        while autosave_running.load(Ordering::SeqCst) {
-            thread::sleep(Duration::from_secs(30));
+        while wait_while_running(&autosave_running, AUTOSAVE_INTERVAL) {
             counter += 1;
 
             let current_recording = {
@@ -152,7 +156,10 @@ pub fn record_session(output_file: &str) -> io::Result<()> {
                 recording_lock.clone()
             };
 
-            if !current_recording.frames.is_empty() {
+            // JSONL recordings are already streamed to disk frame by frame
+            if !current_recording.frames.is_empty()
+                && current_recording.format == RecordingFormat::Json
+            {
                 if let Err(e) = current_recording.save(&autosave_path) {
                     eprintln!("Error during autosave #{}: {}", counter, e);
                 } else {
@@ -162,42 +169,79 @@ pub fn record_session(output_file: &str) -> io::Result<()> {
         }
     });
 
-    while running.load(Ordering::SeqCst) {
-        input.clear();
-        match stdin.read_line(&mut input) {
-            Ok(_) => {
-                if input.trim() == "exit" {
-                    println!("Exit command detected, ending recording...");
-                    break;
-                }
-
-                match child_stdin.write_all(input.as_bytes()) {
-                    Ok(_) => {
-                        child_stdin.flush().unwrap_or_default();
-                    }
-                    Err(e) => {
-                        eprintln!("Failed to write to child stdin: {}", e);
+    let status_handle = options
+        .show_status
+        .then(|| spawn_status_thread(&recording, &status, &running));
+    let resize_handle = spawn_resize_thread(&recording, &running);
+
+    if options.command.is_some() {
+        // The command gets no input, and its output is complete once both
+        // streams close
+        drop(child_stdin);
+        for handle in reader_handles.drain(..) {
+            let _ = handle.join();
+        }
+        match child.wait() {
+            Ok(exit) if !exit.success() => eprintln!("Warning: the command finished with {}", exit),
+            Ok(_) => {}
+            Err(e) => eprintln!("Error waiting for the command: {}", e),
+        }
+    } else {
+        while running.load(Ordering::SeqCst) {
+            input.clear();
+            match stdin.read_line(&mut input) {
+                Ok(_) => {
+                    if input.trim() == "exit" {
+                        println!("Exit command detected, ending recording...");
                         break;
                     }
+
+                    // Each line is sent to the shell in one write, so it's kept
+                    // as one group of keystrokes
+                    let _ = sender.send(Capture::Input(input.clone()));
+
+                    match child_stdin.write_all(input.as_bytes()) {
+                        Ok(_) => {
+                            child_stdin.flush().unwrap_or_default();
+                        }
+                        Err(e) => {
+                            eprintln!("Failed to write to child stdin: {}", e);
+                            break;
+                        }
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Error reading from stdin: {}", e);
+                    break;
                 }
-            }
-            Err(e) => {
-                eprintln!("Error reading from stdin: {}", e);
-                break;
             }
         }
     }
 
-    println!("Shutting down recording...");
     running.store(false, Ordering::SeqCst);
+    status.clear();
+    println!("Shutting down recording...");
 
     let _ = child.kill();
 
     thread::sleep(Duration::from_millis(200));
 
-    let _ = stdout_handle.join();
-    let _ = stderr_handle.join();
+    for handle in reader_handles {
+        let _ = handle.join();
+    }
+    // The writer finishes once the readers have dropped their senders
+    drop(sender);
+    let _ = writer_handle.join();
+    // Wake the threads waiting between saves and status updates so they
+    // see the recording has stopped
+    autosave_handle.thread().unpark();
     let _ = autosave_handle.join();
+    if let Some(handle) = status_handle {
+        handle.thread().unpark();
+        let _ = handle.join();
+    }
+    let _ = resize_handle.join();
+    status.clear();
 
     let final_recording_data = {
         let recording_lock = recording.lock().unwrap();
@@ -209,33 +253,285 @@ pub fn record_session(output_file: &str) -> io::Result<()> {
         final_recording_data.frames.len()
     );
 
-    fs::write(
-        &output_path,
-        serde_json::to_string_pretty(&final_recording_data.frames).unwrap_or_default(),
-    )?;
+    final_recording_data.save(&output_path)?;
+
+    finish_with_gif(&output_path, output_file);
+
+    Ok(())
+}
+
+// The shell asked for, then the user's login shell from $SHELL, then bash
+// (cmd on Windows)
+fn choose_shell(requested: Option<&str>) -> String {
+    requested
+        .map(str::to_string)
+        .or_else(|| env::var("SHELL").ok().filter(|shell| !shell.is_empty()))
+        .unwrap_or_else(|| {
+            if cfg!(target_os = "windows") {
+                "cmd".to_string()
+            } else {
+                "bash".to_string()
+            }
+        })
+}
+
+// Flag that makes the shell run a single command and exit
+fn command_flag(shell: &str) -> &'static str {
+    let name = Path::new(shell)
+        .file_stem()
+        .and_then(|name| name.to_str())
+        .unwrap_or(shell)
+        .to_ascii_lowercase();
+
+    match name.as_str() {
+        "cmd" => "/C",
+        "powershell" | "pwsh" => "-Command",
+        _ => "-c",
+    }
+}
 
-    println!("Recording saved to {}", output_path.display());
+// Something read from the session, in the order it was read
+enum Capture {
+    Stdout(String, Option<Vec<u8>>),
+    Stderr(String, Option<Vec<u8>>),
+    Input(String),
+}
+
+// Read one of the child's output streams until it closes or the recording
+// stops, sending each chunk to the writer thread
+fn spawn_reader<R: Read + Send + 'static>(
+    mut reader: R,
+    capture: fn(String, Option<Vec<u8>>) -> Capture,
+    sender: &mpsc::Sender<Capture>,
+    running: &Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+    let sender = sender.clone();
+    let running = running.clone();
+
+    thread::spawn(move || {
+        let mut buffer = [0; 1024];
+        let mut stream = Utf8Stream::new();
+
+        while running.load(Ordering::SeqCst) {
+            match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => {
+                    let (content, raw) = stream.decode(&buffer[0..n]);
+                    if sender.send(capture(content, raw)).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Error reading from child output: {}", e);
+                    break;
+                }
+            }
+        }
+
+        // A stream that ends partway through a character keeps its last bytes
+        let (content, raw) = stream.finish();
+        let _ = sender.send(capture(content, raw));
+    })
+}
+
+// Create the recording to capture into, or load the one being appended to
+pub(crate) fn open_recording(output_path: &Path, options: &RecordOptions) -> io::Result<Recording> {
+    let size = utils::terminal_size();
+
+    if !options.append {
+        let metadata = Metadata {
+            width: size.map(|(width, _)| width),
+            height: size.map(|(_, height)| height),
+            created: utils::unix_time(),
+            ..options.metadata.clone()
+        };
+        let mut recording = Recording::with_format(options.format, metadata, output_path)?;
+        recording.compact = options.compact_json;
+        return Ok(recording);
+    }
+
+    let mut recording = Recording::open(output_path)?;
+    recording.compact = options.compact_json;
+    let metadata = &mut recording.metadata;
+
+    match (size, metadata.width, metadata.height) {
+        (Some((width, height)), Some(original_width), Some(original_height))
+            if (width, height) != (original_width, original_height) =>
+        {
+            eprintln!(
+                "Warning: the terminal is {}x{} but {} was recorded at {}x{}",
+                width,
+                height,
+                output_path.display(),
+                original_width,
+                original_height
+            );
+        }
+        (Some((width, height)), None, None) => {
+            metadata.width = Some(width);
+            metadata.height = Some(height);
+        }
+        _ => {}
+    }
+    if options.metadata.default_speed.is_some() {
+        metadata.default_speed = options.metadata.default_speed;
+    }
+
+    println!(
+        "Appending to {} existing frames ({:.1}s)",
+        recording.frames.len(),
+        recording.frames.last().map_or(0, |f| f.timestamp) as f64 / 1000.0
+    );
+    recording.resume(output_path)
+}
+
+// Refresh the status line once a second until the recording stops
+pub(crate) fn spawn_status_thread(
+    recording: &Arc<Mutex<Recording>>,
+    status: &Arc<StatusLine>,
+    running: &Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+    let recording = recording.clone();
+    let status = status.clone();
+    let running = running.clone();
+
+    thread::spawn(move || {
+        while running.load(Ordering::SeqCst) {
+            let (elapsed, frames) = {
+                let recording = recording.lock().unwrap();
+                (recording.elapsed(), recording.frames.len())
+            };
+            status.update(elapsed, frames);
+            wait_while_running(&running, Duration::from_secs(1));
+        }
+    })
+}
+
+// Wait out `interval`, returning early (and false) once `running` is
+// cleared. Whoever clears it unparks the waiting thread so it notices
+// straight away.
+fn wait_while_running(running: &AtomicBool, interval: Duration) -> bool {
+    let deadline = Instant::now() + interval;
+    while running.load(Ordering::SeqCst) {
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        thread::park_timeout(deadline - now);
+    }
+    false
+}
 
-    // Auto-export to GIF
+// Record each change in the size of the terminal rcrd runs in, so exports
+// can follow it. The shell is attached through pipes and never gets SIGWINCH
+// itself, so rcrd catches it and reads the new size from its own terminal.
+pub(crate) fn spawn_resize_thread(
+    recording: &Arc<Mutex<Recording>>,
+    running: &Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+    let recording = recording.clone();
+    let running = running.clone();
+
+    utils::watch_resizes();
+
+    thread::spawn(move || {
+        let mut last_size = utils::terminal_size();
+        while running.load(Ordering::SeqCst) {
+            thread::sleep(RESIZE_POLL_INTERVAL);
+            if !utils::take_resized() {
+                continue;
+            }
+            let size = utils::terminal_size();
+            if let Some((width, height)) = size.filter(|_| size != last_size) {
+                recording.lock().unwrap().add_resize(width, height);
+            }
+            last_size = size;
+        }
+    })
+}
+
+// Save the recording and export a GIF when the user presses Ctrl+C
+pub(crate) fn install_interrupt_handler(
+    recording: Arc<Mutex<Recording>>,
+    output_path: PathBuf,
+    output_file: String,
+    running: Arc<AtomicBool>,
+) {
+    ctrlc::set_handler(move || {
+        println!("\nCtrl+C detected, saving recording and exiting...");
+        running.store(false, Ordering::SeqCst);
+
+        thread::sleep(Duration::from_millis(500));
+
+        let rec = recording.lock().unwrap().clone();
+        if let Err(e) = rec.save(&output_path) {
+            eprintln!("Error saving recording on Ctrl+C: {}", e);
+        } else {
+            match auto_export_gif(&output_path, &output_file) {
+                Ok(gif_output) => println!("GIF exported to: {}", gif_output.display()),
+                Err(e) => eprintln!("Warning: Failed to create GIF automatically: {}", e),
+            }
+        }
+
+        std::process::exit(0);
+    })
+    .expect("Error setting Ctrl+C handler");
+}
+
+// Auto-export a freshly saved recording and tell the user how to customize it
+pub(crate) fn finish_with_gif(output_path: &Path, output_file: &str) {
+    match auto_export_gif(output_path, output_file) {
+        Ok(gif_output) => {
+            println!("GIF exported to: {}", gif_output.display());
+            println!("To customize the GIF, use: terminal-recorder export {} custom.gif --width X --height Y", output_path.display());
+        }
+        Err(e) => {
+            eprintln!("Warning: Failed to create GIF automatically: {}", e);
+            println!("You can still manually convert this to a GIF with: terminal-recorder export {} output.gif", output_path.display());
+        }
+    }
+}
+
+fn auto_export_gif(output_path: &Path, output_file: &str) -> io::Result<PathBuf> {
     let gif_output = output_path.with_extension("gif");
     println!("Automatically creating GIF from recording...");
 
     // Use default settings for GIF export
-    if let Err(e) = export::gif::export_to_gif(
+    export::gif::export_to_gif(
         output_path.to_str().unwrap_or(output_file),
         gif_output.to_str().unwrap_or("output.gif"),
-        1.0,  // Default speed
-        80,   // Default width
-        24,   // Default height
-        16,   // Default font size
-        true, // Default to dark theme
-    ) {
-        eprintln!("Warning: Failed to create GIF automatically: {}", e);
-        println!("You can still manually convert this to a GIF with: terminal-recorder export {} output.gif", output_path.display());
-    } else {
-        println!("GIF exported to: {}", gif_output.display());
-        println!("To customize the GIF, use: terminal-recorder export {} custom.gif --width X --height Y", output_path.display());
+        &ExportOptions::default(),
+    )?;
+
+    Ok(gif_output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_end_when_the_recording_stops() {
+        let running = Arc::new(AtomicBool::new(true));
+        let waiter = {
+            let running = running.clone();
+            thread::spawn(move || wait_while_running(&running, Duration::from_secs(60)))
+        };
+
+        let started = Instant::now();
+        running.store(false, Ordering::SeqCst);
+        waiter.thread().unpark();
+
+        assert!(!waiter.join().unwrap());
+        assert!(started.elapsed() < Duration::from_secs(5));
     }
 
-    Ok(())
+    #[test]
+    fn waits_run_the_full_interval_while_recording() {
+        let running = AtomicBool::new(true);
+        let started = Instant::now();
+
+        assert!(wait_while_running(&running, Duration::from_millis(50)));
+        assert!(started.elapsed() >= Duration::from_millis(50));
+    }
 }
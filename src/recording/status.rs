@@ -0,0 +1,92 @@
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Optional status line on stderr showing how long a recording has been
+// running and how many frames it has captured.
+//
+// The session's output shares the terminal with it, so the line is only drawn
+// while that output is sitting at the start of a line, and is wiped again
+// before any more output is echoed. Both happen under one lock so the status
+// can never land in the middle of the session's output.
+pub struct StatusLine {
+    enabled: bool,
+    state: Mutex<State>,
+}
+
+struct State {
+    // Whether the status text is currently on screen
+    visible: bool,
+    // Whether the last echoed output ended with a newline
+    at_line_start: bool,
+}
+
+impl StatusLine {
+    pub fn new(enabled: bool) -> Self {
+        StatusLine {
+            enabled,
+            state: Mutex::new(State {
+                visible: false,
+                at_line_start: true,
+            }),
+        }
+    }
+
+    // Echo session output to stdout, or stderr for the child's stderr
+    pub fn echo(&self, content: &str, to_stderr: bool) {
+        let mut state = self.state.lock().unwrap();
+        if state.visible {
+            clear_line();
+            state.visible = false;
+        }
+
+        if to_stderr {
+            eprint!("{}", content);
+            io::stderr().flush().unwrap_or_default();
+        } else {
+            print!("{}", content);
+            io::stdout().flush().unwrap_or_default();
+        }
+
+        if let Some(last) = content.chars().last() {
+            state.at_line_start = last == '\n';
+        }
+    }
+
+    // Redraw the status text, if enabled and the session isn't mid-line
+    pub fn update(&self, elapsed: Duration, frames: usize) {
+        if !self.enabled {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        if !state.at_line_start {
+            return;
+        }
+
+        let seconds = elapsed.as_secs();
+        eprint!(
+            "\r\x1B[K[REC {:02}:{:02} | {} {}]",
+            seconds / 60,
+            seconds % 60,
+            frames,
+            if frames == 1 { "frame" } else { "frames" }
+        );
+        io::stderr().flush().unwrap_or_default();
+        state.visible = true;
+    }
+
+    // Remove the status text before the recorder prints its own messages
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.visible {
+            clear_line();
+            state.visible = false;
+        }
+    }
+}
+
+fn clear_line() {
+    eprint!("\r\x1B[K");
+    io::stderr().flush().unwrap_or_default();
+}
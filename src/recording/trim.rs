@@ -0,0 +1,268 @@
+use crate::recording::{cast, RecordedFrame, Recording, DEFAULT_HEIGHT, DEFAULT_WIDTH};
+use crate::terminal::VirtualTerminal;
+use crate::utils;
+use std::io;
+
+// Longest wait kept before the final frame once the end has been trimmed
+const MAX_FINAL_GAP_MS: u128 = 1000;
+
+// Drop the frames after the last one that changes the screen, and shorten a
+// long wait before that last change. Screens are compared cell by cell,
+// colors included, on a terminal of the given size. Input frames never count
+// as a change, so typing `exit` at the end is trimmed along with the idle
+// output around it.
+pub fn trim_trailing(
+    mut frames: Vec<RecordedFrame>,
+    width: usize,
+    height: usize,
+) -> Vec<RecordedFrame> {
+    let mut terminal = VirtualTerminal::new(width, height, true);
+    let mut screen = terminal.styled_screen_text();
+    let mut last_change = None;
+
+    for (i, frame) in frames.iter().enumerate() {
+        if !frame.origin.is_output() {
+            continue;
+        }
+
+        terminal.process_content(&frame.content);
+        let next = terminal.styled_screen_text();
+        if next != screen {
+            screen = next;
+            last_change = Some(i);
+        }
+    }
+
+    // A recording that never draws anything is left alone
+    let Some(last) = last_change else {
+        return frames;
+    };
+    frames.truncate(last + 1);
+
+    let previous = frames[..last]
+        .iter()
+        .rev()
+        .find(|frame| frame.origin.is_output())
+        .map(|frame| frame.timestamp);
+    if let Some(previous) = previous {
+        let end = frames[last].timestamp.min(previous + MAX_FINAL_GAP_MS);
+        for frame in &mut frames {
+            frame.timestamp = frame.timestamp.min(end);
+        }
+    }
+
+    frames
+}
+
+// Keep the frames from `start_ms` to `end_ms` (inclusive), rebased to start
+// at zero. The output before the cut is replayed in a first frame, so colors,
+// cursor position and the screen carry over into the kept part.
+pub fn cut_range(
+    frames: &[RecordedFrame],
+    start_ms: u128,
+    end_ms: Option<u128>,
+) -> io::Result<Vec<RecordedFrame>> {
+    let first = frames.partition_point(|f| f.timestamp < start_ms);
+    let end = end_ms.map_or(frames.len(), |end| {
+        frames.partition_point(|f| f.timestamp <= end)
+    });
+
+    if first >= end {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "No frames between {:.3}s and {}",
+                start_ms as f64 / 1000.0,
+                end_ms.map_or("the end".to_string(), |end| format!(
+                    "{:.3}s",
+                    end as f64 / 1000.0
+                ))
+            ),
+        ));
+    }
+
+    Ok(Recording::slice(frames, first..end))
+}
+
+// Trim a recording file in place, or into `output` when given. A time range
+// is cut first, then the trailing frames of what's left.
+pub fn trim_file(
+    file: &str,
+    output: Option<&str>,
+    start: Option<f32>,
+    end: Option<f32>,
+    trailing: bool,
+) -> io::Result<()> {
+    if !trailing && start.is_none() && end.is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Nothing to trim; pass --start, --end or --trailing",
+        ));
+    }
+    if let (Some(start), Some(end)) = (start, end) {
+        if end <= start {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("--end ({}s) must be after --start ({}s)", end, start),
+            ));
+        }
+    }
+
+    let output_path = output.map(utils::get_absolute_path);
+    if let Some(output_path) = output_path.as_deref().filter(|p| cast::is_cast_path(p)) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Can't save to {}: trimmed recordings are saved in rcrd's format, so use a .json or .jsonl name (and `rcrd cast` to convert back)",
+                output_path.display()
+            ),
+        ));
+    }
+
+    let path = utils::get_absolute_path(file);
+    let mut recording = Recording::open(&path)?;
+    let before = (
+        recording.frames.len(),
+        recording.frames.last().map_or(0, |f| f.timestamp),
+    );
+
+    if start.is_some() || end.is_some() {
+        let to_ms = |seconds: f32| (seconds * 1000.0).round() as u128;
+        recording.frames = cut_range(&recording.frames, start.map_or(0, to_ms), end.map(to_ms))?;
+    }
+    if trailing {
+        let width = recording.metadata.width.unwrap_or(DEFAULT_WIDTH) as usize;
+        let height = recording.metadata.height.unwrap_or(DEFAULT_HEIGHT) as usize;
+        recording.frames = trim_trailing(recording.frames, width, height);
+    }
+    let after = (
+        recording.frames.len(),
+        recording.frames.last().map_or(0, |f| f.timestamp),
+    );
+
+    match output_path {
+        Some(output_path) => recording.save(&output_path)?,
+        None => recording.save_in_place(&path)?,
+    }
+
+    println!(
+        "Removed {} frames and {:.1}s",
+        before.0.saturating_sub(after.0),
+        before.1.saturating_sub(after.1) as f64 / 1000.0
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::FrameOrigin;
+    use std::fs;
+
+    fn times(frames: &[RecordedFrame]) -> Vec<(&str, u128)> {
+        frames
+            .iter()
+            .map(|f| (f.content.as_str(), f.timestamp))
+            .collect()
+    }
+
+    fn styled_screen(frames: &[RecordedFrame]) -> String {
+        let mut terminal = VirtualTerminal::new(10, 2, true);
+        for frame in frames {
+            terminal.process_content(&frame.content);
+        }
+        terminal.styled_screen_text()
+    }
+
+    #[test]
+    fn cut_range_rebases_the_kept_frames() {
+        let frames = vec![
+            RecordedFrame::new("a".to_string(), 0),
+            RecordedFrame::new("b".to_string(), 1000),
+            RecordedFrame::new("c".to_string(), 2500),
+            RecordedFrame::new("d".to_string(), 4000),
+        ];
+        let cut = cut_range(&frames, 1000, Some(2500)).unwrap();
+
+        assert_eq!(times(&cut), vec![("a", 0), ("b", 0), ("c", 1500)]);
+    }
+
+    #[test]
+    fn cut_range_carries_the_screen_across_the_cut() {
+        let frames = vec![
+            RecordedFrame::new("\x1B[31m$ ls\r\n".to_string(), 0),
+            RecordedFrame::new("src".to_string(), 2000),
+        ];
+        let cut = cut_range(&frames, 1000, None).unwrap();
+
+        // Same text and colors as the uncut recording ends with
+        assert_eq!(styled_screen(&cut), styled_screen(&frames));
+        assert_eq!(Recording::final_screen(&cut, 10, 2), "$ ls\nsrc");
+    }
+
+    #[test]
+    fn cut_range_without_frames_is_an_error() {
+        let frames = vec![RecordedFrame::new("a".to_string(), 0)];
+        let err = cut_range(&frames, 500, Some(900)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn trim_trailing_drops_frames_after_the_last_change() {
+        let frames = vec![
+            RecordedFrame::new("a".to_string(), 0),
+            RecordedFrame::new("b".to_string(), 500),
+            RecordedFrame {
+                origin: FrameOrigin::Input,
+                ..RecordedFrame::new("exit\r".to_string(), 900)
+            },
+            RecordedFrame::new("\x1B[0m".to_string(), 1200),
+        ];
+        let trimmed = trim_trailing(frames, 10, 2);
+
+        assert_eq!(times(&trimmed), vec![("a", 0), ("b", 500)]);
+    }
+
+    #[test]
+    fn trim_trailing_caps_the_wait_before_the_last_change() {
+        let frames = vec![
+            RecordedFrame::new("a".to_string(), 0),
+            RecordedFrame {
+                origin: FrameOrigin::Input,
+                ..RecordedFrame::new("x".to_string(), 30_000)
+            },
+            RecordedFrame::new("b".to_string(), 60_000),
+        ];
+        let trimmed = trim_trailing(frames, 10, 2);
+
+        assert_eq!(
+            times(&trimmed),
+            vec![("a", 0), ("x", MAX_FINAL_GAP_MS), ("b", MAX_FINAL_GAP_MS)]
+        );
+    }
+
+    #[test]
+    fn trim_file_refuses_to_rewrite_a_cast() {
+        let path = utils::temp_path("trim-in-place.cast");
+        let cast = "{\"version\": 2, \"width\": 10, \"height\": 2}\n[0.0, \"o\", \"a\"]\n[1.0, \"o\", \"b\"]\n";
+        fs::write(&path, cast).unwrap();
+
+        let err = trim_file(path.to_str().unwrap(), None, Some(0.5), None, false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(fs::read_to_string(&path).unwrap(), cast);
+
+        let output = utils::temp_path("trimmed.cast");
+        let err = trim_file(
+            path.to_str().unwrap(),
+            Some(output.to_str().unwrap()),
+            Some(0.5),
+            None,
+            false,
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(!output.exists());
+
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,45 @@
+use super::{RecordedFrame, RecordingBuilder};
+use crate::terminal::parser::{self, Token};
+use crate::utils;
+use std::fs;
+use std::io;
+use std::mem;
+
+// Build a recording that types the text out one character per frame.
+// Escape sequences draw nothing by themselves, so each one is folded into
+// the frame of the character that follows it instead of costing a delay.
+pub fn typing_frames(text: &str, delay_ms: u128, hold_ms: u128) -> Vec<RecordedFrame> {
+    let mut builder = RecordingBuilder::new();
+    let mut pending = String::new();
+
+    for (token, raw) in parser::tokenize(text) {
+        pending.push_str(raw);
+
+        if matches!(token, Token::Text(_) | Token::Control('\n')) {
+            builder.push_content(mem::take(&mut pending), delay_ms);
+        }
+    }
+
+    // Trailing escapes (usually a reset) still need to reach the terminal
+    if !pending.is_empty() {
+        builder.extend_last(&pending);
+    }
+
+    // Hold the finished text on screen before the GIF loops
+    if hold_ms > 0 && !builder.is_empty() {
+        builder.push_content(String::new(), hold_ms);
+    }
+
+    builder.into_frames()
+}
+
+// Read the text to type from a file
+pub fn load_text(file: &str) -> io::Result<String> {
+    let path = utils::get_absolute_path(file);
+    fs::read_to_string(&path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Failed to read {}: {}", path.display(), e),
+        )
+    })
+}
@@ -0,0 +1,56 @@
+// Decodes a byte stream that arrives in arbitrary chunks, such as a child's
+// output read 1024 bytes at a time. A character split across two reads is
+// held back until the rest of it arrives instead of being replaced.
+#[derive(Debug, Default)]
+pub struct Utf8Stream {
+    pending: Vec<u8>,
+}
+
+impl Utf8Stream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The text of the next chunk, along with its exact bytes if any of them
+    // weren't valid UTF-8 and the text is a lossy copy
+    pub fn decode(&mut self, bytes: &[u8]) -> (String, Option<Vec<u8>>) {
+        self.pending.extend_from_slice(bytes);
+        let end = self.pending.len() - incomplete_tail(&self.pending);
+        let chunk: Vec<u8> = self.pending.drain(..end).collect();
+        text_of(chunk)
+    }
+
+    // Whatever is still held back once the stream has ended
+    pub fn finish(&mut self) -> (String, Option<Vec<u8>>) {
+        text_of(std::mem::take(&mut self.pending))
+    }
+}
+
+fn text_of(bytes: Vec<u8>) -> (String, Option<Vec<u8>>) {
+    match String::from_utf8(bytes) {
+        Ok(text) => (text, None),
+        Err(e) => {
+            let bytes = e.into_bytes();
+            (String::from_utf8_lossy(&bytes).into_owned(), Some(bytes))
+        }
+    }
+}
+
+// Length of the character cut off at the end of `bytes`, if its lead byte
+// and continuation bytes so far are valid. A UTF-8 character is at most 4
+// bytes, so only the last 3 can be an incomplete one.
+fn incomplete_tail(bytes: &[u8]) -> usize {
+    let search_from = bytes.len().saturating_sub(3);
+    let lead = match (search_from..bytes.len())
+        .rev()
+        .find(|&i| bytes[i] & 0xC0 != 0x80)
+    {
+        Some(lead) => lead,
+        None => return 0,
+    };
+
+    match std::str::from_utf8(&bytes[lead..]) {
+        Err(e) if e.valid_up_to() == 0 && e.error_len().is_none() => bytes.len() - lead,
+        _ => 0,
+    }
+}
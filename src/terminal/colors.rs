@@ -1,6 +1,7 @@
 use image::Rgb;
+use std::str::FromStr;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct TermColor {
     pub r: u8,
     pub g: u8,
@@ -8,7 +9,27 @@ pub struct TermColor {
 }
 
 impl TermColor {
-    pub fn to_rgb(&self) -> Rgb<u8> {
+    pub fn to_rgb(self) -> Rgb<u8> {
         Rgb([self.r, self.g, self.b])
     }
 }
+
+// Parse a hex color such as #1e1e1e (the # is optional)
+impl FromStr for TermColor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        let invalid = || format!("Invalid color '{}' (expected #rrggbb)", s);
+        if hex.len() != 6 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+        Ok(TermColor {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+        })
+    }
+}
@@ -0,0 +1,117 @@
+use crate::terminal::TermColor;
+use fontdue::{Font, FontSettings, Metrics};
+use image::{ImageBuffer, Rgb};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+// A rasterized glyph's placement and its coverage, one byte per pixel
+type Glyph = (Metrics, Vec<u8>);
+
+// A TrueType or OpenType font drawn antialiased in place of the built-in
+// bitmaps. Glyphs are rasterized once per character and size.
+pub struct OutlineFont {
+    font: Font,
+    glyphs: RefCell<HashMap<(char, u8), Glyph>>,
+}
+
+impl OutlineFont {
+    pub fn load(path: &str) -> io::Result<Self> {
+        let data = fs::read(path).map_err(|e| {
+            io::Error::new(e.kind(), format!("Failed to read font {}: {}", path, e))
+        })?;
+        let font = Font::from_bytes(data, FontSettings::default()).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Failed to load font {}: {}", path, e),
+            )
+        })?;
+
+        Ok(OutlineFont {
+            font,
+            glyphs: RefCell::new(HashMap::new()),
+        })
+    }
+
+    pub fn has_glyph(&self, character: char) -> bool {
+        self.font.lookup_glyph_index(character) != 0
+    }
+
+    // Pixel size at which a line of text fills the cell height and a
+    // character's advance fits the cell width, whichever is smaller
+    fn pixel_size(&self, font_size: u8) -> f32 {
+        let cell_width = font_size as f32;
+        let cell_height = font_size as f32 * 2.0;
+
+        let advance = self.font.metrics('M', 1.0).advance_width;
+        let line = self
+            .font
+            .horizontal_line_metrics(1.0)
+            .map_or(1.0, |metrics| metrics.ascent - metrics.descent);
+
+        let by_width = if advance > 0.0 {
+            cell_width / advance
+        } else {
+            cell_width
+        };
+        let by_height = if line > 0.0 {
+            cell_height / line
+        } else {
+            cell_height
+        };
+        by_width.min(by_height)
+    }
+
+    // Draw a character into the cell at the given pixel origin, blending the
+    // color over what's already there by the glyph's coverage
+    pub fn draw_glyph(
+        &self,
+        img: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+        character: char,
+        px_start: u32,
+        py_start: u32,
+        font_size: u8,
+        color: TermColor,
+    ) {
+        let px = self.pixel_size(font_size);
+        let cell_width = font_size as i64;
+        let cell_height = (font_size as f32 * 2.0) as i64;
+
+        // Lines are centered in the cell, with every glyph on one baseline
+        let (ascent, descent) = self
+            .font
+            .horizontal_line_metrics(px)
+            .map_or((px, 0.0), |metrics| (metrics.ascent, metrics.descent));
+        let baseline = py_start as i64
+            + ((cell_height as f32 - (ascent - descent)) / 2.0 + ascent).round() as i64;
+
+        let mut glyphs = self.glyphs.borrow_mut();
+        let (metrics, coverage) = glyphs
+            .entry((character, font_size))
+            .or_insert_with(|| self.font.rasterize(character, px));
+
+        let left = px_start as i64
+            + ((cell_width as f32 - metrics.advance_width) / 2.0).round() as i64
+            + metrics.xmin as i64;
+        let top = baseline - metrics.ymin as i64 - metrics.height as i64;
+
+        for (i, &alpha) in coverage.iter().enumerate() {
+            if alpha == 0 {
+                continue;
+            }
+            let x = left + (i % metrics.width) as i64;
+            let y = top + (i / metrics.width) as i64;
+            if x < 0 || y < 0 || x >= img.width() as i64 || y >= img.height() as i64 {
+                continue;
+            }
+
+            let pixel = img.get_pixel_mut(x as u32, y as u32);
+            let Rgb([r, g, b]) = *pixel;
+            let blend = |under: u8, over: u8| {
+                ((under as u32 * (255 - alpha as u32) + over as u32 * alpha as u32) / 255) as u8
+            };
+            *pixel = Rgb([blend(r, color.r), blend(g, color.g), blend(b, color.b)]);
+        }
+    }
+}
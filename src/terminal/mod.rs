@@ -1,4 +1,6 @@
 pub mod colors;
+pub mod font;
+pub mod parser;
 pub mod virtual_term;
 
 pub use colors::TermColor;
@@ -0,0 +1,152 @@
+// A unit of terminal output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token<'a> {
+    // A printable character
+    Text(char),
+    // A C0 control character other than ESC
+    Control(char),
+    // ESC [ <params> <command>
+    Csi { params: &'a str, command: char },
+    // ESC ] <payload> terminated by BEL or ST
+    Osc(&'a str),
+    // ESC P/X/^/_ <payload> terminated by ST (DCS and the other string sequences)
+    Dcs(&'a str),
+    // Any other escape sequence, without the leading ESC (e.g. "7" or "(B")
+    Esc(&'a str),
+    // An escape sequence cut off by the end of the input
+    Incomplete(&'a str),
+}
+
+// Split terminal output into tokens, each paired with the raw text it came from
+pub fn tokenize(input: &str) -> Tokens<'_> {
+    Tokens { input, pos: 0 }
+}
+
+pub struct Tokens<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = (Token<'a>, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.pos;
+        let rest = &self.input[start..];
+        let c = rest.chars().next()?;
+
+        if c != '\x1B' {
+            self.pos += c.len_utf8();
+            let token = if c.is_control() && (c as u32) < 0x80 {
+                Token::Control(c)
+            } else {
+                Token::Text(c)
+            };
+            return Some((token, &self.input[start..self.pos]));
+        }
+
+        let token = match rest[1..].chars().next() {
+            Some('[') => self.csi(start),
+            Some(']') => self.string_sequence(start, true),
+            Some('P' | 'X' | '^' | '_') => self.string_sequence(start, false),
+            Some(_) => self.escape(start),
+            None => {
+                self.pos = self.input.len();
+                Token::Incomplete(&self.input[start..])
+            }
+        };
+
+        Some((token, &self.input[start..self.pos]))
+    }
+}
+
+impl<'a> Tokens<'a> {
+    fn csi(&mut self, start: usize) -> Token<'a> {
+        let params_start = start + 2;
+
+        for (offset, c) in self.input[params_start..].char_indices() {
+            if ('\x40'..='\x7E').contains(&c) {
+                self.pos = params_start + offset + 1;
+                return Token::Csi {
+                    params: &self.input[params_start..params_start + offset],
+                    command: c,
+                };
+            }
+        }
+
+        self.pos = self.input.len();
+        Token::Incomplete(&self.input[start..])
+    }
+
+    // OSC may also be terminated by BEL; the other string sequences need ST
+    fn string_sequence(&mut self, start: usize, is_osc: bool) -> Token<'a> {
+        let payload_start = start + 2;
+        let payload = &self.input[payload_start..];
+
+        let mut chars = payload.char_indices().peekable();
+        while let Some((offset, c)) = chars.next() {
+            let terminator_len = match c {
+                '\x07' if is_osc => 1,
+                '\x1B' if chars.peek().map(|&(_, next)| next) == Some('\\') => 2,
+                _ => continue,
+            };
+
+            self.pos = payload_start + offset + terminator_len;
+            let body = &payload[..offset];
+            return if is_osc {
+                Token::Osc(body)
+            } else {
+                Token::Dcs(body)
+            };
+        }
+
+        self.pos = self.input.len();
+        Token::Incomplete(&self.input[start..])
+    }
+
+    // Intermediate bytes (0x20-0x2F) followed by a final byte
+    fn escape(&mut self, start: usize) -> Token<'a> {
+        let body_start = start + 1;
+
+        for (offset, c) in self.input[body_start..].char_indices() {
+            if !('\x20'..='\x2F').contains(&c) {
+                self.pos = body_start + offset + c.len_utf8();
+                return Token::Esc(&self.input[body_start..self.pos]);
+            }
+        }
+
+        self.pos = self.input.len();
+        Token::Incomplete(&self.input[start..])
+    }
+}
+
+// Name for a CSI sequence, keeping any private-mode prefix.
+// Mode numbers are kept for set/reset since each mode is its own feature.
+pub fn csi_name(params: &str, command: char) -> String {
+    let prefix: String = params
+        .chars()
+        .take_while(|c| matches!(c, '?' | '>' | '=' | '<'))
+        .collect();
+
+    if command == 'h' || command == 'l' {
+        format!("CSI {}{}", params, command)
+    } else {
+        format!("CSI {}{}", prefix, command)
+    }
+}
+
+// Short name identifying the kind of an escape sequence, e.g. "CSI m" or
+// "OSC 2". Text and control characters have none.
+pub fn sequence_name(token: &Token) -> Option<String> {
+    match *token {
+        Token::Csi { params, command } => Some(csi_name(params, command)),
+        Token::Osc(payload) => Some(format!(
+            "OSC {}",
+            payload.split(';').next().unwrap_or_default()
+        )),
+        Token::Dcs(_) => Some("DCS".to_string()),
+        Token::Esc(sequence) => Some(format!("ESC {}", sequence)),
+        Token::Incomplete(_) => Some("incomplete".to_string()),
+        Token::Text(_) | Token::Control(_) => None,
+    }
+}
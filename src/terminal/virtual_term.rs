@@ -1,7 +1,64 @@
-use crate::export::bitmap::{create_character_bitmaps, scale_bitmap, CharBitmap};
+use crate::export::bitmap::{self, create_character_bitmaps, scale_bitmap, CharBitmap};
+use crate::terminal::font::OutlineFont;
+use crate::terminal::parser::{self, Token};
 use crate::terminal::TermColor;
 use image::{ImageBuffer, Rgb};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::mem;
+use std::str::FromStr;
+use unicode_width::UnicodeWidthChar;
+
+// Blinking text alternates between shown and hidden on this period
+pub const BLINK_INTERVAL_MS: u128 = 500;
+
+// Titles kept by CSI 22 t before the oldest is dropped, as in xterm
+const TITLE_STACK_LIMIT: usize = 10;
+
+// Drawn in place of an iTerm2 inline image
+const IMAGE_LABEL: &str = "[image]";
+
+// Glyphs drawn for whitespace when showing whitespace
+const TAB_MARK: char = '→';
+const SPACE_MARK: char = '·';
+const LINE_BREAK_MARK: char = '¶';
+
+// How well the bitmap font suits the cell size at a given font size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlyphFit {
+    Fits,
+    // Glyphs are larger than the cell and get clipped by their neighbours
+    Overflows,
+    // Glyphs fill less than half the cell width
+    Cramped,
+}
+
+// Shape the cursor is drawn as in exports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorStyle {
+    // The cell in reverse video
+    Block,
+    Underline,
+    // A thin line along the left edge of the cell
+    Bar,
+    None,
+}
+
+impl FromStr for CursorStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "block" => Ok(CursorStyle::Block),
+            "underline" => Ok(CursorStyle::Underline),
+            "bar" => Ok(CursorStyle::Bar),
+            "none" => Ok(CursorStyle::None),
+            _ => Err(format!(
+                "Invalid cursor '{}' (expected block, underline, bar or none)",
+                s
+            )),
+        }
+    }
+}
 
 // Terminal cell - represents a single character with formatting
 #[derive(Clone)]
@@ -12,6 +69,16 @@ pub struct TermCell {
     pub bold: bool,
     pub italic: bool,
     pub underline: bool,
+    pub blink: bool,
+    // Drawn with the foreground and background swapped (SGR 7)
+    pub reverse: bool,
+    pub strikethrough: bool,
+    // Whitespace that left this cell blank, shown when showing whitespace
+    pub whitespace: Option<char>,
+    // Columns the character takes: 2 for a wide character such as an
+    // ideograph, and 0 for the column covered by the wide character to its
+    // left
+    pub width: u8,
 }
 
 impl Default for TermCell {
@@ -31,29 +98,96 @@ impl Default for TermCell {
             bold: false,
             italic: false,
             underline: false,
+            blink: false,
+            reverse: false,
+            strikethrough: false,
+            whitespace: None,
+            width: 1,
         }
     }
 }
 
+// Cursor position and text attributes stored by DECSC (ESC 7 or CSI s)
+#[derive(Clone, Copy)]
+struct SavedCursor {
+    x: usize,
+    y: usize,
+    fg: TermColor,
+    bg: TermColor,
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    blink: bool,
+    reverse: bool,
+    strikethrough: bool,
+    origin_mode: bool,
+}
+
 // Virtual terminal to process ANSI escape sequences
 pub struct VirtualTerminal {
     width: usize,
     height: usize,
     cells: Vec<Vec<TermCell>>,
+    // Rows whose text ran off the right edge and continued on the next row
+    wrapped: Vec<bool>,
     cursor_x: usize,
     cursor_y: usize,
+    // Rows that scroll, set by DECSTBM (CSI top;bottom r); the whole screen
+    // unless a program narrows it
+    scroll_top: usize,
+    scroll_bottom: usize,
+    // DECOM (CSI ?6h): cursor positions count from the top of the scroll
+    // region and stay inside it
+    origin_mode: bool,
+    saved_cursor: Option<SavedCursor>,
+    // DECTCEM (CSI ?25h/l): whether programs want the cursor shown
+    cursor_visible: bool,
+    // The normal screen's cells and wrapped rows, kept aside while a
+    // full-screen program draws on the alternate screen
+    primary_screen: Option<(Vec<Vec<TermCell>>, Vec<bool>)>,
     current_fg: TermColor,
     current_bg: TermColor,
     bold: bool,
     italic: bool,
     underline: bool,
-    dark_theme: bool,
+    blink: bool,
+    reverse: bool,
+    strikethrough: bool,
+    default_fg: TermColor,
+    default_bg: TermColor,
+    // Window title set through OSC 0/2
+    title: Option<String>,
+    // Titles saved by CSI 22 t, most recent last
+    title_stack: Vec<Option<String>>,
+    // Unfinished escape sequence carried over to the next chunk of output
+    pending: String,
+    // BEL characters seen since the last call to take_bells
+    bells: usize,
+    // Columns and rows reached by anything visible drawn so far
+    extent: (usize, usize),
+    // Distance between tab stops
+    tab_width: usize,
+    // Draw markers for tabs, trailing spaces and line breaks
+    show_whitespace: bool,
+    // Where a carriage return left the cursor, so a following line feed
+    // marks the end of the line rather than its start
+    line_end: Option<(usize, usize)>,
+    // Whether to tally ignored sequences, and the tally so far
+    strict: bool,
+    unhandled: BTreeMap<String, usize>,
     // Character bitmap cache
     char_bitmaps: HashMap<char, CharBitmap>,
+    // Font drawn instead of the bitmaps, for the characters it has
+    font: Option<OutlineFont>,
 }
 
 impl VirtualTerminal {
+    // Callers validate dimensions up front; a zero size is bumped to one cell
+    // so the cursor arithmetic always has a cell to land on
     pub fn new(width: usize, height: usize, dark_theme: bool) -> Self {
+        let width = width.max(1);
+        let height = height.max(1);
+
         let default_fg = if dark_theme {
             TermColor {
                 r: 240,
@@ -86,10 +220,11 @@ impl VirtualTerminal {
         for _ in 0..height {
             let mut row = Vec::with_capacity(width);
             for _ in 0..width {
-                let mut cell = TermCell::default();
-                cell.fg_color = default_fg;
-                cell.bg_color = default_bg;
-                row.push(cell);
+                row.push(TermCell {
+                    fg_color: default_fg,
+                    bg_color: default_bg,
+                    ..TermCell::default()
+                });
             }
             cells.push(row);
         }
@@ -100,149 +235,385 @@ impl VirtualTerminal {
             width,
             height,
             cells,
+            wrapped: vec![false; height],
             cursor_x: 0,
             cursor_y: 0,
+            scroll_top: 0,
+            scroll_bottom: height - 1,
+            origin_mode: false,
+            saved_cursor: None,
+            cursor_visible: true,
+            primary_screen: None,
             current_fg: default_fg,
             current_bg: default_bg,
             bold: false,
             italic: false,
             underline: false,
-            dark_theme,
+            blink: false,
+            reverse: false,
+            strikethrough: false,
+            default_fg,
+            default_bg,
+            title: None,
+            title_stack: Vec::new(),
+            pending: String::new(),
+            bells: 0,
+            extent: (0, 0),
+            tab_width: 8,
+            show_whitespace: false,
+            line_end: None,
+            strict: false,
+            unhandled: BTreeMap::new(),
             char_bitmaps,
+            font: None,
         }
     }
 
     pub fn process_content(&mut self, content: &str) {
-        let mut chars = content.chars().peekable();
-
-        while let Some(c) = chars.next() {
-            match c {
-                '\x1B' => {
-                    if let Some('[') = chars.next() {
-                        let mut sequence = String::new();
-
-                        while let Some(&next) = chars.peek() {
-                            if next.is_ascii_alphabetic() {
-                                let command = chars.next().unwrap();
-                                self.process_csi_sequence(&sequence, command);
-                                break;
-                            } else {
-                                sequence.push(chars.next().unwrap());
-                            }
-                        }
-                    }
-                }
-                '\n' => {
-                    self.cursor_x = 0;
-                    self.cursor_y = (self.cursor_y + 1) % self.height;
+        // A long string sequence such as an inline image spans many chunks.
+        // Until a chunk could end it there's nothing to parse, so it's only
+        // collected rather than scanned again from the start each time.
+        let in_string = matches!(
+            self.pending.as_bytes(),
+            [0x1B, b']' | b'P' | b'X' | b'^' | b'_', ..]
+        );
+        if in_string
+            && !content.contains(['\x07', '\x1B'])
+            && !(self.pending.ends_with('\x1B') && content.starts_with('\\'))
+        {
+            self.pending.push_str(content);
+            return;
+        }
 
-                    if self.cursor_y == 0 {
-                        self.scroll_up();
-                        self.cursor_y = self.height - 1;
-                    }
+        // Output is captured in chunks, so an escape sequence cut off at the
+        // end of one frame is completed by the start of the next
+        let content = if self.pending.is_empty() {
+            content.to_string()
+        } else {
+            mem::take(&mut self.pending) + content
+        };
+
+        for (token, _) in parser::tokenize(&content) {
+            let line_end = self.line_end.take();
+            match token {
+                Token::Csi { params, command } => self.process_csi_sequence(params, command),
+                Token::Osc(payload) => self.process_osc_sequence(payload),
+                Token::Control('\n') => {
+                    let (x, y) = line_end.unwrap_or((self.cursor_x, self.cursor_y));
+                    self.mark_whitespace(x, y, LINE_BREAK_MARK);
+                    self.wrapped[self.cursor_y] = false;
+                    self.cursor_x = 0;
+                    self.line_feed();
                 }
-                '\r' => {
+                Token::Control('\r') => {
+                    self.line_end = Some((self.cursor_x, self.cursor_y));
                     self.cursor_x = 0;
                 }
-                '\t' => {
-                    self.cursor_x = (self.cursor_x + 8) & !7;
+                Token::Control('\t') => {
+                    self.mark_whitespace(self.cursor_x, self.cursor_y, TAB_MARK);
+                    self.cursor_x = self.next_tab_stop(self.cursor_x);
                     if self.cursor_x >= self.width {
                         self.cursor_x = 0;
-                        self.cursor_y = (self.cursor_y + 1) % self.height;
+                        self.line_feed();
                     }
                 }
-                '\x08' => {
+                // The bell is audible only and leaves the screen alone
+                Token::Control('\x07') => self.bells += 1,
+                Token::Control('\x08') => {
                     if self.cursor_x > 0 {
                         self.cursor_x -= 1;
+                    } else if self.cursor_y > 0 && self.wrapped[self.cursor_y - 1] {
+                        // Reverse-wrap onto the end of the row this one continues
+                        self.cursor_y -= 1;
+                        self.cursor_x = self.width - 1;
                     }
                 }
-                _ => {
-                    if self.cursor_x < self.width && self.cursor_y < self.height {
-                        self.cells[self.cursor_y][self.cursor_x] = TermCell {
-                            character: c,
-                            fg_color: self.current_fg,
-                            bg_color: self.current_bg,
-                            bold: self.bold,
-                            italic: self.italic,
-                            underline: self.underline,
-                        };
-
-                        self.cursor_x += 1;
-                        if self.cursor_x >= self.width {
-                            self.cursor_x = 0;
-                            self.cursor_y = (self.cursor_y + 1) % self.height;
-
-                            if self.cursor_y == 0 {
-                                self.scroll_up();
-                                self.cursor_y = self.height - 1;
-                            }
-                        }
+                Token::Text(c) => self.put_char(c),
+                Token::Incomplete(raw) => self.pending = raw.to_string(),
+                Token::Control(c) => self.note_unhandled(format!("C0 0x{:02X}", c as u32)),
+                Token::Esc("7") => self.save_cursor(),
+                Token::Esc("8") => self.restore_cursor(),
+                // Index, next line and reverse index scroll at the edges of
+                // the scroll region like a line feed does
+                Token::Esc("D") => self.line_feed(),
+                Token::Esc("E") => {
+                    self.cursor_x = 0;
+                    self.line_feed();
+                }
+                Token::Esc("M") => self.reverse_line_feed(),
+                Token::Esc(sequence) => self.note_unhandled(format!("ESC {}", sequence)),
+                Token::Dcs(_) => self.note_unhandled("DCS"),
+            }
+        }
+    }
+
+    fn put_char(&mut self, c: char) {
+        // Combining marks and other zero-width characters have nothing to
+        // draw in a cell of their own
+        let columns = c.width().unwrap_or(1);
+        if columns == 0 {
+            return;
+        }
+        let wide = columns == 2 && self.width > 1;
+
+        if self.cursor_x < self.width && self.cursor_y < self.height {
+            // A wide character that doesn't fit at the end of the line
+            // starts the next one
+            if wide && self.cursor_x + 1 == self.width {
+                self.wrapped[self.cursor_y] = true;
+                self.cursor_x = 0;
+                self.line_feed();
+            }
+
+            let (x, y) = (self.cursor_x, self.cursor_y);
+            if wide {
+                self.set_cell(x + 1, y, ' ');
+                self.set_cell(x, y, c);
+                self.cells[y][x].width = 2;
+                self.cells[y][x + 1].width = 0;
+                self.cells[y][x + 1].whitespace = None;
+                self.extent.0 = self.extent.0.max(x + 2);
+                self.cursor_x += 2;
+            } else {
+                self.set_cell(x, y, c);
+                self.cursor_x += 1;
+            }
+
+            if self.cursor_x >= self.width {
+                self.wrapped[self.cursor_y] = true;
+                self.cursor_x = 0;
+                self.line_feed();
+            }
+        }
+    }
+
+    // Write a character with the current attributes without moving the cursor
+    fn set_cell(&mut self, x: usize, y: usize, c: char) {
+        // Overwriting either half of a wide character blanks the other half
+        match self.cells[y][x].width {
+            0 if x > 0 => self.clear_cell(y, x - 1),
+            2 => self.clear_cell(y, x + 1),
+            _ => {}
+        }
+
+        self.cells[y][x] = TermCell {
+            character: c,
+            fg_color: self.current_fg,
+            bg_color: self.current_bg,
+            bold: self.bold,
+            italic: self.italic,
+            underline: self.underline,
+            blink: self.blink,
+            reverse: self.reverse,
+            strikethrough: self.strikethrough,
+            whitespace: (c == ' ').then_some(SPACE_MARK),
+            width: 1,
+        };
+        if c != ' ' || self.current_bg != self.default_bg || self.reverse {
+            self.extent = (self.extent.0.max(x + 1), self.extent.1.max(y + 1));
+        }
+    }
+
+    // OSC 0 and 2 set the window title and OSC 1337 File= is an iTerm2
+    // inline image; other operating system commands have no effect on the
+    // rendered screen
+    fn process_osc_sequence(&mut self, payload: &str) {
+        if let Some((command, text)) = payload.split_once(';') {
+            if command == "0" || command == "2" {
+                self.title = if text.is_empty() {
+                    None
+                } else {
+                    Some(text.to_string())
+                };
+                return;
+            }
+
+            if command == "1337" {
+                if let Some(file) = text.strip_prefix("File=") {
+                    self.inline_image(file);
+                    return;
+                }
+            }
+        }
+
+        let command = payload.split(';').next().unwrap_or_default();
+        self.note_unhandled(format!("OSC {}", command));
+    }
+
+    // The image data isn't decoded. An inline image is shown as a box the
+    // size it asked for (or a one-line label when that isn't given in cells),
+    // leaving the cursor after it as iTerm2 does. Files sent without
+    // inline=1 are downloads and leave the screen alone.
+    fn inline_image(&mut self, file: &str) {
+        let arguments = file
+            .split_once(':')
+            .map_or(file, |(arguments, _)| arguments);
+        let argument = |name: &str| {
+            arguments
+                .split(';')
+                .find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+        };
+        if argument("inline") != Some("1") {
+            return;
+        }
+
+        // Sizes can also be in pixels, percent or "auto"
+        let cells = |name: &str| {
+            argument(name)
+                .and_then(|value| value.parse::<usize>().ok())
+                .filter(|&n| n > 0)
+        };
+        let columns = cells("width").unwrap_or(IMAGE_LABEL.len()).min(self.width);
+        let rows = cells("height").unwrap_or(1).min(self.height);
+
+        let left = self.cursor_x.min(self.width - 1);
+        for row in 0..rows {
+            if row > 0 {
+                self.line_feed();
+            }
+
+            for column in 0..columns.min(self.width - left) {
+                let c = if rows >= 3 && columns >= 3 {
+                    let edge_x = column == 0 || column == columns - 1;
+                    let edge_y = row == 0 || row == rows - 1;
+                    match (edge_x, edge_y) {
+                        (true, true) => '+',
+                        (false, true) => '-',
+                        (true, false) => '|',
+                        (false, false) => ' ',
                     }
+                } else {
+                    IMAGE_LABEL.chars().nth(column).unwrap_or(' ')
+                };
+                self.set_cell(left + column, self.cursor_y, c);
+            }
+        }
+
+        // Label the middle of a box when there's room inside it
+        if rows >= 3 && columns >= IMAGE_LABEL.len() + 2 {
+            let y = self.cursor_y.saturating_sub(rows / 2);
+            let x = left + (columns - IMAGE_LABEL.len()) / 2;
+            for (i, c) in IMAGE_LABEL.chars().enumerate() {
+                if x + i < self.width {
+                    self.set_cell(x + i, y, c);
                 }
             }
         }
+
+        self.cursor_x = (left + columns).min(self.width - 1);
+    }
+
+    // Number of bells rung since the last call, so a player can pass them on
+    pub fn take_bells(&mut self) -> usize {
+        mem::take(&mut self.bells)
+    }
+
+    // The grid of cells, row by row
+    pub fn cells(&self) -> &[Vec<TermCell>] {
+        &self.cells
+    }
+
+    // Furthest column and row that anything visible has been drawn in, as a
+    // size in cells. Unlike used_region this covers everything since the
+    // terminal was created, including text that has since been cleared.
+    pub fn content_extent(&self) -> (usize, usize) {
+        self.extent
+    }
+
+    // Window title most recently set by the program, if any
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
     }
 
     fn process_csi_sequence(&mut self, sequence: &str, command: char) {
+        // SGR mouse reports (CSI < button;x;y M/m) are terminal input that
+        // can end up in a capture; they never affect the screen. Older X10
+        // style reports carry raw bytes after CSI M and can't be told apart
+        // from output, so they are not filtered.
+        if sequence.starts_with('<') && (command == 'M' || command == 'm') {
+            return;
+        }
+
+        if let Some(modes) = sequence.strip_prefix('?') {
+            if command == 'h' || command == 'l' {
+                self.set_private_modes(modes, command);
+                return;
+            }
+        }
+
         match command {
             'm' => {
+                // An empty parameter is a 0, so a bare CSI m resets. A reset
+                // can start a longer list, e.g. CSI 0;1;31m.
                 let params: Vec<&str> = sequence.split(';').collect();
 
-                if params.is_empty() || params[0].is_empty() || params[0] == "0" {
-                    self.reset_text_attributes();
-                } else {
-                    let mut i = 0;
-                    while i < params.len() {
-                        let param = params[i].parse::<u8>().unwrap_or(0);
-
-                        match param {
-                            0 => self.reset_text_attributes(),
-                            1 => self.bold = true,
-                            3 => self.italic = true,
-                            4 => self.underline = true,
-                            30..=37 => self.set_color(param - 30, true),
-                            40..=47 => self.set_color(param - 40, false),
-                            90..=97 => self.set_bright_color(param - 90, true),
-                            100..=107 => self.set_bright_color(param - 100, false),
-                            38 => {
-                                if i + 1 < params.len() {
-                                    let mode = params[i + 1].parse::<u8>().unwrap_or(0);
-                                    if mode == 5 && i + 2 < params.len() {
-                                        let color_idx = params[i + 2].parse::<u8>().unwrap_or(0);
-                                        self.set_256_color(color_idx, true);
-                                        i += 2;
-                                    } else if mode == 2 && i + 4 < params.len() {
-                                        let r = params[i + 2].parse::<u8>().unwrap_or(0);
-                                        let g = params[i + 3].parse::<u8>().unwrap_or(0);
-                                        let b = params[i + 4].parse::<u8>().unwrap_or(0);
-                                        self.current_fg = TermColor { r, g, b };
-                                        i += 4;
-                                    }
+                let mut i = 0;
+                while i < params.len() {
+                    let param = params[i].parse::<u8>().unwrap_or(0);
+
+                    match param {
+                        0 => self.reset_text_attributes(),
+                        1 => self.bold = true,
+                        3 => self.italic = true,
+                        // Double underline (21) is drawn as a single one
+                        4 | 21 => self.underline = true,
+                        5 | 6 => self.blink = true,
+                        7 => self.reverse = true,
+                        9 => self.strikethrough = true,
+                        // Targeted resets end one attribute and leave the rest
+                        22 => self.bold = false,
+                        23 => self.italic = false,
+                        24 => self.underline = false,
+                        25 => self.blink = false,
+                        27 => self.reverse = false,
+                        // Concealed text isn't hidden, so there's nothing to
+                        // turn off
+                        28 => {}
+                        29 => self.strikethrough = false,
+                        30..=37 => self.set_color(param - 30, true),
+                        39 => self.current_fg = self.default_fg,
+                        40..=47 => self.set_color(param - 40, false),
+                        49 => self.current_bg = self.default_bg,
+                        90..=97 => self.set_bright_color(param - 90, true),
+                        100..=107 => self.set_bright_color(param - 100, false),
+                        38 => {
+                            if i + 1 < params.len() {
+                                let mode = params[i + 1].parse::<u8>().unwrap_or(0);
+                                if mode == 5 && i + 2 < params.len() {
+                                    let color_idx = params[i + 2].parse::<u8>().unwrap_or(0);
+                                    self.set_256_color(color_idx, true);
+                                    i += 2;
+                                } else if mode == 2 && i + 4 < params.len() {
+                                    let r = params[i + 2].parse::<u8>().unwrap_or(0);
+                                    let g = params[i + 3].parse::<u8>().unwrap_or(0);
+                                    let b = params[i + 4].parse::<u8>().unwrap_or(0);
+                                    self.current_fg = TermColor { r, g, b };
+                                    i += 4;
                                 }
-                                i += 1;
                             }
-                            48 => {
-                                if i + 1 < params.len() {
-                                    let mode = params[i + 1].parse::<u8>().unwrap_or(0);
-                                    if mode == 5 && i + 2 < params.len() {
-                                        let color_idx = params[i + 2].parse::<u8>().unwrap_or(0);
-                                        self.set_256_color(color_idx, false);
-                                        i += 2;
-                                    } else if mode == 2 && i + 4 < params.len() {
-                                        let r = params[i + 2].parse::<u8>().unwrap_or(0);
-                                        let g = params[i + 3].parse::<u8>().unwrap_or(0);
-                                        let b = params[i + 4].parse::<u8>().unwrap_or(0);
-                                        self.current_bg = TermColor { r, g, b };
-                                        i += 4;
-                                    }
+                            i += 1;
+                        }
+                        48 => {
+                            if i + 1 < params.len() {
+                                let mode = params[i + 1].parse::<u8>().unwrap_or(0);
+                                if mode == 5 && i + 2 < params.len() {
+                                    let color_idx = params[i + 2].parse::<u8>().unwrap_or(0);
+                                    self.set_256_color(color_idx, false);
+                                    i += 2;
+                                } else if mode == 2 && i + 4 < params.len() {
+                                    let r = params[i + 2].parse::<u8>().unwrap_or(0);
+                                    let g = params[i + 3].parse::<u8>().unwrap_or(0);
+                                    let b = params[i + 4].parse::<u8>().unwrap_or(0);
+                                    self.current_bg = TermColor { r, g, b };
+                                    i += 4;
                                 }
-                                i += 1;
                             }
-                            _ => {}
+                            i += 1;
                         }
-
-                        i += 1;
+                        _ => self.note_unhandled(format!("SGR {}", params[i])),
                     }
+
+                    i += 1;
                 }
             }
             'A' => {
@@ -255,11 +626,11 @@ impl VirtualTerminal {
             }
             'B' => {
                 let count = sequence.parse::<usize>().unwrap_or(1);
-                self.cursor_y = (self.cursor_y + count).min(self.height - 1);
+                self.cursor_y = self.cursor_y.saturating_add(count).min(self.height - 1);
             }
             'C' => {
                 let count = sequence.parse::<usize>().unwrap_or(1);
-                self.cursor_x = (self.cursor_x + count).min(self.width - 1);
+                self.cursor_x = self.cursor_x.saturating_add(count).min(self.width - 1);
             }
             'D' => {
                 let count = sequence.parse::<usize>().unwrap_or(1);
@@ -269,9 +640,21 @@ impl VirtualTerminal {
                     self.cursor_x = 0;
                 }
             }
+            'I' => {
+                let count = sequence.parse::<usize>().unwrap_or(1).max(1);
+                for _ in 0..count.min(self.width) {
+                    self.cursor_x = self.next_tab_stop(self.cursor_x).min(self.width - 1);
+                }
+            }
+            'Z' => {
+                let count = sequence.parse::<usize>().unwrap_or(1).max(1);
+                for _ in 0..count.min(self.width) {
+                    self.cursor_x = self.previous_tab_stop(self.cursor_x);
+                }
+            }
             'H' | 'f' => {
                 let parts: Vec<&str> = sequence.split(';').collect();
-                let row = if parts.len() > 0 && !parts[0].is_empty() {
+                let row = if !parts.is_empty() && !parts[0].is_empty() {
                     parts[0].parse::<usize>().unwrap_or(1).saturating_sub(1)
                 } else {
                     0
@@ -283,9 +666,35 @@ impl VirtualTerminal {
                     0
                 };
 
-                self.cursor_y = row.min(self.height - 1);
-                self.cursor_x = col.min(self.width - 1);
+                self.move_cursor_to(row, col);
             }
+            'd' => {
+                let row = sequence.parse::<usize>().unwrap_or(1).saturating_sub(1);
+                self.move_cursor_to(row, self.cursor_x);
+            }
+            // Private sequences ending in r (restoring DEC modes) aren't
+            // scroll regions
+            'r' if !sequence.starts_with('?') => {
+                let mut params = sequence
+                    .split(';')
+                    .map(|param| param.parse::<usize>().ok().filter(|&n| n > 0));
+                let top = params.next().flatten().unwrap_or(1) - 1;
+                let bottom = params
+                    .next()
+                    .flatten()
+                    .unwrap_or(self.height)
+                    .min(self.height)
+                    - 1;
+
+                // A region needs at least two rows; anything else is ignored
+                if top < bottom {
+                    self.scroll_top = top;
+                    self.scroll_bottom = bottom;
+                    self.move_cursor_to(0, 0);
+                }
+            }
+            's' if sequence.is_empty() => self.save_cursor(),
+            'u' if sequence.is_empty() => self.restore_cursor(),
             'J' => {
                 let mode = sequence.parse::<u8>().unwrap_or(0);
 
@@ -299,6 +708,7 @@ impl VirtualTerminal {
                             for x in 0..self.width {
                                 self.clear_cell(y, x);
                             }
+                            self.wrapped[y] = false;
                         }
                     }
                     1 => {
@@ -306,19 +716,24 @@ impl VirtualTerminal {
                             for x in 0..self.width {
                                 self.clear_cell(y, x);
                             }
+                            self.wrapped[y] = false;
                         }
 
                         for x in 0..=self.cursor_x {
                             self.clear_cell(self.cursor_y, x);
                         }
                     }
-                    2 | 3 => {
+                    2 => {
                         for y in 0..self.height {
                             for x in 0..self.width {
                                 self.clear_cell(y, x);
                             }
+                            self.wrapped[y] = false;
                         }
                     }
+                    // Clears the scrollback and leaves the screen alone. No
+                    // scrollback is kept, so there's nothing to do.
+                    3 => {}
                     _ => {}
                 }
             }
@@ -344,40 +759,330 @@ impl VirtualTerminal {
                     _ => {}
                 }
             }
+            // Insert and delete lines within the scroll region, from the
+            // cursor row down; rows pushed past the region are lost
+            'L' | 'M' => {
+                let (top, bottom) = (self.scroll_top, self.scroll_bottom);
+                let y = self.cursor_y;
+                if y < top || y > bottom {
+                    return;
+                }
+
+                let count = sequence
+                    .parse::<usize>()
+                    .unwrap_or(1)
+                    .clamp(1, bottom - y + 1);
+                let blank_rows = if command == 'L' {
+                    self.cells[y..=bottom].rotate_right(count);
+                    self.wrapped[y..=bottom].rotate_right(count);
+                    y..y + count
+                } else {
+                    self.cells[y..=bottom].rotate_left(count);
+                    self.wrapped[y..=bottom].rotate_left(count);
+                    bottom + 1 - count..bottom + 1
+                };
+                for row in blank_rows {
+                    self.wrapped[row] = false;
+                    for x in 0..self.width {
+                        self.clear_cell(row, x);
+                    }
+                }
+
+                self.cursor_x = 0;
+                self.extend_to_used();
+            }
+            // Insert blanks at the cursor or delete the characters there,
+            // shifting the rest of the row
+            '@' | 'P' => {
+                let (x, y) = (self.cursor_x.min(self.width - 1), self.cursor_y);
+                let count = sequence
+                    .parse::<usize>()
+                    .unwrap_or(1)
+                    .clamp(1, self.width - x);
+
+                let blank_columns = if command == '@' {
+                    self.cells[y][x..].rotate_right(count);
+                    x..x + count
+                } else {
+                    self.cells[y][x..].rotate_left(count);
+                    self.width - count..self.width
+                };
+                for column in blank_columns {
+                    self.clear_cell(y, column);
+                }
+
+                self.repair_wide(y);
+                self.extend_to_used();
+            }
+            't' => self.window_operation(sequence),
             _ => {
                 // Unsupported command, ignore
+                self.note_unhandled(parser::csi_name(sequence, command));
             }
         }
     }
 
+    // Window manipulation (CSI Ps ; ... t). Saving and restoring the title
+    // is tracked so a restored title shows up in exports. Moving, resizing
+    // and size or state reports don't change what's on screen, and a capture
+    // has no one to answer queries, so they're consumed.
+    fn window_operation(&mut self, sequence: &str) {
+        let mut params = sequence.split(';').map(|param| param.parse::<u16>().ok());
+        let operation = params.next().flatten();
+        // 0 is the icon and window title, 1 the icon only, 2 the window only
+        let affects_window = params.next().flatten().unwrap_or(0) != 1;
+
+        match operation {
+            Some(22) if affects_window => {
+                if self.title_stack.len() == TITLE_STACK_LIMIT {
+                    self.title_stack.remove(0);
+                }
+                self.title_stack.push(self.title.clone());
+            }
+            Some(23) if affects_window => {
+                if let Some(title) = self.title_stack.pop() {
+                    self.title = title;
+                }
+            }
+            Some(1..) => {}
+            _ => self.note_unhandled(parser::csi_name(sequence, 't')),
+        }
+    }
+
+    // DEC private modes (CSI ? Pm h/l). The ones listed only change how the
+    // terminal handles input or draws its cursor, so they're consumed
+    // without affecting the rendered screen.
+    fn set_private_modes(&mut self, modes: &str, command: char) {
+        for mode in modes.split(';') {
+            match mode {
+                // Application cursor keys and cursor blink
+                "1" | "12" => {}
+                "25" => self.cursor_visible = command == 'h',
+                // Mouse tracking modes and their report encodings
+                "9" | "1000" | "1001" | "1002" | "1003" | "1005" | "1006" | "1015" | "1016" => {}
+                // Focus events and bracketed paste
+                "1004" | "2004" => {}
+                // Alternate screen; 1049 also saves and restores the cursor
+                // around it, and 1048 does only that
+                "47" | "1047" | "1049" => {
+                    if command == 'h' {
+                        if mode == "1049" {
+                            self.save_cursor();
+                        }
+                        self.enter_alternate_screen();
+                    } else {
+                        self.leave_alternate_screen();
+                        if mode == "1049" {
+                            self.restore_cursor();
+                        }
+                    }
+                }
+                "1048" => {
+                    if command == 'h' {
+                        self.save_cursor();
+                    } else {
+                        self.restore_cursor();
+                    }
+                }
+                // Origin mode; switching it either way homes the cursor
+                "6" => {
+                    self.origin_mode = command == 'h';
+                    self.move_cursor_to(0, 0);
+                }
+                _ => self.note_unhandled(format!("CSI ?{}{}", mode, command)),
+            }
+        }
+    }
+
+    // Switch to a blank alternate screen, keeping the normal one to return
+    // to. Entering again while already there just clears it.
+    fn enter_alternate_screen(&mut self) {
+        let blank = vec![vec![self.blank_cell(); self.width]; self.height];
+        let cells = std::mem::replace(&mut self.cells, blank);
+        let wrapped = std::mem::replace(&mut self.wrapped, vec![false; self.height]);
+        if self.primary_screen.is_none() {
+            self.primary_screen = Some((cells, wrapped));
+        }
+        self.line_end = None;
+    }
+
+    fn leave_alternate_screen(&mut self) {
+        if let Some((cells, wrapped)) = self.primary_screen.take() {
+            self.cells = cells;
+            self.wrapped = wrapped;
+            self.line_end = None;
+        }
+    }
+
+    // An empty cell in the theme's default colors
+    fn blank_cell(&self) -> TermCell {
+        TermCell {
+            fg_color: self.default_fg,
+            bg_color: self.default_bg,
+            ..TermCell::default()
+        }
+    }
+
+    // Move to a 0-based row and column given by a positioning command. In
+    // origin mode the row counts from the top of the scroll region and is
+    // kept inside it.
+    fn move_cursor_to(&mut self, row: usize, col: usize) {
+        self.cursor_y = if self.origin_mode {
+            self.scroll_top.saturating_add(row).min(self.scroll_bottom)
+        } else {
+            row.min(self.height - 1)
+        };
+        self.cursor_x = col.min(self.width - 1);
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved_cursor = Some(SavedCursor {
+            x: self.cursor_x,
+            y: self.cursor_y,
+            fg: self.current_fg,
+            bg: self.current_bg,
+            bold: self.bold,
+            italic: self.italic,
+            underline: self.underline,
+            blink: self.blink,
+            reverse: self.reverse,
+            strikethrough: self.strikethrough,
+            origin_mode: self.origin_mode,
+        });
+    }
+
+    // Restoring without a saved cursor homes it and resets the attributes,
+    // as xterm does. The position is clamped in case the screen shrank.
+    fn restore_cursor(&mut self) {
+        match self.saved_cursor {
+            Some(saved) => {
+                self.cursor_x = saved.x.min(self.width - 1);
+                self.cursor_y = saved.y.min(self.height - 1);
+                self.current_fg = saved.fg;
+                self.current_bg = saved.bg;
+                self.bold = saved.bold;
+                self.italic = saved.italic;
+                self.underline = saved.underline;
+                self.blink = saved.blink;
+                self.reverse = saved.reverse;
+                self.strikethrough = saved.strikethrough;
+                self.origin_mode = saved.origin_mode;
+            }
+            None => {
+                self.reset_text_attributes();
+                self.origin_mode = false;
+                self.cursor_x = 0;
+                self.cursor_y = 0;
+            }
+        }
+    }
+
+    // Change the size of the grid, as when the window is resized. Content
+    // stays anchored to the top left, except that rows are dropped from the
+    // top when needed to keep the cursor's row on screen.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        let width = width.max(1);
+        let height = height.max(1);
+
+        let dropped = (self.cursor_y + 1).saturating_sub(height);
+        self.cells.drain(..dropped);
+        self.wrapped.drain(..dropped);
+        self.cursor_y -= dropped;
+
+        let blank = self.blank_cell();
+        fit_grid(&mut self.cells, &mut self.wrapped, width, height, &blank);
+        if let Some((cells, wrapped)) = &mut self.primary_screen {
+            fit_grid(cells, wrapped, width, height, &blank);
+        }
+
+        self.width = width;
+        self.height = height;
+        self.cursor_x = self.cursor_x.min(width - 1);
+        self.scroll_top = 0;
+        self.scroll_bottom = height - 1;
+        if let Some(saved) = &mut self.saved_cursor {
+            saved.y = saved.y.saturating_sub(dropped);
+        }
+        self.extent = (self.extent.0.min(width), self.extent.1.min(height));
+        self.line_end = None;
+    }
+
+    // Tab stops sit at every multiple of the tab width (8 by default)
+    pub fn set_tab_width(&mut self, tab_width: usize) {
+        self.tab_width = tab_width.max(1);
+    }
+
+    // Replace the theme's default colors, repainting the cells drawn in them.
+    // Meant to be called on a fresh terminal, before any content.
+    pub fn set_default_colors(&mut self, fg: Option<TermColor>, bg: Option<TermColor>) {
+        let fg = fg.unwrap_or(self.default_fg);
+        let bg = bg.unwrap_or(self.default_bg);
+
+        for cell in self.cells.iter_mut().flatten() {
+            if cell.fg_color == self.default_fg {
+                cell.fg_color = fg;
+            }
+            if cell.bg_color == self.default_bg {
+                cell.bg_color = bg;
+            }
+        }
+        if self.current_fg == self.default_fg {
+            self.current_fg = fg;
+        }
+        if self.current_bg == self.default_bg {
+            self.current_bg = bg;
+        }
+
+        self.default_fg = fg;
+        self.default_bg = bg;
+    }
+
+    fn next_tab_stop(&self, x: usize) -> usize {
+        (x / self.tab_width + 1) * self.tab_width
+    }
+
+    fn previous_tab_stop(&self, x: usize) -> usize {
+        x.saturating_sub(1) / self.tab_width * self.tab_width
+    }
+
+    // Collect the escape sequences and control characters the terminal
+    // ignores, for diagnosing exports that don't look right
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    // Draw text with a TrueType or OpenType font. Characters the font lacks
+    // still use the built-in bitmaps.
+    pub fn set_font(&mut self, font: OutlineFont) {
+        self.font = Some(font);
+    }
+
+    // Render tabs, trailing spaces and line breaks as visible markers. Only
+    // the image changes; the text is laid out as usual.
+    pub fn set_show_whitespace(&mut self, show_whitespace: bool) {
+        self.show_whitespace = show_whitespace;
+    }
+
+    // How often each ignored sequence was seen while strict mode was on
+    pub fn unhandled_sequences(&self) -> &BTreeMap<String, usize> {
+        &self.unhandled
+    }
+
+    fn note_unhandled(&mut self, name: impl Into<String>) {
+        if self.strict {
+            *self.unhandled.entry(name.into()).or_insert(0) += 1;
+        }
+    }
+
     fn reset_text_attributes(&mut self) {
         self.bold = false;
         self.italic = false;
         self.underline = false;
-
-        if self.dark_theme {
-            self.current_fg = TermColor {
-                r: 240,
-                g: 240,
-                b: 240,
-            };
-            self.current_bg = TermColor {
-                r: 30,
-                g: 30,
-                b: 30,
-            };
-        } else {
-            self.current_fg = TermColor {
-                r: 30,
-                g: 30,
-                b: 30,
-            };
-            self.current_bg = TermColor {
-                r: 245,
-                g: 245,
-                b: 245,
-            };
-        }
+        self.blink = false;
+        self.reverse = false;
+        self.strikethrough = false;
+        self.current_fg = self.default_fg;
+        self.current_bg = self.default_bg;
     }
 
     fn set_color(&mut self, color_index: u8, is_foreground: bool) {
@@ -596,22 +1301,215 @@ impl VirtualTerminal {
             self.cells[y][x].bold = false;
             self.cells[y][x].italic = false;
             self.cells[y][x].underline = false;
+            self.cells[y][x].blink = false;
+            self.cells[y][x].reverse = false;
+            self.cells[y][x].strikethrough = false;
+            self.cells[y][x].whitespace = None;
+            self.cells[y][x].width = 1;
         }
     }
 
-    fn scroll_up(&mut self) {
-        // Move all lines up one position
-        for y in 1..self.height {
-            self.cells[y - 1] = self.cells[y].clone();
+    // Blank the halves of wide characters left without their other half
+    // once a row has been shifted
+    fn repair_wide(&mut self, y: usize) {
+        for x in 0..self.width {
+            let orphaned = match self.cells[y][x].width {
+                0 => x == 0 || self.cells[y][x - 1].width != 2,
+                2 => x + 1 == self.width || self.cells[y][x + 1].width != 0,
+                _ => false,
+            };
+            if orphaned {
+                self.clear_cell(y, x);
+            }
+        }
+    }
+
+    // Grow the extent to cover whatever was moved past it
+    fn extend_to_used(&mut self) {
+        if let Some((_, _, max_x, max_y)) = self.used_region() {
+            self.extent = (self.extent.0.max(max_x + 1), self.extent.1.max(max_y + 1));
+        }
+    }
+
+    // Note the whitespace that passed over a blank cell
+    fn mark_whitespace(&mut self, x: usize, y: usize, mark: char) {
+        if let Some(cell) = self.cells.get_mut(y).and_then(|row| row.get_mut(x)) {
+            if cell.character == ' ' {
+                cell.whitespace = Some(mark);
+            }
+        }
+    }
+
+    // Move down a row, scrolling when on the bottom row of the scroll
+    // region. Below the region the cursor stops at the bottom of the screen.
+    fn line_feed(&mut self) {
+        if self.cursor_y == self.scroll_bottom {
+            self.scroll_up();
+        } else if self.cursor_y + 1 < self.height {
+            self.cursor_y += 1;
+        }
+    }
+
+    // Move up a row, scrolling the region down when on its top row
+    fn reverse_line_feed(&mut self) {
+        if self.cursor_y == self.scroll_top {
+            self.scroll_down();
+        } else if self.cursor_y > 0 {
+            self.cursor_y -= 1;
         }
+    }
+
+    fn scroll_up(&mut self) {
+        let (top, bottom) = (self.scroll_top, self.scroll_bottom);
+
+        // Move the region's lines up one position, reusing its top line as
+        // the new bottom line
+        self.cells[top..=bottom].rotate_left(1);
+        self.wrapped[top..=bottom].rotate_left(1);
+        self.wrapped[bottom] = false;
 
         // Clear the bottom line
         for x in 0..self.width {
-            self.clear_cell(self.height - 1, x);
+            self.clear_cell(bottom, x);
+        }
+    }
+
+    fn scroll_down(&mut self) {
+        let (top, bottom) = (self.scroll_top, self.scroll_bottom);
+
+        // Move the region's lines down one position, reusing its bottom line
+        // as the new top line
+        self.cells[top..=bottom].rotate_right(1);
+        self.wrapped[top..=bottom].rotate_right(1);
+        self.wrapped[top] = false;
+
+        for x in 0..self.width {
+            self.clear_cell(top, x);
+        }
+    }
+
+    // Plain text of the visible grid, one line per row with trailing spaces trimmed
+    pub fn screen_text(&self) -> String {
+        self.cells
+            .iter()
+            .map(|row| {
+                let line: String = row
+                    .iter()
+                    .filter(|cell| cell.width != 0)
+                    .map(|cell| cell.character)
+                    .collect();
+                line.trim_end().to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // Like screen_text, but every run of cells with the same style starts
+    // with a readable tag of its colors and attributes, e.g.
+    // "[#f0f0f0/#1e1e1e bold]ls"
+    pub fn styled_screen_text(&self) -> String {
+        self.cells
+            .iter()
+            .map(|row| {
+                let used = row
+                    .iter()
+                    .rposition(|cell| {
+                        cell.character != ' ' || cell.bg_color != self.default_bg || cell.reverse
+                    })
+                    .map_or(0, |last| last + 1);
+
+                let mut line = String::new();
+                let mut current: Option<String> = None;
+                for cell in row[..used].iter().filter(|cell| cell.width != 0) {
+                    let tag = style_tag(cell);
+                    if current.as_ref() != Some(&tag) {
+                        line.push_str(&tag);
+                        current = Some(tag);
+                    }
+                    line.push(cell.character);
+                }
+                line
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // Bounding box (min_x, min_y, max_x, max_y) of the cells that hold text,
+    // a non-default background or reverse video, or None while the screen is
+    // blank
+    pub fn used_region(&self) -> Option<(usize, usize, usize, usize)> {
+        let mut region: Option<(usize, usize, usize, usize)> = None;
+
+        for (y, row) in self.cells.iter().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                if cell.character == ' ' && cell.bg_color == self.default_bg && !cell.reverse {
+                    continue;
+                }
+
+                region = Some(match region {
+                    None => (x, y, x, y),
+                    Some((min_x, min_y, max_x, max_y)) => {
+                        (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+                    }
+                });
+            }
         }
+
+        region
+    }
+
+    // Whether any visible cell has the blink attribute
+    pub fn has_blink(&self) -> bool {
+        self.cells.iter().flatten().any(|cell| cell.blink)
     }
 
-    pub fn render_to_image(&self, font_size: u8) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    // Redraw the visible grid as an ANSI stream with explicit attributes for
+    // every run of cells, leaving the cursor where the terminal has it
+    pub fn render_ansi(&self) -> String {
+        let mut output = String::from("\x1B[H");
+
+        for (y, row) in self.cells.iter().enumerate() {
+            let mut current: Option<&TermCell> = None;
+
+            // A wide character moves the real cursor over the column it covers
+            for cell in row.iter().filter(|cell| cell.width != 0) {
+                let changed = current.is_none_or(|prev| {
+                    prev.fg_color != cell.fg_color
+                        || prev.bg_color != cell.bg_color
+                        || prev.bold != cell.bold
+                        || prev.italic != cell.italic
+                        || prev.underline != cell.underline
+                        || prev.blink != cell.blink
+                        || prev.reverse != cell.reverse
+                        || prev.strikethrough != cell.strikethrough
+                });
+
+                if changed {
+                    output.push_str(&cell_sgr(cell));
+                    current = Some(cell);
+                }
+                output.push(cell.character);
+            }
+
+            output.push_str("\x1B[0m");
+            if y + 1 < self.height {
+                output.push_str("\r\n");
+            }
+        }
+
+        output.push_str(&format!(
+            "\x1B[{};{}H",
+            self.cursor_y + 1,
+            self.cursor_x + 1
+        ));
+        output
+    }
+
+    // Render the grid as it looks `time_ms` into the animation, which decides
+    // whether blinking cells are in their visible phase
+    pub fn render_to_image(&self, font_size: u8, time_ms: u128) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+        let blink_visible = (time_ms / BLINK_INTERVAL_MS).is_multiple_of(2);
+
         // Cell dimensions in pixels
         let cell_width = font_size as u32;
         let cell_height = (font_size as f32 * 2.0) as u32;
@@ -621,77 +1519,783 @@ impl VirtualTerminal {
         let height = (self.height as u32) * cell_height;
         let mut img = ImageBuffer::new(width, height);
 
-        // Scale factor for bitmap adjustment
-        let scale_factor = (font_size as f32 / 8.0).max(1.0) as usize;
-
         // Fill the image with cells
         for y in 0..self.height {
+            // Spaces are only marked after the last visible character
+            let trailing_from = self.cells[y]
+                .iter()
+                .rposition(|cell| cell.character != ' ')
+                .map_or(0, |x| x + 1);
+
             for x in 0..self.width {
                 let cell = &self.cells[y][x];
+                // The column covered by a wide character is drawn with it
+                if cell.width == 0 && x > 0 && self.cells[y][x - 1].width == 2 {
+                    continue;
+                }
+                let (fg, bg) = if cell.reverse {
+                    (cell.bg_color, cell.fg_color)
+                } else {
+                    (cell.fg_color, cell.bg_color)
+                };
 
                 // Calculate pixel positions
                 let px_start = x as u32 * cell_width;
                 let py_start = y as u32 * cell_height;
+                let span = if cell.width == 2 && x + 1 < self.width {
+                    2
+                } else {
+                    1
+                };
+                let span_width = span * cell_width;
 
                 // Draw background
                 for py in py_start..py_start + cell_height {
-                    for px in px_start..px_start + cell_width {
+                    for px in px_start..px_start + span_width {
                         if px < width && py < height {
-                            img.put_pixel(px, py, cell.bg_color.to_rgb());
+                            img.put_pixel(px, py, bg.to_rgb());
                         }
                     }
                 }
 
-                // Draw character using bitmap approach
-                if cell.character != ' ' {
-                    // Get bitmap for this character, or use the default if not available
-                    let bitmap = if let Some(bitmap) = self.char_bitmaps.get(&cell.character) {
-                        bitmap
-                    } else if let Some(bitmap) = self.char_bitmaps.get(&'?') {
-                        // Fallback to question mark for unknown characters
-                        bitmap
-                    } else {
-                        // Skip if we don't have a bitmap at all
-                        continue;
-                    };
-
-                    // Compute scaled bitmap dimensions
-                    let scaled_bitmap = scale_bitmap(bitmap, scale_factor);
-                    let bitmap_width = scaled_bitmap[0].len() as u32;
-                    let bitmap_height = scaled_bitmap.len() as u32;
-
-                    // Center the character in the cell
-                    let offset_x = (cell_width - bitmap_width) / 2;
-                    let offset_y = (cell_height - bitmap_height) / 2;
-
-                    // Draw the character bitmap
-                    for (dy, row) in scaled_bitmap.iter().enumerate() {
-                        for (dx, &pixel) in row.iter().enumerate() {
-                            if pixel {
-                                let px = px_start + offset_x + dx as u32;
-                                let py = py_start + offset_y + dy as u32;
-
-                                if px < width && py < height {
-                                    img.put_pixel(px, py, cell.fg_color.to_rgb());
-                                }
-                            }
-                        }
-                    }
+                // Draw character using bitmap approach; blinking text is
+                // left as plain background during its hidden phase
+                if cell.character != ' ' && (blink_visible || !cell.blink) {
+                    // Centered across both columns of a wide character
+                    let glyph_x = px_start + (span - 1) * cell_width / 2;
+                    self.draw_glyph(&mut img, cell.character, glyph_x, py_start, font_size, fg);
 
                     // If underlined, draw a line at the bottom
                     if cell.underline {
                         let underline_y = py_start + cell_height - 2;
-                        for dx in 0..cell_width {
+                        for dx in 0..span_width {
                             let px = px_start + dx;
                             if px < width && underline_y < height {
-                                img.put_pixel(px, underline_y, cell.fg_color.to_rgb());
+                                img.put_pixel(px, underline_y, fg.to_rgb());
                             }
                         }
                     }
+
+                    // Strikethrough runs through the middle of the cell
+                    if cell.strikethrough {
+                        let strike_y = py_start + cell_height / 2;
+                        for dx in 0..span_width {
+                            let px = px_start + dx;
+                            if px < width && strike_y < height {
+                                img.put_pixel(px, strike_y, fg.to_rgb());
+                            }
+                        }
+                    }
+                }
+
+                if let (true, ' ', Some(mark)) =
+                    (self.show_whitespace, cell.character, cell.whitespace)
+                {
+                    if mark != SPACE_MARK || x >= trailing_from {
+                        self.draw_glyph(
+                            &mut img,
+                            mark,
+                            px_start,
+                            py_start,
+                            font_size,
+                            faint(fg, bg),
+                        );
+                    }
                 }
             }
         }
 
         img
     }
+    // Draw a line of text onto an image using the terminal's glyphs, with
+    // each character occupying one cell starting at the given pixel origin
+    pub fn draw_text(
+        &self,
+        img: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+        text: &str,
+        origin: (u32, u32),
+        font_size: u8,
+        colors: (TermColor, TermColor),
+    ) {
+        let (x, y) = origin;
+        let (fg, bg) = colors;
+        let cell_width = font_size as u32;
+        let cell_height = (font_size as f32 * 2.0) as u32;
+
+        let text_width = text.chars().count() as u32 * cell_width;
+        for py in y..(y + cell_height).min(img.height()) {
+            for px in x..(x + text_width).min(img.width()) {
+                img.put_pixel(px, py, bg.to_rgb());
+            }
+        }
+
+        self.draw_glyphs(img, text, origin, font_size, fg);
+    }
+
+    // Draw just the glyphs of a line of text, leaving the pixels behind them
+    pub fn draw_glyphs(
+        &self,
+        img: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+        text: &str,
+        origin: (u32, u32),
+        font_size: u8,
+        color: TermColor,
+    ) {
+        let (x, y) = origin;
+        let cell_width = font_size as u32;
+
+        for (i, c) in text.chars().enumerate() {
+            if c != ' ' {
+                self.draw_glyph(img, c, x + i as u32 * cell_width, y, font_size, color);
+            }
+        }
+    }
+
+    // Draw the cursor over a rendered image of the grid, unless a program
+    // has hidden it
+    pub fn draw_cursor(
+        &self,
+        img: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+        font_size: u8,
+        style: CursorStyle,
+    ) {
+        if !self.cursor_visible || style == CursorStyle::None {
+            return;
+        }
+
+        let cell_width = font_size as u32;
+        let cell_height = (font_size as f32 * 2.0) as u32;
+        let thickness = glyph_scale(font_size) as u32;
+
+        let x = self.cursor_x.min(self.width - 1);
+        let y = self.cursor_y.min(self.height - 1);
+        let cell = &self.cells[y][x];
+        let (fg, bg) = if cell.reverse {
+            (cell.bg_color, cell.fg_color)
+        } else {
+            (cell.fg_color, cell.bg_color)
+        };
+        let px_start = x as u32 * cell_width;
+        let py_start = y as u32 * cell_height;
+        // On a wide character the cursor covers both its columns
+        let span = if cell.width == 2 && x + 1 < self.width {
+            2
+        } else {
+            1
+        };
+        let span_width = span * cell_width;
+
+        let (left, top, width, height) = match style {
+            CursorStyle::Block => (0, 0, span_width, cell_height),
+            CursorStyle::Underline => (0, cell_height - thickness, span_width, thickness),
+            CursorStyle::Bar => (0, 0, thickness, cell_height),
+            CursorStyle::None => return,
+        };
+        for py in py_start + top..(py_start + top + height).min(img.height()) {
+            for px in px_start + left..(px_start + left + width).min(img.width()) {
+                img.put_pixel(px, py, fg.to_rgb());
+            }
+        }
+
+        // The character under a block cursor shows through in reverse
+        if style == CursorStyle::Block && cell.character != ' ' {
+            let glyph_x = px_start + (span - 1) * cell_width / 2;
+            self.draw_glyph(img, cell.character, glyph_x, py_start, font_size, bg);
+        }
+    }
+
+    // Default foreground and background colors for the current theme
+    pub fn default_colors(&self) -> (TermColor, TermColor) {
+        (self.default_fg, self.default_bg)
+    }
+
+    // How well the scaled glyphs suit the cells at a given font size
+    pub fn glyph_fit(&self, font_size: u8) -> GlyphFit {
+        // Outline glyphs are sized to the cells
+        if self.font.is_some() {
+            return GlyphFit::Fits;
+        }
+
+        let scale = glyph_scale(font_size) as u32;
+        let (glyph_width, glyph_height) =
+            self.char_bitmaps
+                .iter()
+                .fold((0, 0), |(width, height), (&character, bitmap)| {
+                    let bitmap_width = bitmap.iter().map(|row| row.len()).max().unwrap_or(0);
+                    let bitmap_height = bitmap.len() + bitmap::descent(character);
+                    (
+                        width.max(bitmap_width as u32),
+                        height.max(bitmap_height as u32),
+                    )
+                });
+
+        let cell_width = font_size as u32;
+        let cell_height = (font_size as f32 * 2.0) as u32;
+
+        if glyph_width * scale > cell_width || glyph_height * scale > cell_height {
+            GlyphFit::Overflows
+        } else if glyph_width * scale * 2 < cell_width {
+            GlyphFit::Cramped
+        } else {
+            GlyphFit::Fits
+        }
+    }
+
+    fn draw_glyph(
+        &self,
+        img: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+        character: char,
+        px_start: u32,
+        py_start: u32,
+        font_size: u8,
+        color: TermColor,
+    ) {
+        if let Some(font) = self.font.as_ref().filter(|font| font.has_glyph(character)) {
+            font.draw_glyph(img, character, px_start, py_start, font_size, color);
+            return;
+        }
+
+        let cell_width = font_size as u32;
+        let cell_height = (font_size as f32 * 2.0) as u32;
+
+        let scale_factor = glyph_scale(font_size);
+
+        // Get bitmap for this character, or use the default if not available
+        let (bitmap, descent) = if let Some(bitmap) = self.char_bitmaps.get(&character) {
+            (bitmap, bitmap::descent(character))
+        } else if let Some(bitmap) = self.char_bitmaps.get(&'?') {
+            // Fallback to question mark for unknown characters
+            (bitmap, 0)
+        } else {
+            // Skip if we don't have a bitmap at all
+            return;
+        };
+
+        // Compute scaled bitmap dimensions
+        let scaled_bitmap = scale_bitmap(bitmap, scale_factor);
+        let bitmap_width = scaled_bitmap[0].len() as u32;
+        let bitmap_height = scaled_bitmap.len() as u32;
+
+        // Center the character in the cell, then drop descenders below the
+        // shared baseline
+        let offset_x = cell_width.saturating_sub(bitmap_width) / 2;
+        let offset_y =
+            cell_height.saturating_sub(bitmap_height) / 2 + (descent * scale_factor) as u32;
+
+        // Draw the character bitmap
+        for (dy, row) in scaled_bitmap.iter().enumerate() {
+            for (dx, &pixel) in row.iter().enumerate() {
+                if pixel {
+                    let px = px_start + offset_x + dx as u32;
+                    let py = py_start + offset_y + dy as u32;
+
+                    if px < img.width() && py < img.height() {
+                        img.put_pixel(px, py, color.to_rgb());
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Cut or pad a grid of cells and its wrapped rows to the given size,
+// keeping the top left
+fn fit_grid(
+    cells: &mut Vec<Vec<TermCell>>,
+    wrapped: &mut Vec<bool>,
+    width: usize,
+    height: usize,
+    blank: &TermCell,
+) {
+    cells.resize(height, vec![blank.clone(); width]);
+    for row in cells.iter_mut() {
+        row.resize(width, blank.clone());
+    }
+    wrapped.resize(height, false);
+}
+
+// Color halfway between the text and its background, for whitespace markers
+fn faint(fg: TermColor, bg: TermColor) -> TermColor {
+    let mix = |a: u8, b: u8| ((a as u16 + b as u16) / 2) as u8;
+    TermColor {
+        r: mix(fg.r, bg.r),
+        g: mix(fg.g, bg.g),
+        b: mix(fg.b, bg.b),
+    }
+}
+
+// Whole-pixel factor the glyph bitmaps are scaled up by
+fn glyph_scale(font_size: u8) -> usize {
+    (font_size as f32 / 8.0).max(1.0) as usize
+}
+
+// Readable description of a cell's colors and attributes
+fn style_tag(cell: &TermCell) -> String {
+    let mut tag = format!(
+        "[#{:02x}{:02x}{:02x}/#{:02x}{:02x}{:02x}",
+        cell.fg_color.r,
+        cell.fg_color.g,
+        cell.fg_color.b,
+        cell.bg_color.r,
+        cell.bg_color.g,
+        cell.bg_color.b
+    );
+    for (set, name) in [
+        (cell.bold, "bold"),
+        (cell.italic, "italic"),
+        (cell.underline, "underline"),
+        (cell.blink, "blink"),
+        (cell.reverse, "reverse"),
+        (cell.strikethrough, "strikethrough"),
+    ] {
+        if set {
+            tag.push(' ');
+            tag.push_str(name);
+        }
+    }
+    tag.push(']');
+    tag
+}
+
+// SGR sequence selecting a cell's colors and attributes from a clean state
+fn cell_sgr(cell: &TermCell) -> String {
+    let mut sgr = String::from("\x1B[0");
+    if cell.bold {
+        sgr.push_str(";1");
+    }
+    if cell.italic {
+        sgr.push_str(";3");
+    }
+    if cell.underline {
+        sgr.push_str(";4");
+    }
+    if cell.blink {
+        sgr.push_str(";5");
+    }
+    if cell.reverse {
+        sgr.push_str(";7");
+    }
+    if cell.strikethrough {
+        sgr.push_str(";9");
+    }
+    sgr.push_str(&format!(
+        ";38;2;{};{};{};48;2;{};{};{}m",
+        cell.fg_color.r,
+        cell.fg_color.g,
+        cell.fg_color.b,
+        cell.bg_color.r,
+        cell.bg_color.g,
+        cell.bg_color.b
+    ));
+    sgr
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A dark-themed terminal with the given output already processed
+    fn terminal_with(width: usize, height: usize, content: &str) -> VirtualTerminal {
+        let mut terminal = VirtualTerminal::new(width, height, true);
+        terminal.process_content(content);
+        terminal
+    }
+
+    #[test]
+    fn screen_text_drops_colors_and_trailing_spaces() {
+        let terminal = terminal_with(
+            20,
+            3,
+            "\x1B[1;31mred\x1B[0m \x1B[42mgreen\x1B[0m   \r\n\x1B[38;5;208mnext\x1B[0m",
+        );
+
+        assert_eq!(terminal.screen_text(), "red green\nnext\n");
+    }
+
+    #[test]
+    fn backspace_reverse_wraps_onto_a_wrapped_row() {
+        let mut terminal = terminal_with(5, 3, "abcdefg\x08\x08\x08");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (4, 0));
+
+        terminal.process_content("X");
+        assert_eq!(terminal.screen_text(), "abcdX\nfg\n");
+    }
+
+    #[test]
+    fn backspace_stops_at_the_start_of_an_unwrapped_row() {
+        let terminal = terminal_with(5, 3, "ab\r\n\x08\x08");
+
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (0, 1));
+    }
+
+    #[test]
+    fn tiny_terminals_survive_cursor_movement_and_scrolling() {
+        let stress = "abc\r\n\n\x1B[5B\x1B[5A\x1B[5C\x1B[5D\x1B[9;9H\x1B[2L\x1B[2M\x1B[2@\x1B[2P\x1BM\x1B[S\x1B[T\x1B[3I\x1B[3Z\x08\tz";
+        for (width, height) in [(1, 1), (2, 2)] {
+            let terminal = terminal_with(width, height, stress);
+            assert!(terminal.cursor_x < width && terminal.cursor_y < height);
+        }
+    }
+
+    #[test]
+    fn zero_sizes_are_raised_to_one_cell() {
+        let terminal = terminal_with(0, 0, "x\r\ny");
+
+        assert_eq!(terminal.cells.len(), 1);
+        assert_eq!(terminal.cells[0].len(), 1);
+    }
+
+    #[test]
+    fn cursor_tabulation_moves_between_tab_stops() {
+        let mut terminal = terminal_with(40, 2, "ab\x1B[I");
+        assert_eq!(terminal.cursor_x, 8);
+
+        terminal.process_content("\x1B[2I");
+        assert_eq!(terminal.cursor_x, 24);
+
+        terminal.process_content("\x1B[Z");
+        assert_eq!(terminal.cursor_x, 16);
+
+        terminal.process_content("x\x1B[2Z");
+        assert_eq!(terminal.cursor_x, 8);
+    }
+
+    #[test]
+    fn cursor_tabulation_stays_on_the_row() {
+        let mut terminal = terminal_with(20, 2, "\x1B[9I");
+        assert_eq!(terminal.cursor_x, 19);
+
+        terminal.process_content("\x1B[9Z");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (0, 0));
+    }
+
+    #[test]
+    fn cursor_tabulation_follows_the_tab_width() {
+        let mut terminal = VirtualTerminal::new(20, 2, true);
+        terminal.set_tab_width(4);
+        terminal.process_content("\x1B[3I");
+        assert_eq!(terminal.cursor_x, 12);
+
+        terminal.process_content("\x1B[Z");
+        assert_eq!(terminal.cursor_x, 8);
+    }
+
+    #[test]
+    fn bell_is_counted_without_drawing_anything() {
+        let mut terminal = terminal_with(10, 2, "a\x07b\x07");
+
+        assert_eq!(terminal.screen_text(), "ab\n");
+        assert_eq!(terminal.cursor_x, 2);
+        assert_eq!(terminal.take_bells(), 2);
+        assert_eq!(terminal.take_bells(), 0);
+    }
+
+    #[test]
+    fn erase_display_2_clears_the_screen_in_place() {
+        let terminal = terminal_with(10, 3, "one\r\ntwo\x1B[2J");
+
+        assert_eq!(terminal.screen_text(), "\n\n");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (3, 1));
+    }
+
+    #[test]
+    fn erase_display_3_leaves_the_screen_alone() {
+        // Only the scrollback is cleared, and none is kept
+        let terminal = terminal_with(10, 3, "one\r\ntwo\x1B[3J");
+
+        assert_eq!(terminal.screen_text(), "one\ntwo\n");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (3, 1));
+    }
+
+    #[test]
+    fn reverse_video_swaps_colors_until_turned_off() {
+        let terminal = terminal_with(10, 1, "\x1B[7mab\x1B[27mc\x1B[7md\x1B[0me");
+        let reversed: Vec<bool> = terminal.cells()[0][..5].iter().map(|c| c.reverse).collect();
+        assert_eq!(reversed, vec![true, true, false, true, false]);
+
+        // The top left pixel of a cell is always background
+        let (fg, bg) = terminal.default_colors();
+        let img = terminal.render_to_image(8, 0);
+        assert_eq!(*img.get_pixel(0, 0), fg.to_rgb());
+        assert_eq!(*img.get_pixel(2 * 8, 0), bg.to_rgb());
+    }
+
+    #[test]
+    fn attribute_resets_clear_one_attribute_each() {
+        let terminal = terminal_with(
+            10,
+            1,
+            "\x1B[1;3;4;31;42mA\x1B[22mB\x1B[23mC\x1B[24mD\x1B[39mE\x1B[49mF",
+        );
+        let cells = &terminal.cells()[0];
+        let (default_fg, default_bg) = terminal.default_colors();
+        let red = cells[0].fg_color;
+        let green = cells[0].bg_color;
+        assert_ne!(red, default_fg);
+        assert_ne!(green, default_bg);
+
+        let attributes = |cell: &TermCell| (cell.bold, cell.italic, cell.underline);
+        assert_eq!(attributes(&cells[0]), (true, true, true));
+        assert_eq!(attributes(&cells[1]), (false, true, true));
+        assert_eq!(attributes(&cells[2]), (false, false, true));
+        assert_eq!(attributes(&cells[3]), (false, false, false));
+
+        // Colors are left alone until their own resets
+        assert_eq!((cells[3].fg_color, cells[3].bg_color), (red, green));
+        assert_eq!((cells[4].fg_color, cells[4].bg_color), (default_fg, green));
+        assert_eq!(
+            (cells[5].fg_color, cells[5].bg_color),
+            (default_fg, default_bg)
+        );
+    }
+
+    #[test]
+    fn strikethrough_draws_through_the_middle_row() {
+        let terminal = terminal_with(4, 1, "\x1B[9mx\x1B[0mx\x1B[9;29mx");
+        let struck: Vec<bool> = terminal.cells()[0][..3]
+            .iter()
+            .map(|c| c.strikethrough)
+            .collect();
+        assert_eq!(struck, vec![true, false, false]);
+
+        let font_size = 8;
+        let middle = font_size as u32;
+        let img = terminal.render_to_image(font_size, 0);
+        let (fg, _) = terminal.default_colors();
+        let solid =
+            |x0: u32| (x0..x0 + font_size as u32).all(|x| *img.get_pixel(x, middle) == fg.to_rgb());
+        assert!(solid(0));
+        assert!(!solid(font_size as u32));
+    }
+
+    #[test]
+    fn cursor_save_and_restore_in_both_forms() {
+        for (save, restore) in [("\x1B7", "\x1B8"), ("\x1B[s", "\x1B[u")] {
+            let terminal = terminal_with(10, 3, &format!("ab{}\x1B[1mcd\r\n{}x", save, restore));
+
+            assert_eq!(terminal.screen_text(), "abxd\n\n");
+            assert!(!terminal.cells()[0][2].bold);
+            assert_eq!((terminal.cursor_x, terminal.cursor_y), (3, 0));
+        }
+    }
+
+    #[test]
+    fn restored_cursor_is_clamped_to_a_smaller_screen() {
+        let mut terminal = terminal_with(10, 5, "\x1B[5;9H\x1B7");
+        terminal.resize(4, 2);
+        terminal.process_content("\x1B8");
+
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (3, 1));
+    }
+
+    #[test]
+    fn alternate_screen_is_blank_and_gives_the_screen_back() {
+        let mut terminal = terminal_with(10, 3, "shell$ ");
+        terminal.process_content("\x1B[?1049h");
+        assert_eq!(terminal.screen_text(), "\n\n");
+
+        terminal.process_content("\x1B[2;1Heditor");
+        assert_eq!(terminal.screen_text(), "\neditor\n");
+
+        // 1049 puts the cursor back where it was too
+        terminal.process_content("\x1B[?1049l");
+        assert_eq!(terminal.screen_text(), "shell$\n\n");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (7, 0));
+    }
+
+    #[test]
+    fn mode_47_swaps_screens_without_moving_the_cursor() {
+        let terminal = terminal_with(10, 3, "one\x1B[?47htwo\x1B[?47l");
+
+        assert_eq!(terminal.screen_text(), "one\n\n");
+        assert_eq!(terminal.cursor_x, 6);
+    }
+
+    #[test]
+    fn osc_sets_the_title_without_touching_the_screen() {
+        let mut terminal = terminal_with(20, 2, "a\x1B]0;first title\x07b");
+        assert_eq!(terminal.title(), Some("first title"));
+
+        terminal.process_content("\x1B]2;second\x1B\\c");
+        assert_eq!(terminal.title(), Some("second"));
+        assert_eq!(terminal.screen_text(), "abc\n");
+    }
+
+    #[test]
+    fn osc_split_across_frames_is_completed() {
+        let mut terminal = terminal_with(20, 2, "\x1B]2;split ti");
+        terminal.process_content("tle\x07x");
+
+        assert_eq!(terminal.title(), Some("split title"));
+        assert_eq!(terminal.screen_text(), "x\n");
+    }
+
+    #[test]
+    fn cursor_cell_is_drawn_over_a_blank_cell() {
+        let font_size = 8;
+        let terminal = terminal_with(4, 2, "$ ");
+        let plain = terminal.render_to_image(font_size, 0);
+        let (fg, bg) = terminal.default_colors();
+        // The cursor sits on the third cell of the first row
+        let cursor_cell = (2 * font_size as u32, 0);
+
+        for style in [CursorStyle::Block, CursorStyle::Underline, CursorStyle::Bar] {
+            let mut img = plain.clone();
+            terminal.draw_cursor(&mut img, font_size, style);
+
+            assert_ne!(img, plain, "{:?}", style);
+            // Only the cursor's cell changes
+            let only_cursor_cell = img
+                .enumerate_pixels()
+                .filter(|(x, y, pixel)| *pixel != plain.get_pixel(*x, *y))
+                .all(|(x, y, _)| {
+                    (cursor_cell.0..cursor_cell.0 + font_size as u32).contains(&x)
+                        && y < 2 * font_size as u32
+                });
+            assert!(only_cursor_cell, "{:?}", style);
+        }
+
+        let mut block = plain.clone();
+        terminal.draw_cursor(&mut block, font_size, CursorStyle::Block);
+        assert_eq!(*plain.get_pixel(cursor_cell.0, 0), bg.to_rgb());
+        assert_eq!(*block.get_pixel(cursor_cell.0, 0), fg.to_rgb());
+    }
+
+    #[test]
+    fn hidden_cursor_is_not_drawn() {
+        let mut terminal = terminal_with(4, 2, "$ \x1B[?25l");
+        let plain = terminal.render_to_image(8, 0);
+        let mut img = plain.clone();
+        terminal.draw_cursor(&mut img, 8, CursorStyle::Block);
+        assert_eq!(img, plain);
+
+        terminal.process_content("\x1B[?25h");
+        terminal.draw_cursor(&mut img, 8, CursorStyle::Block);
+        assert_ne!(img, plain);
+    }
+
+    #[test]
+    fn scrolling_stays_inside_the_scroll_region() {
+        // A status line on the last row stays put while rows 1 to 3 scroll
+        let terminal = terminal_with(10, 4, "\x1B[4;1Hstatus\x1B[1;3r\x1B[1;1Ha\r\nb\r\nc\r\nd");
+
+        assert_eq!(terminal.screen_text(), "b\nc\nd\nstatus");
+    }
+
+    #[test]
+    fn index_and_reverse_index_scroll_at_the_region_edges() {
+        let mut terminal = terminal_with(10, 4, "\x1B[2;3r\x1B[1;1Htop\x1B[2;1Hx\x1B[3;1Hy");
+
+        // Index at the bottom margin scrolls the region up
+        terminal.process_content("\x1BD");
+        assert_eq!(terminal.screen_text(), "top\ny\n\n");
+
+        // Reverse index at the top margin scrolls it down
+        terminal.process_content("\x1B[2;1H\x1BM");
+        assert_eq!(terminal.screen_text(), "top\n\ny\n");
+
+        // Next line also returns to the first column
+        terminal.process_content("ab\x1BE");
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (0, 2));
+    }
+
+    #[test]
+    fn insert_and_delete_lines_within_the_scroll_region() {
+        let mut terminal = terminal_with(10, 4, "a\r\nb\r\nc\r\nd\x1B[2;1H\x1B[L");
+        assert_eq!(terminal.screen_text(), "a\n\nb\nc");
+
+        terminal.process_content("\x1B[2M");
+        assert_eq!(terminal.screen_text(), "a\nc\n\n");
+
+        // Rows below the region aren't moved
+        let mut terminal = terminal_with(10, 4, "a\r\nb\r\nc\r\nd\x1B[1;3r\x1B[1;1H\x1B[L");
+        assert_eq!(terminal.screen_text(), "\na\nb\nd");
+        terminal.process_content("\x1B[M");
+        assert_eq!(terminal.screen_text(), "a\nb\n\nd");
+    }
+
+    #[test]
+    fn insert_and_delete_characters_shift_the_rest_of_the_row() {
+        let mut terminal = terminal_with(8, 1, "abcdef\x1B[1;3H\x1B[2@");
+        assert_eq!(terminal.screen_text(), "ab  cdef");
+
+        terminal.process_content("\x1B[3P");
+        assert_eq!(terminal.screen_text(), "abdef");
+
+        // Vacated cells take the current background
+        terminal.process_content("\x1B[41m\x1B[1;1H\x1B[P");
+        let (_, default_bg) = terminal.default_colors();
+        assert_eq!(terminal.screen_text(), "bdef");
+        assert_ne!(terminal.cells()[0][7].bg_color, default_bg);
+    }
+
+    // Cell widths along a row: 2 for a wide character, 0 for the column it
+    // spills into
+    fn row_widths(terminal: &VirtualTerminal, y: usize) -> Vec<u8> {
+        terminal.cells[y].iter().map(|cell| cell.width).collect()
+    }
+
+    #[test]
+    fn wide_characters_take_two_columns() {
+        let terminal = terminal_with(6, 2, "a中b");
+
+        assert_eq!(row_widths(&terminal, 0), vec![1, 2, 0, 1, 1, 1]);
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (4, 0));
+        assert_eq!(terminal.screen_text(), "a中b\n");
+    }
+
+    #[test]
+    fn wide_character_in_the_last_column_wraps() {
+        let terminal = terminal_with(4, 2, "abc中");
+
+        assert_eq!(row_widths(&terminal, 0), vec![1, 1, 1, 1]);
+        assert_eq!(row_widths(&terminal, 1), vec![2, 0, 1, 1]);
+        assert!(terminal.wrapped[0]);
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (2, 1));
+        assert_eq!(terminal.screen_text(), "abc\n中");
+    }
+
+    #[test]
+    fn wide_character_filling_the_line_moves_to_the_next() {
+        let terminal = terminal_with(4, 2, "ab中");
+
+        assert_eq!(row_widths(&terminal, 0), vec![1, 1, 2, 0]);
+        assert_eq!((terminal.cursor_x, terminal.cursor_y), (0, 1));
+        assert_eq!(terminal.screen_text(), "ab中\n");
+    }
+
+    #[test]
+    fn overwriting_either_half_blanks_the_other() {
+        let right = terminal_with(4, 1, "中\x1B[1;2Hx");
+        assert_eq!(right.screen_text(), " x");
+        assert_eq!(row_widths(&right, 0), vec![1, 1, 1, 1]);
+
+        let left = terminal_with(4, 1, "中\x1B[1;1Hx");
+        assert_eq!(left.screen_text(), "x");
+        assert_eq!(row_widths(&left, 0), vec![1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn inserting_shifts_wide_characters_whole() {
+        let terminal = terminal_with(6, 1, "a中b\x1B[1;1H\x1B[@");
+        assert_eq!(terminal.screen_text(), " a中b");
+        assert_eq!(row_widths(&terminal, 0), vec![1, 1, 2, 0, 1, 1]);
+
+        // Pushed half off the edge, the wide character is blanked
+        let terminal = terminal_with(4, 2, "ab中\x1B[1;1H\x1B[@");
+        assert_eq!(terminal.screen_text(), " ab\n");
+        assert_eq!(row_widths(&terminal, 0), vec![1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn deleting_shifts_wide_characters_whole() {
+        let terminal = terminal_with(6, 1, "a中b\x1B[1;1H\x1B[P");
+        assert_eq!(terminal.screen_text(), "中b");
+        assert_eq!(row_widths(&terminal, 0), vec![2, 0, 1, 1, 1, 1]);
+
+        // Deleting the left half leaves the right half orphaned and blank
+        let terminal = terminal_with(6, 1, "a中b\x1B[1;2H\x1B[P");
+        assert_eq!(terminal.screen_text(), "a b");
+        assert_eq!(row_widths(&terminal, 0), vec![1, 1, 1, 1, 1, 1]);
+    }
 }
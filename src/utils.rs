@@ -1,5 +1,8 @@
 use std::env;
 use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn get_absolute_path(filename: &str) -> PathBuf {
     if Path::new(filename).is_absolute() {
@@ -10,3 +13,110 @@ pub fn get_absolute_path(filename: &str) -> PathBuf {
             .join(filename)
     }
 }
+
+// Full path of an executable found on PATH, like `which`
+pub fn find_in_path(program: &str) -> Option<PathBuf> {
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file())
+}
+
+// Size of the terminal attached to stdout as (columns, rows), if there is one
+#[cfg(unix)]
+pub fn terminal_size() -> Option<(u16, u16)> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let result = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+
+    if result == 0 && size.ws_col > 0 && size.ws_row > 0 {
+        Some((size.ws_col, size.ws_row))
+    } else {
+        None
+    }
+}
+
+#[cfg(not(unix))]
+pub fn terminal_size() -> Option<(u16, u16)> {
+    None
+}
+
+#[cfg(unix)]
+static RESIZED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn note_resize(_: libc::c_int) {
+    RESIZED.store(true, Ordering::SeqCst);
+}
+
+// Start noting SIGWINCH, which the kernel sends when the terminal attached
+// to rcrd changes size
+#[cfg(unix)]
+pub fn watch_resizes() {
+    let handler: extern "C" fn(libc::c_int) = note_resize;
+    unsafe {
+        libc::signal(libc::SIGWINCH, handler as libc::sighandler_t);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn watch_resizes() {}
+
+// Whether a SIGWINCH arrived since the last call
+#[cfg(unix)]
+pub fn take_resized() -> bool {
+    RESIZED.swap(false, Ordering::SeqCst)
+}
+
+#[cfg(not(unix))]
+pub fn take_resized() -> bool {
+    false
+}
+
+// Seconds since the Unix epoch, or None if the clock is set before it
+pub fn unix_time() -> Option<u64> {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|elapsed| elapsed.as_secs())
+}
+
+// Format seconds since the Unix epoch as a UTC date and time, converting the
+// day count to a civil date as in Howard Hinnant's date algorithms
+pub fn format_utc(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let time = secs % 86_400;
+
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+// Format milliseconds since the Unix epoch as format_utc does, to the
+// millisecond
+pub fn format_utc_millis(ms: u64) -> String {
+    let secs = format_utc(ms / 1000);
+    format!("{}.{:03} UTC", secs.trim_end_matches(" UTC"), ms % 1000)
+}
+
+// Path for a scratch file in the system temp directory, unique to this test
+// process
+#[cfg(test)]
+pub fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("rcrd-test-{}-{}", std::process::id(), name))
+}